@@ -23,9 +23,7 @@ for src_file in $src_files; do
   
   base_name=$(basename "$src_file" .c)
 
-  $CC -E -P "$src_file" -o "$tmp/${base_name}_preprocessed.c" || exit
-
-  ./target/debug/jff "$tmp/${base_name}_preprocessed.c" > "$tmp/${base_name}.s" || exit
+  ./target/debug/jff -S -o "$tmp/${base_name}.s" "$src_file" || exit
 
   $CC -o "$tmp/$base_name" "$tmp/${base_name}.s" "$tmp/common.o" || exit
     # cat "$tmp/${base_name}.s"
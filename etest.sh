@@ -0,0 +1,165 @@
+#!/bin/bash
+# 診断メッセージのテスト。コンパイルに失敗し、出力にexpectedが含まれることを確かめる
+assert_error() {
+	expected="$1"
+	input="$2"
+	flags="$3"
+	actual=$(./target/debug/jff -fdiagnostics-color=never $flags -S -o /dev/null -e "$input" 2>&1)
+	if [ "$?" = 0 ]; then
+		echo "$input => expected an error, but compiled"
+		exit 1
+	fi
+	if [[ "$actual" != *"$expected"* ]]; then
+		echo "$input => expected:"
+		echo "$expected"
+		echo "but got:"
+		echo "$actual"
+		exit 1
+	fi
+	echo "$input => ok"
+}
+
+# コンパイルには成功し、出力にexpectedが含まれることを確かめる。flagsで警告を切り替える
+assert_warning() {
+	flags="$1"
+	expected="$2"
+	input="$3"
+	actual=$(./target/debug/jff -fdiagnostics-color=never $flags -S -o /dev/null -e "$input" 2>&1)
+	if [ "$?" != 0 ]; then
+		echo "$input => expected to compile, but failed:"
+		echo "$actual"
+		exit 1
+	fi
+	# expectedが空なら、何も出ないことを確かめる
+	if [[ -z "$expected" && -n "$actual" ]] || [[ "$actual" != *"$expected"* ]]; then
+		echo "$input => expected:"
+		echo "$expected"
+		echo "but got:"
+		echo "$actual"
+		exit 1
+	fi
+	echo "$flags $input => ok"
+}
+
+# --dump-tokensなどの出力にexpectedが含まれることを確かめる
+assert_output() {
+	flags="$1"
+	expected="$2"
+	input="$3"
+	actual=$(./target/debug/jff $flags -e "$input")
+	if [ "$?" != 0 ] || [[ "$actual" != *"$expected"* ]]; then
+		echo "$flags $input => expected:"
+		echo "$expected"
+		echo "but got:"
+		echo "$actual"
+		exit 1
+	fi
+	echo "$flags $input => ok"
+}
+
+cargo build -q
+
+assert_error "<command line>:1:28: error: undefined variable" 'int main() { int x; return y; }'
+assert_error "<command line>:1:30: error: cannot add pointers to pointers
+    1 | int main() { int *p; int x = p + p; return x; }
+      |                              ^~~~~" 'int main() { int *p; int x = p + p; return x; }'
+# #includeやコメントがあっても、元のファイルの行を指す
+assert_error "<command line>:5:29: error: cannot add pointers to pointers
+    5 | int main() { int *p; return p + p; } // comment" $'#include "test.h"\n/*\n comment\n*/\nint main() { int *p; return p + p; } // comment' "-Itest"
+assert_error '{"file":"<command line>","line":2,"column":29,' $'#include "test.h"\nint main() { int *p; return p + p; }' "-Itest --diagnostics-format=json"
+# マクロを展開した行は、元の行と桁が合わないので表示しない
+assert_error "<command line>:2:31: error: cannot add pointers to pointers
+<command line>:3:" $'#define ADD(a, b) ((a) + (b))\nint main() { int *p; return ADD(p, p); }\nint f() { int *p; return p + p; }'
+assert_error "<command line>:2:5: error: redefinition of 'g'" $'int g = 1;\nint g = 2;'
+assert_error "<command line>:1:5: note: previous definition is here" $'int g = 1;\nint g = 2;'
+assert_error "<command line>:1:19: error: non-static declaration of 'x' follows static declaration" 'static int x; int x;'
+assert_error "<command line>:1:12: note: previous declaration is here" 'static int x; int x;'
+assert_error "error: static declaration of 'x' follows non-static declaration" 'int x; static int x;'
+assert_error "<command line>:1:24: error: redefinition of 'f'" 'int f(){return 1;} int f(){return 2;}'
+assert_error "<command line>:1:5: note: previous definition is here" 'int f(){return 1;} int f(){return 2;}'
+assert_error "expected ','" 'int main() { extern int a b; return 0; }'
+assert_error "expected ','" 'int main() { static int a b; return 0; }'
+assert_warning "-Wall" "" 'int g; int main() { extern int g; return 0; }'
+# 1回で複数のエラーを報告する
+assert_error "<command line>:2:18: error: undefined variable" $'int f() { return a; }\nint g() { return b; }'
+assert_error "<command line>:1:18: error: undefined variable" $'int f() { return a; }\nint g() { return b; }'
+assert_error "<command line>:1:23: error: expected '}'" 'int main() { return 1; '
+
+# 意味の検査
+assert_error "<command line>:1:25: error: redefinition of 'x'" 'int main() { int x; int x; return 0; }'
+assert_error "<command line>:1:18: note: previous definition is here" 'int main() { int x; int x; return 0; }'
+assert_error "error: redefinition of parameter 'a'" 'int f(int a, int a) { return a; }'
+assert_error "error: redefinition of 'a'" 'int f(int a) { int a; return a; }'
+assert_error "<command line>:1:14: error: lvalue required as left operand of assignment" 'int main() { 1 = 2; return 0; }'
+assert_error "error: lvalue required as unary '&' operand" 'int main() { int *p = &1; return 0; }'
+assert_error "error: lvalue required as increment operand" 'int main() { int x; ++(x + 1); return 0; }'
+assert_error "error: assignment to expression with array type" 'int main() { int a[2]; int b[2]; a = b; return 0; }'
+assert_error "error: cannot take address of bit-field 'b'" 'struct S { int b:3; } s; int main() { int *p = &s.b; return 0; }'
+assert_error "error: invalid operands to binary * (have 'int *' and 'int *')" 'int main() { int *p; int *q; return p * q; }'
+assert_error "error: invalid operands to binary - (have 'int *' and 'char *')" 'int main() { int *p; char *q; return p - q; }'
+assert_error "error: invalid operands to binary == (have 'struct S' and 'struct S')" 'struct S { int x; } s; int main() { return s == s; }'
+assert_error "error: wrong type argument to unary minus" 'int main() { int *p; return -p; }'
+assert_error "error: incompatible types when assigning to type 'int' from type 'struct S'" 'struct S { int x; } s; int main() { int x; x = s; return x; }'
+assert_error "<command line>:1:33: error: storage size of 'x' isn't known" 'struct S; int main() { struct S x; return 0; }'
+assert_error "<command line>:1:20: error: storage size of 'x' isn't known" 'struct S; struct S x;'
+assert_error "error: used struct type value where scalar is required" 'struct S { int x; } s; int main() { if (s) return 1; return 0; }'
+
+# 警告
+assert_warning "" "<command line>:1:21: warning: implicit declaration of function 'f' [-Wimplicit-function-declaration]" 'int main() { return f(); }'
+assert_warning "" "warning: overflow in conversion from 'int' to 'char' changes value from '300' to '44' [-Woverflow]" 'int main() { char c = 300; return c; }'
+assert_warning "" "<command line>:1:10: warning: overflow in conversion from 'int' to 'char' changes value from '200' to '-56' [-Woverflow]" 'char c = 200;'
+assert_warning "" "warning: overflow in conversion from 'long int' to 'int' changes value from '3000000000' to '-1294967296' [-Woverflow]" 'int main() { int i = 3000000000L; return i; }'
+assert_warning "" "" 'int main() { unsigned char c = -1; unsigned u = -1; int i = 4294967295u; return c + u + i; }'
+assert_warning "-Wall" "<command line>:1:18: warning: unused variable 'x' [-Wunused-variable]" 'int main() { int x; return 0; }'
+assert_warning "-Wall" "warning: control reaches end of non-void function [-Wreturn-type]" 'int f(int x) { if (x) return 1; }'
+assert_warning "-Wextra" "warning: unused parameter 'x' [-Wunused-parameter]" 'int f(int x) { return 0; }'
+assert_warning "-Wshadow" "note: shadowed declaration is here" 'int x; int main() { int x = 1; return x; }'
+assert_warning "-Wconversion" "may change value [-Wconversion]" 'int main() { long l = 1; int i = l; return i; }'
+assert_warning "-Wall -Wno-unused-variable" "" 'int main() { int x; return 0; }'
+assert_error "error: unused variable 'x' [-Werror=unused-variable]" 'int main() { int x; return 0; }' "-Wall -Werror"
+assert_error "error: unused variable 'x' [-Werror=unused-variable]" 'int main() { int x; return 0; }' "-Werror=unused-variable"
+
+# ビルドシステムが付けるオプションは警告して無視する
+assert_warning "-fPIC -MMD -MF x.d" "jff: warning: argument unused during compilation: '-MF x.d'" 'int main() { return 0; }'
+# -eのソースは次の引数で受け取り、-eで始まる知らないオプションはエラーにする
+assert_error "jff: error: unknown argument: '-eint'" 'int main() { return 0; }' "-eint"
+
+# --diagnostics-format=json
+assert_error '{"file":"<command line>","line":1,"column":28,"length":1,"severity":"error","code":null,"message":"undefined variable","notes":[]}' 'int main() { int x; return y; }' "--diagnostics-format=json"
+assert_error '"severity":"error","code":"unused-variable","message":"unused variable '"'x'"'"' 'int main() { int x; return 0; }' "--diagnostics-format=json -Werror=unused-variable"
+assert_error '"notes":[{"file":"<command line>","line":1,"column":5,"length":1,"severity":"note"' $'int g = 1;\nint g = 2;' "--diagnostics-format=json"
+
+# ダンプ
+assert_output "--dump-tokens" "num '42L' 'long int' <1:21>" 'int main() { return 42L; }'
+assert_output "--dump-ast" "Function main 'int' <1:12-1:32>
+  Block <1:12-1:32>
+    Return 'int' <1:14-1:30>
+      Add 'int' <1:21-1:29>
+        Num 1 'int' <1:21>
+        Mul 'int' <1:25-1:29>" 'int main() { return 1 + 2 * 3; }'
+assert_output "--dump-ast" "GlobalVar s 'struct S' <1:21>
+GlobalVar p 'struct <anonymous> *' <1:43>" 'struct S { int x; } s; struct { int y; } *p;'
+assert_output "--dump-ast=json" '"length":1,"line":1,"column":21,"end_line":1,"end_column":21},"value":1}' 'int main() { return 1 + 2 * 3; }'
+assert_output "--dump-ir" "bb0:
+  %0 = imm 1
+  %1 = imm 2
+  %2 = add %0, %1
+  %3 = ext i32 %2
+  ret %3" 'int main() { return 1 + 2; }'
+assert_output "--dump-ir" "  br %3, bb1, bb2" 'int f(int x) { if (x) return 1; return 2; }'
+# returnの後ろの、たどり着けないブロックは出さない
+assert_output "--dump-ir" "  ret %0
+
+function g" 'int f() { return 1; } int g() { return 2; }'
+
+# レジスタ割り当て。アドレスを取らない変数はレジスタに置き、呼び出しをまたぐ値はcallee-savedに置いて保存する
+assert_output "-S -o -" "      sxtw x19, w9
+      bl _g" 'int g(); int f(int a) { g(); return a; }'
+assert_output "-S -o -" "      str x19, [x29, 16]" 'int g(); int f(int a) { g(); return a; }'
+assert_output "-S -o -" "      str w10, [x9]" 'int g(int *p); int f() { int x = 1; return g(&x); }'
+# 合流したブロックの入口で生きている値も、呼び出しをまたぐならcallee-savedに置く
+assert_output "-S -o -" "      bl _f
+      mov x9, x0
+      add x9, x19, x9" 'int f(); int g(int c, int d, int e) { return (c ? (d ? 1 : 2) : (e ? 3 : 4)) + f(); }'
+
+echo OK
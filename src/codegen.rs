@@ -1,476 +1,562 @@
 use crate::{
-    type_utils::{copy_type, copy_var_type},
+    ir::*,
+    lower::lower,
+    regalloc::{allocate, promote_slots, Allocation, Loc},
     types::*,
 };
-fn push16() {
-    println!("      str x0, [sp, -16]!  // push"); // 16はハードコードだが、スタックのサイズを計算して動的にするべき?
-}
-fn pop16() {
-    println!("      ldr x1, [sp], 16 // pop");
+use std::fmt::Write;
+
+// 出力はCodegen.outに1行ずつためていく
+macro_rules! emit {
+    ($cg:expr, $($arg:tt)*) => {
+        writeln!($cg.out, $($arg)*).unwrap()
+    };
 }
 
-static mut IFIDX: usize = 0;
-static mut FORIDX: usize = 0;
-static mut CURRENTFN: String = String::new();
+// 1つの翻訳単位のコード生成の状態。関数のコードはlower.rsで作ったIRから生成する
+struct Codegen<'a, 'b> {
+    ctx: &'a Ctx<'b>,
+    out: String,
+    cur_fn: String,
+    slot_offsets: Vec<usize>, // 今の関数のローカル変数の、x29からのオフセット
+    alloc: Allocation,        // 今の関数のレジスタ割り当て
+}
 
-fn load(ty: &Type) {
-    match ty.kind {
-        TypeKind::Array { .. } | TypeKind::Struct { .. } | TypeKind::Union { .. } => {
-            return;
-        }
-        _ => {}
-    }
-    match ty.size {
-        1 => {
-            println!("      ldrsb x0, [x0]");
-        }
-        2 => {
-            println!("      ldrsh w0, [x0]")
-        }
-        4 => {
-            println!("      ldr w0, [x0]");
+impl Codegen<'_, '_> {
+    // 64bitの値をregに入れる。movの即値には収まらないことが多いので、16bitずつmovz, movkで組み立てる。
+    // 0xffffの塊の方が多ければ、movnで反転した値から始めた方が命令が少なく済む
+    fn mov_imm(&mut self, reg: &str, val: u64) {
+        let chunk = |i: u32| (val >> (i * 16)) & 0xffff;
+        let zeros = (0..4).filter(|i| chunk(*i) == 0).count();
+        let ones = (0..4).filter(|i| chunk(*i) == 0xffff).count();
+        let fill = if ones > zeros { 0xffff } else { 0 };
+        let mut first = true;
+        for i in 0..4 {
+            if chunk(i) == fill {
+                continue;
+            }
+            if !first {
+                emit!(self, "      movk {}, {}, lsl {}", reg, chunk(i), i * 16);
+            } else if fill == 0xffff {
+                emit!(
+                    self,
+                    "      movn {}, {}, lsl {}",
+                    reg,
+                    !chunk(i) & 0xffff,
+                    i * 16
+                );
+            } else {
+                emit!(self, "      movz {}, {}, lsl {}", reg, chunk(i), i * 16);
+            }
+            first = false;
         }
-        _ => {
-            println!("      ldr x0, [x0]");
+        // 0か-1
+        if first {
+            emit!(
+                self,
+                "      {} {}, 0",
+                if fill == 0 { "movz" } else { "movn" },
+                reg
+            );
         }
     }
-}
 
-fn store(ty: &Type) {
-    if let TypeKind::Struct { .. } | TypeKind::Union { .. } = &ty.kind {
-        for i in 0..ty.size {
-            println!("      ldrb w2, [x0, {}]", i);
-            println!("      strb w2, [x1, {}]", i);
+    // add, subの即値は12bitまでなので、それより大きい値はx16に入れてから計算する
+    fn imm_op(&mut self, op: &str, dst: &str, src: &str, imm: usize) {
+        if imm < 4096 {
+            emit!(self, "      {} {}, {}, {}", op, dst, src, imm);
+        } else {
+            self.mov_imm("x16", imm as u64);
+            emit!(self, "      {} {}, {}, x16", op, dst, src);
         }
-        return;
     }
-    match ty.size {
-        1 => {
-            println!("      strb w0, [x1]");
+
+    // スピルした仮想レジスタの、x29からのオフセット
+    fn spill_offset(&self, slot: usize) -> usize {
+        16 + self.alloc.callee_saved.len() * 8 + slot * 8
+    }
+
+    // x29からoffsetの位置を読み書きする。ldr, strの即値に収まらなければx16を使う
+    fn frame_op(&mut self, op: &str, phys: &str, offset: usize) {
+        if offset <= 32760 {
+            emit!(self, "      {} {}, [x29, {}]", op, phys, offset);
+        } else {
+            self.mov_imm("x16", offset as u64);
+            emit!(self, "      {} {}, [x29, x16]", op, phys);
         }
-        2 => {
-            println!("      strh w0, [x1]")
+    }
+
+    // 仮想レジスタの値を読むレジスタの名前。スピルしていればscratchに読み込む
+    fn src(&mut self, reg: Reg, scratch: &str) -> String {
+        match self.alloc.locs[reg.0] {
+            Loc::Reg(n) => format!("x{}", n),
+            Loc::Stack(slot) => {
+                self.frame_op("ldr", scratch, self.spill_offset(slot));
+                scratch.to_string()
+            }
         }
-        4 => {
-            println!("      str w0, [x1]");
+    }
+
+    // 仮想レジスタに書き込むレジスタの名前。スピルしていればscratchに書いて、finishでスタックに移す
+    fn dst(&self, reg: Reg, scratch: &str) -> String {
+        match self.alloc.locs[reg.0] {
+            Loc::Reg(n) => format!("x{}", n),
+            Loc::Stack(_) => scratch.to_string(),
         }
-        _ => {
-            println!("      str x0, [x1]");
+    }
+
+    fn finish(&mut self, reg: Reg, scratch: &str) {
+        if let Loc::Stack(slot) = self.alloc.locs[reg.0] {
+            self.frame_op("str", scratch, self.spill_offset(slot));
         }
     }
-}
 
-// 多分ガバガバ
-fn cast(from: Type, to: Type) {
-    let instruction = match (from.clone().kind, to.clone().kind) {
-        (TypeKind::Char, TypeKind::Short) => "      sxtb w0, w0",
-        (TypeKind::Char, TypeKind::Int) => "      sxtb w0, w0",
-        (TypeKind::Char, TypeKind::Long) => "      sxtb x0, w0",
-        (TypeKind::Char, TypeKind::Char) => "",
+    // physの値を仮想レジスタに移す
+    fn assign(&mut self, reg: Reg, phys: &str) {
+        let dst = self.dst(reg, phys);
+        if dst != phys {
+            emit!(self, "      mov {}, {}", dst, phys);
+        }
+        self.finish(reg, phys);
+    }
 
-        (TypeKind::Short, TypeKind::Char) => "      sxtb w0, w0",
-        (TypeKind::Short, TypeKind::Int) => "      sxth w0, w0",
-        (TypeKind::Short, TypeKind::Long) => "      sxth x0, w0",
-        (TypeKind::Short, TypeKind::Short) => "",
+    fn label(&self, bb: BlockId) -> String {
+        format!("L.{}.{}", self.cur_fn, bb)
+    }
 
-        (TypeKind::Int, TypeKind::Char) => "      sxtb w0, w0",
-        (TypeKind::Int, TypeKind::Short) => "      sxth w0, w0",
-        (TypeKind::Int, TypeKind::Long) => "      sxtw x0, w0",
-        (TypeKind::Int, TypeKind::Int) => "",
+    // addrから読み込み、型に合わせて64bitに符号拡張(unsignedならゼロ拡張)してdstに置く
+    fn load(&mut self, dst: &str, addr: &str, ty: IntTy) {
+        let w = w(dst);
+        match (ty.size, ty.is_unsigned) {
+            (1, true) => emit!(self, "      ldrb {}, [{}]", w, addr),
+            (1, false) => emit!(self, "      ldrsb {}, [{}]", dst, addr),
+            (2, true) => emit!(self, "      ldrh {}, [{}]", w, addr),
+            (2, false) => emit!(self, "      ldrsh {}, [{}]", dst, addr),
+            (4, true) => emit!(self, "      ldr {}, [{}]", w, addr),
+            (4, false) => emit!(self, "      ldrsw {}, [{}]", dst, addr),
+            _ => emit!(self, "      ldr {}, [{}]", dst, addr),
+        }
+    }
 
-        (TypeKind::Long, TypeKind::Char) => "      sxtb w0, w0",
-        (TypeKind::Long, TypeKind::Short) => "      sxth w0, w0",
-        (TypeKind::Long, TypeKind::Int) => "      sxtw x0, w0",
-        (TypeKind::Long, TypeKind::Long) => "",
+    // srcの下位sizeバイトをaddrに書き込む
+    fn store(&mut self, src: &str, addr: &str, size: usize) {
+        match size {
+            1 => emit!(self, "      strb {}, [{}]", w(src), addr),
+            2 => emit!(self, "      strh {}, [{}]", w(src), addr),
+            4 => emit!(self, "      str {}, [{}]", w(src), addr),
+            _ => emit!(self, "      str {}, [{}]", src, addr),
+        }
+    }
 
-        _ => {
-            eprintln!("from type: {:#?}", from);
-            eprintln!("to type: {:#?}", to);
-            panic!("not supported cast type")
+    // srcの値を、型のサイズで切り詰めてから64bitに拡張し直してdstに置く
+    fn extend(&mut self, dst: &str, src: &str, ty: IntTy) {
+        match (ty.size, ty.is_unsigned) {
+            (1, true) => emit!(self, "      and {}, {}, 0xff", dst, src),
+            (1, false) => emit!(self, "      sxtb {}, {}", dst, w(src)),
+            (2, true) => emit!(self, "      and {}, {}, 0xffff", dst, src),
+            (2, false) => emit!(self, "      sxth {}, {}", dst, w(src)),
+            (4, true) => emit!(self, "      mov {}, {}", w(dst), w(src)),
+            (4, false) => emit!(self, "      sxtw {}, {}", dst, w(src)),
+            _ if dst != src => emit!(self, "      mov {}, {}", dst, src),
+            _ => {}
         }
-    };
-    println!("{}", instruction);
-}
+    }
 
-fn gen_addr(node: Node) {
-    match node.kind {
-        NodeKind::Var { var } => {
-            let var = var.borrow();
-            if var.is_local {
-                println!("      add x0, x29, {}", var.offset);
-            } else {
-                println!("      adrp x0, {}@PAGE", var.name); // what is PAGE?
-                println!("      add x0, x0, {}@PAGEOFF;", var.name);
+    // 命令はどれも、被演算子をすべて読んでから結果を書く。割り当てで、使い終わった値と結果が同じレジスタになることがあるため
+    fn gen_bin(&mut self, op: BinOp, dst: &str, lhs: &str, rhs: &str) {
+        let insn = match op {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::SDiv => "sdiv",
+            BinOp::UDiv => "udiv",
+            BinOp::And => "and",
+            BinOp::Or => "orr",
+            BinOp::Xor => "eor",
+            BinOp::Shl => "lsl",
+            BinOp::LShr => "lsr",
+            BinOp::AShr => "asr",
+            // 余りは商を求めてから lhs - 商 * rhs
+            BinOp::SRem | BinOp::URem => {
+                let div = if op == BinOp::SRem { "sdiv" } else { "udiv" };
+                emit!(self, "      {} x2, {}, {}", div, lhs, rhs);
+                emit!(self, "      msub {}, x2, {}, {}", dst, rhs, lhs);
+                return;
             }
-        }
-        NodeKind::Deref { lhs, .. } => {
-            gen_expr(*lhs);
-        }
-        NodeKind::Member { lhs, member } => {
-            gen_addr(*lhs);
-            println!("      add x0, x0, {}", member.offset);
-        }
-        _ => panic!("not expected node: {:#?}", node),
+        };
+        emit!(self, "      {} {}, {}, {}", insn, dst, lhs, rhs);
     }
-}
 
-fn gen_expr(node: Node) {
-    match node.kind {
-        NodeKind::Num { val } => {
-            println!("      mov x0, {}", val);
-        }
-        NodeKind::Var { ref var } => {
-            let ty = copy_var_type(var);
-            gen_addr(node);
-            load(&ty);
-        }
-        NodeKind::Cast { ref lhs } => {
-            let to_ty = copy_type(&node);
-            let from_ty = copy_type(lhs);
-            gen_expr(*lhs.clone());
-            cast(from_ty, to_ty);
-        }
-        NodeKind::Member { .. } => {
-            let ty = copy_type(&node);
-            gen_addr(node); // x.valとかだったら、xのアドレスをx0に入れる。
-            load(&ty);
-        }
-        NodeKind::Add { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      add x0, x1, x0");
-        }
-        NodeKind::Sub { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      sub x0, x1, x0");
-        }
-        NodeKind::Mul { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      mul x0, x1, x0");
-        }
-        NodeKind::Div { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      sdiv x0, x1, x0");
-        }
-        NodeKind::Mod { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      sdiv x2, x1, x0");
-            println!("      msub x0, x2, x0, x1");
-        }
-        NodeKind::Neg { lhs } => {
-            gen_expr(*lhs);
-            println!("      neg x0, x0");
-        }
-        NodeKind::Eq { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      cmp x0, x1");
-            println!("      cset x0, eq");
-        }
-        NodeKind::Ne { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      cmp x0, x1");
-            println!("      cset x0, ne");
-        }
-        NodeKind::Lt { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      cmp x1, x0");
-            println!("      cset x0, lt");
-        }
-        NodeKind::Le { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      cmp x1, x0");
-            println!("      cset x0, le");
-        }
-        NodeKind::Gt { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      cmp x1, x0");
-            println!("      cset x0, gt");
-        }
-        NodeKind::Ge { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      cmp x1, x0");
-            println!("      cset x0, ge");
-        }
-        NodeKind::And { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      mov x2, 0");
-            println!("      cmp x1, 0");
-            println!("      cset x2, ne");
-            println!("      cmp x0, 0");
-            println!("      cset x0, ne");
-            println!("      and x0, x0, x2");
-        }
-        NodeKind::Or { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      mov x2, 0");
-            println!("      cmp x1, 0");
-            println!("      cset x2, ne");
-            println!("      cmp x0, 0");
-            println!("      cset x0, ne");
-            println!("      orr x0, x0, x2");
-        }
-        NodeKind::BitAnd { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      and x0, x1, x0");
-        }
-        NodeKind::BitXor { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      eor x0, x1, x0");
-        }
-        NodeKind::BitOr { lhs, rhs } => {
-            gen_expr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            println!("      orr x0, x1, x0");
-        }
-        NodeKind::NdAssign { lhs, rhs } => {
-            gen_addr(*lhs);
-            push16();
-            gen_expr(*rhs);
-            pop16();
-            store(node.ty.as_ref().unwrap()); // unwrap使わずにいけないかな
-        }
-        NodeKind::Addr { lhs } => {
-            gen_addr(*lhs);
-        }
-        NodeKind::Deref { lhs, .. } => {
-            gen_expr(*lhs);
-            load(node.ty.as_ref().unwrap()); // 正しいか？
-        }
-        NodeKind::FuncCall { name, args } => {
-            for arg in &args {
-                gen_expr(arg.clone());
-                push16();
+    fn gen_inst(&mut self, inst: &Inst, next: Option<BlockId>) {
+        match inst {
+            Inst::Imm { dst, val } => {
+                let d = self.dst(*dst, "x0");
+                self.mov_imm(&d, *val as u64);
+                self.finish(*dst, "x0");
             }
-            for i in (0..args.len()).rev() {
-                println!("      ldr x{}, [sp], 16 // pop for function arg", i);
+            Inst::Mov { dst, src } => {
+                let s = self.src(*src, "x0");
+                self.assign(*dst, &s);
             }
-            println!("      bl _{}", name);
-        }
-        NodeKind::GNUStmtExpr { body } => {
-            for stmt in body {
-                gen_stmt(stmt);
+            Inst::Param { dst, index } => {
+                self.assign(*dst, &format!("x{}", index));
+            }
+            Inst::SlotAddr { dst, slot } => {
+                let d = self.dst(*dst, "x0");
+                self.imm_op("add", &d, "x29", self.slot_offsets[*slot]);
+                self.finish(*dst, "x0");
+            }
+            Inst::GlobalAddr { dst, name } => {
+                let d = self.dst(*dst, "x0");
+                emit!(self, "      adrp {}, {}@PAGE", d, name);
+                emit!(self, "      add {}, {}, {}@PAGEOFF", d, d, name);
+                self.finish(*dst, "x0");
+            }
+            Inst::Load { dst, addr, ty } => {
+                let a = self.src(*addr, "x0");
+                let d = self.dst(*dst, "x0");
+                self.load(&d, &a, *ty);
+                self.finish(*dst, "x0");
+            }
+            Inst::Store { addr, src, size } => {
+                let a = self.src(*addr, "x1");
+                let s = self.src(*src, "x0");
+                self.store(&s, &a, *size);
+            }
+            Inst::MemCopy { dst, src, size } => {
+                let d = self.src(*dst, "x1");
+                let s = self.src(*src, "x0");
+                for i in 0..*size {
+                    if i < 4096 {
+                        emit!(self, "      ldrb w2, [{}, {}]", s, i);
+                        emit!(self, "      strb w2, [{}, {}]", d, i);
+                    } else {
+                        self.mov_imm("x16", i as u64);
+                        emit!(self, "      ldrb w2, [{}, x16]", s);
+                        emit!(self, "      strb w2, [{}, x16]", d);
+                    }
+                }
+            }
+            // アドレスを進めながら書くので、x1に写してから使う
+            Inst::MemZero { addr, size } => {
+                let a = self.src(*addr, "x1");
+                if a != "x1" {
+                    emit!(self, "      mov x1, {}", a);
+                }
+                for _ in 0..size / 8 {
+                    emit!(self, "      str xzr, [x1], 8");
+                }
+                for _ in 0..size % 8 {
+                    emit!(self, "      strb wzr, [x1], 1");
+                }
+            }
+            Inst::Bin { op, dst, lhs, rhs } => {
+                let l = self.src(*lhs, "x1");
+                let r = self.src(*rhs, "x0");
+                let d = self.dst(*dst, "x0");
+                self.gen_bin(*op, &d, &l, &r);
+                self.finish(*dst, "x0");
+            }
+            Inst::Cmp { op, dst, lhs, rhs } => {
+                let cond = match op {
+                    CmpOp::Eq => "eq",
+                    CmpOp::Ne => "ne",
+                    CmpOp::Lt => "lt",
+                    CmpOp::Le => "le",
+                    CmpOp::Gt => "gt",
+                    CmpOp::Ge => "ge",
+                    CmpOp::Ult => "lo",
+                    CmpOp::Ule => "ls",
+                    CmpOp::Ugt => "hi",
+                    CmpOp::Uge => "hs",
+                };
+                let l = self.src(*lhs, "x1");
+                let r = self.src(*rhs, "x0");
+                let d = self.dst(*dst, "x0");
+                emit!(self, "      cmp {}, {}", l, r);
+                emit!(self, "      cset {}, {}", d, cond);
+                self.finish(*dst, "x0");
+            }
+            Inst::Un { op, dst, src } => {
+                let s = self.src(*src, "x0");
+                let d = self.dst(*dst, "x0");
+                match op {
+                    UnOp::Neg => emit!(self, "      neg {}, {}", d, s),
+                    UnOp::BitNot => emit!(self, "      mvn {}, {}", d, s),
+                }
+                self.finish(*dst, "x0");
+            }
+            Inst::Ext { dst, src, ty } => {
+                let s = self.src(*src, "x0");
+                let d = self.dst(*dst, "x0");
+                self.extend(&d, &s, *ty);
+                self.finish(*dst, "x0");
+            }
+            Inst::BitExtract {
+                dst,
+                src,
+                offset,
+                width,
+                is_unsigned,
+            } => {
+                let s = self.src(*src, "x0");
+                let d = self.dst(*dst, "x0");
+                let op = if *is_unsigned { "ubfx" } else { "sbfx" };
+                emit!(self, "      {} {}, {}, {}, {}", op, d, s, offset, width);
+                self.finish(*dst, "x0");
+            }
+            // bfiは書き込み先の残りのビットを使うので、baseをx2に写してから挿入する
+            Inst::BitInsert {
+                dst,
+                base,
+                src,
+                offset,
+                width,
+            } => {
+                let b = self.src(*base, "x2");
+                let s = self.src(*src, "x0");
+                if b != "x2" {
+                    emit!(self, "      mov x2, {}", b);
+                }
+                emit!(self, "      bfi x2, {}, {}, {}", s, offset, width);
+                self.assign(*dst, "x2");
+            }
+            // 割り当てるレジスタはx9以降なので、引数をx0-x7に移しても壊れない
+            Inst::Call { dst, name, args } => {
+                for (i, arg) in args.iter().enumerate() {
+                    let phys = format!("x{}", i);
+                    let a = self.src(*arg, &phys);
+                    if a != phys {
+                        emit!(self, "      mov {}, {}", phys, a);
+                    }
+                }
+                emit!(self, "      bl _{}", name);
+                self.assign(*dst, "x0");
+            }
+            Inst::Jmp(target) => {
+                if next != Some(*target) {
+                    emit!(self, "      b {}", self.label(*target));
+                }
+            }
+            // 次のブロックへの分岐は、フォールスルーにして省く
+            Inst::Br { cond, then, els } => {
+                let c = self.src(*cond, "x0");
+                if next == Some(*els) {
+                    emit!(self, "      cbnz {}, {}", c, self.label(*then));
+                } else if next == Some(*then) {
+                    emit!(self, "      cbz {}, {}", c, self.label(*els));
+                } else {
+                    emit!(self, "      cbnz {}, {}", c, self.label(*then));
+                    emit!(self, "      b {}", self.label(*els));
+                }
+            }
+            Inst::Ret(val) => {
+                if let Some(val) = val {
+                    let s = self.src(*val, "x0");
+                    if s != "x0" {
+                        emit!(self, "      mov x0, {}", s);
+                    }
+                }
+                emit!(self, "      b L.{}.end", self.cur_fn);
             }
         }
-        _ => panic!("not expected node: {:#?}", node),
     }
-}
 
-fn gen_stmt(node: Node) {
-    // eprintln!("gen_stmt: {:#?}", node);
-    match node.kind {
-        NodeKind::ExprStmt { lhs } => {
-            gen_expr(*lhs);
-        }
-        NodeKind::Return { lhs } => {
-            gen_expr(*lhs);
-            println!("      b end.{}", unsafe { CURRENTFN.clone() });
-        }
-        NodeKind::Block { body } => {
-            for stmt in body {
-                gen_stmt(stmt);
+    // 関数以外のグローバル変数
+    fn handle_data(&mut self) {
+        let ctx = self.ctx;
+        for var in &ctx.gvars {
+            let var = var.borrow();
+
+            // externで宣言されただけの変数は、ほかのファイルで定義されている
+            if !var.is_definition {
+                continue;
+            }
+
+            // 初期値がない場合の処理。.bssに置くので、オブジェクトファイルに0のバイト列を持たなくてよい
+            // 仮定義はcommonシンボルにして、ほかのファイルの同名の定義とリンク時にまとめてもらう
+            // staticな変数はファイル外と共有しないので、.zerofillで__bssに確保する
+            if var.init_gval.is_none() {
+                let align = var.align.max(1).trailing_zeros();
+                // int x[]; のように最後まで要素数が決まらなかった配列は、要素1個とみなす
+                let size = match &var.ty.kind {
+                    TypeKind::Array { ptr_to, len } if *len < 0 => ptr_to.size,
+                    _ => var.ty.size,
+                };
+                if var.is_tentative && !var.is_static {
+                    emit!(self, ".comm {},{},{}", var.asm_name, size, align);
+                } else {
+                    emit!(
+                        self,
+                        ".zerofill __DATA,__bss,{},{},{}",
+                        var.asm_name,
+                        size,
+                        align
+                    );
+                }
+                continue;
+            }
+
+            // 初期値がある場合の処理
+            match &var.init_gval.as_ref().unwrap() {
+                InitGval::Str(s) => {
+                    let body = &s[..s.len() - 1]; // 末尾の\0は.ascizがつける
+                    emit!(self, ".text");
+                    // __cstringはリンカが\0で区切って扱うので、途中に\0を含む文字列は別のセクションに置く
+                    if body.contains(&0) {
+                        emit!(self, ".section __TEXT,__const");
+                    } else {
+                        emit!(self, ".cstring"); // セクションの指定
+                    }
+                    emit!(self, ".align 3"); // ポインタは8byte。align 3 は　2^3 = 8byteでアラインメント
+                    emit!(self, "{}:", var.asm_name);
+                    emit!(self, "      .asciz \"{}\"", escape_asciz(body));
+                }
+                InitGval::Data { bytes, relocs } => {
+                    emit!(self, ".data");
+                    if !var.is_static {
+                        emit!(self, ".global {}", var.asm_name);
+                    }
+                    emit!(self, ".align {}", var.align.max(1).trailing_zeros());
+                    emit!(self, "{}:", var.asm_name);
+                    self.emit_data(bytes, relocs);
+                }
             }
         }
-        NodeKind::If { cond, then, els } => {
-            let idx = unsafe { IFIDX };
-            unsafe { IFIDX += 1 };
-            gen_expr(*cond);
-            println!("      cmp x0, 1");
-            if let Some(els) = els {
-                println!("      b.ne else.{}", idx);
-                gen_stmt(*then);
-                println!("      b endif.{}", idx);
-                println!("else.{}:", idx);
-                gen_stmt(*els);
+    }
+
+    // 初期値のバイト列を出力する。ほかの変数のアドレスが入る位置は.quad label+addendにする
+    fn emit_data(&mut self, bytes: &[u8], relocs: &[Relocation]) {
+        let mut pos = 0;
+        for reloc in relocs {
+            self.emit_bytes(bytes, pos, reloc.offset);
+            if reloc.addend == 0 {
+                emit!(self, "      .quad {}", reloc.label);
             } else {
-                println!("      b.ne endif.{}", idx);
-                gen_stmt(*then);
+                emit!(self, "      .quad {}{:+}", reloc.label, reloc.addend);
             }
-            println!("endif.{}:", idx);
-        }
-        NodeKind::For {
-            init,
-            cond,
-            inc,
-            body,
-        } => {
-            let idx = unsafe { FORIDX };
-            unsafe { FORIDX += 1 };
-            gen_stmt(*init);
-            println!("      b cond.{}", idx);
-            println!("startfor.{}:", idx);
-            gen_stmt(*body);
-            if let Some(inc) = inc {
-                gen_expr(*inc);
-            }
-            println!("cond.{}:", idx);
-            if let Some(cond) = cond {
-                gen_expr(*cond);
-                println!("      cmp x0, 1");
-                println!("      b.ne endfor.{}", idx);
-            }
-            println!("      b startfor.{}", idx);
-            println!("endfor.{}:", idx);
+            pos = reloc.offset + 8;
         }
-        NodeKind::While { cond, body } => {
-            let idx = unsafe { FORIDX };
-            unsafe { FORIDX += 1 };
-            println!("startwhile.{}:", idx);
-            gen_expr(*cond);
-            println!("      cmp x0, 1");
-            println!("      b.ne endwhile.{}", idx);
-            gen_stmt(*body);
-            println!("      b startwhile.{}", idx);
-            println!("endwhile.{}:", idx);
-        }
-        _ => panic!("not expected node: {:#?}", node),
+        self.emit_bytes(bytes, pos, bytes.len());
     }
-}
 
-fn align_to(n: usize, to: usize) -> usize {
-    if to == 0 {
-        return n; // なぜreturnを書く必要がある？ nではだめなのか
+    // アラインメントの合う範囲で、なるべく大きい単位で出力する
+    fn emit_bytes(&mut self, bytes: &[u8], start: usize, end: usize) {
+        let mut pos = start;
+        while pos < end {
+            let rest = &bytes[pos..end];
+            let zeros = rest.iter().take_while(|b| **b == 0).count();
+            if zeros >= 16 {
+                emit!(self, "      .zero {}", zeros);
+                pos += zeros;
+                continue;
+            }
+            let (directive, size) = match rest.len() {
+                n if n >= 8 && pos.is_multiple_of(8) => (".xword", 8),
+                n if n >= 4 && pos.is_multiple_of(4) => (".word", 4),
+                n if n >= 2 && pos.is_multiple_of(2) => (".short", 2),
+                _ => (".byte", 1),
+            };
+            let mut buf = [0; 8];
+            buf[..size].copy_from_slice(&rest[..size]);
+            emit!(self, "      {} {}", directive, u64::from_le_bytes(buf));
+            pos += size;
+        }
     }
-    (n + to - 1) & !(to - 1)
-}
 
-// 関数以外のグローバル変数
-fn handle_data(ctx: &Ctx) {
-    for var in &ctx.gvars {
-        let var = var.borrow();
+    // 関数
+    // スタックフレームは、x29から順にfp, lr、使ったcallee-savedレジスタ、スピルした仮想レジスタ、ローカル変数
+    fn handle_text(&mut self, funcs: Vec<IrFunction>) {
+        for mut func in funcs {
+            promote_slots(&mut func);
+            self.alloc = allocate(&func);
+            self.cur_fn = func.name.clone();
+            let mut stack_size = self.spill_offset(self.alloc.nspills);
+            self.slot_offsets.clear();
+            for slot in &func.slots {
+                stack_size = align_to(stack_size, slot.align);
+                self.slot_offsets.push(stack_size);
+                stack_size += slot.size;
+            }
+            stack_size = align_to(stack_size, 16);
 
-        // 初期値がない場合の処理
-        if var.init_gval.is_none() {
-            println!(".data");
-            println!(".global {}", var.name);
-            println!("{}:", var.name);
-            println!("      .zero {}", var.ty.size);
-            continue;
-        }
+            emit!(self, ".text");
+            emit!(self, ".align 2");
+            if !func.is_static {
+                emit!(self, ".global _{}", func.name); // 関数はアンダースコアをつけるのが慣例
+            }
+            emit!(self, "_{}:", func.name);
+            self.imm_op("sub", "sp", "sp", stack_size);
+            emit!(self, "      stp x29, x30, [sp]");
+            emit!(self, "      mov x29, sp");
+            self.save_callee_saved("stp", "str");
 
-        // 初期値がある場合の処理
-        match &var.init_gval.as_ref().unwrap() {
-            InitGval::Str(s) => {
-                let trimmed = s.trim_end_matches('\0'); // ヌル文字を除去
-                println!(".text");
-                println!(".cstring"); // セクションの指定
-                println!(".align 3"); // ポインタは8byte。align 3 は　2^3 = 8byteでアラインメント
-                println!("{}:", var.name);
-                if trimmed.is_empty() {
-                    println!("      .asciz \"\"");
-                } else {
-                    println!("      .asciz \"{}\"", trimmed);
+            for (i, block) in func.blocks.iter().enumerate() {
+                emit!(self, "{}:", self.label(i));
+                let next = (i + 1 < func.blocks.len()).then_some(i + 1);
+                for inst in &block.insts {
+                    self.gen_inst(inst, next);
                 }
             }
-            InitGval::Num(val) => {
-                println!(".data");
-                println!(".global {}", var.name);
-                println!("{}:", var.name);
-                println!("      .xword {}", val);
-            }
+
+            emit!(self, "L.{}.end:", func.name);
+            self.save_callee_saved("ldp", "ldr");
+            emit!(self, "      ldp x29, x30, [sp]");
+            self.imm_op("add", "sp", "sp", stack_size);
+            emit!(self, "      ret");
         }
     }
-}
 
-// 関数
-fn handle_text(ctx: &Ctx) {
-    for (name, func) in &ctx.functions {
-        // 宣言のみの場合はスキップ
-        if !func.is_def {
-            continue;
-        }
-        unsafe { CURRENTFN = name.clone() };
-        let mut stack_size = 16; // fp, lp用に事前確保
-        for scope in &func.exited_scope {
-            for var in &scope.variables {
-                let mut var = var.borrow_mut();
-                stack_size = align_to(stack_size, var.ty.align);
-                var.offset = stack_size;
-                stack_size += var.ty.size; // もしかしたら撮りすぎかも。alignをうまく使う？
-                                           // eprintln!("var:{:#?}", var);
+    // 使ったcallee-savedレジスタを、fp, lrのすぐ後ろに2つずつ保存する(戻す)
+    fn save_callee_saved(&mut self, pair: &str, single: &str) {
+        let regs = self.alloc.callee_saved.clone();
+        for (i, chunk) in regs.chunks(2).enumerate() {
+            let offset = 16 + i * 16;
+            match chunk {
+                [a, b] => emit!(self, "      {} x{}, x{}, [x29, {}]", pair, a, b, offset),
+                _ => emit!(self, "      {} x{}, [x29, {}]", single, chunk[0], offset),
             }
         }
-        stack_size = align_to(stack_size, 16);
+    }
+}
 
-        println!(".text");
-        println!(".align 2");
-        println!(".global _{}", name); // 関数はアンダースコアをつけるのが慣例
-        println!("_{}:", name);
-        println!("      sub sp, sp, {}", stack_size);
-        println!("      stp x29, x30, [sp]");
-        println!("      mov x29, sp");
+// x{n}の下位32bitの名前
+fn w(reg: &str) -> String {
+    format!("w{}", &reg[1..])
+}
 
-        // 引数の処理
-        // chibiccだと、関数の引数でもレジスタの選別をしていた。
-        for (i, arg) in func.args.iter().enumerate() {
-            // 他のアドレスを計算する際にx0を使うので、最初の引数のみ特別扱いして対比する
-            if i == 0 {
-                println!("      mov x9, x0");
-                gen_addr(arg.clone());
-                println!("      str x9, [x0]");
-                continue;
-            }
-            gen_addr(arg.clone());
-            println!("      str x{}, [x0]", i);
-        }
+fn align_to(n: usize, to: usize) -> usize {
+    if to == 0 {
+        return n; // なぜreturnを書く必要がある？ nではだめなのか
+    }
+    (n + to - 1) & !(to - 1)
+}
 
-        if let Some(body) = &func.body {
-            gen_stmt(body.clone());
+// アセンブラの文字列リテラルとして書けるようにエスケープする。表示できない文字は8進数にする
+fn escape_asciz(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for &b in bytes {
+        match b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\{:03o}", b)),
         }
-
-        println!("end.{}:", name);
-        println!("      ldp x29, x30, [sp]");
-        println!("      add sp, sp, {}", stack_size);
-        println!("      ret");
     }
+    s
 }
 
-pub fn codegen(ctx: Ctx) {
-    handle_data(&ctx);
-    handle_text(&ctx);
+// アセンブリのテキストを返す
+pub fn codegen(ctx: &Ctx) -> String {
+    let mut cg = Codegen {
+        ctx,
+        out: String::new(),
+        cur_fn: String::new(),
+        slot_offsets: Vec::new(),
+        alloc: Allocation::default(),
+    };
+    let funcs = lower(ctx);
+    cg.handle_data();
+    cg.handle_text(funcs);
+    cg.out
 }
@@ -0,0 +1,452 @@
+// エラーの報告と、エラーからの回復
+// エラーはCtx.diagsに積んでから、Abortで解析を打ち切る。文や宣言の単位でrecoverが受け止めて、
+// ;や}まで読み飛ばして続きを解析するので、1回の実行で複数のエラーを報告できる
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::types::*;
+
+// これ以上エラーが出たら、続けても意味のないエラーばかりになるので諦める
+const MAX_ERRORS: usize = 20;
+
+// エラーを報告して、今解析している文や宣言を諦めるときに投げる
+pub struct Abort;
+// 翻訳単位ごと諦めるときに投げる。recoverでは受け止めない
+pub struct Fatal;
+
+impl Span {
+    // selfとotherの両方を含む範囲
+    pub fn to(self, other: Span) -> Span {
+        let start = self.start.min(other.start);
+        let end = (self.start + self.len).max(other.start + other.len);
+        Span {
+            start,
+            len: end - start,
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn error(span: Option<Span>, message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            span,
+            message: message.to_string(),
+            notes: Vec::new(),
+            warning: None,
+        }
+    }
+
+    pub fn warning(span: Span, message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            span: Some(span),
+            message: message.to_string(),
+            notes: Vec::new(),
+            warning: None,
+        }
+    }
+
+    // 「前の定義はここ」のように、別の場所を指す補足を付ける
+    pub fn with_note(mut self, span: Span, message: &str) -> Self {
+        self.notes.push(Diagnostic {
+            severity: Severity::Note,
+            span: Some(span),
+            message: message.to_string(),
+            notes: Vec::new(),
+            warning: None,
+        });
+        self
+    }
+}
+
+// fを実行し、Abortで打ち切られたらNoneを返す。それ以外のpanicはそのまま上に伝える
+pub fn recover<T>(f: impl FnOnce() -> T) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(val) => Some(val),
+        Err(payload) if payload.is::<Abort>() => None,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+// panic!のメッセージを取り出す
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown error".to_string()
+    }
+}
+
+impl Ctx<'_> {
+    pub fn report(&self, diag: Diagnostic) {
+        let is_error = diag.severity == Severity::Error;
+        self.diags.borrow_mut().push(diag);
+        if is_error && self.error_count() >= MAX_ERRORS {
+            self.diags.borrow_mut().push(Diagnostic {
+                severity: Severity::Error,
+                span: None,
+                message: "too many errors emitted, stopping now".to_string(),
+                notes: Vec::new(),
+                warning: None,
+            });
+            panic::resume_unwind(Box::new(Fatal));
+        }
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diags
+            .borrow()
+            .iter()
+            .filter(|diag| diag.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn tok_span(&self, tok: &Token) -> Span {
+        Span {
+            start: tok.start,
+            len: tok.len,
+        }
+    }
+
+    // tokから、最後に読んだトークンまで
+    pub fn span_from(&self, tok: &Token) -> Span {
+        self.tok_span(tok).to(self.tok_span(self.get_tok(-1)))
+    }
+
+    pub fn error_tok(&self, tok: &Token, msg: &str) -> ! {
+        self.error_at(tok.start, tok.len, msg);
+    }
+
+    // トークンになる前の、ソース上の範囲を指してエラーを出す
+    pub fn error_at(&self, start: usize, len: usize, msg: &str) -> ! {
+        self.report(Diagnostic::error(Some(Span { start, len }), msg));
+        panic::resume_unwind(Box::new(Abort));
+    }
+
+    // 補足付きのエラーを報告して打ち切る
+    pub fn error_diag(&self, diag: Diagnostic) -> ! {
+        self.report(diag);
+        panic::resume_unwind(Box::new(Abort));
+    }
+
+    pub fn error_span(&self, span: Span, msg: &str) -> ! {
+        self.error_at(span.start, span.len, msg);
+    }
+
+    // ノードの範囲を指してエラーを出す
+    pub fn error_node(&self, node: &Node, msg: &str) -> ! {
+        self.error_span(node.span, msg);
+    }
+
+    // エラーのあった文や宣言の残りを読み飛ばす。startはその文や宣言を読み始めたときのconsumed_tokensの長さ
+    // 途中で開いた{}は閉じるまで飛ばし、その外の;か}まで進む。外側のブロックを閉じる}は残しておく
+    pub fn skip_to_sync(&mut self, start: usize) {
+        let mut depth = 0;
+        for tok in &self.consumed_tokens[start.min(self.consumed_tokens.len())..] {
+            match &tok.kind {
+                TokenKind::Punct { str } if str == "{" => depth += 1,
+                TokenKind::Punct { str } if str == "}" && depth > 0 => depth -= 1,
+                _ => {}
+            }
+        }
+        loop {
+            if self.at_eof() {
+                return;
+            }
+            if self.hequal("}") {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+                self.advance(1);
+                if depth == 0 {
+                    return;
+                }
+                continue;
+            }
+            if self.hequal("{") {
+                depth += 1;
+            }
+            let is_semicolon = self.hequal(";");
+            self.advance(1);
+            if is_semicolon && depth == 0 {
+                return;
+            }
+        }
+    }
+
+    // 報告されたものをソース上の順に並べて、表示用の文字列にする。場所のないものは最後
+    // -fdiagnostics-colorが有効ならANSIエスケープシーケンスで色を付ける
+    pub fn render_diagnostics(&self) -> String {
+        let color = self.opts.color;
+        let mut diags = self.diags.borrow().clone();
+        diags.sort_by_key(|diag| diag.span.map_or(usize::MAX, |span| span.start));
+        let mut out = String::new();
+        for diag in &diags {
+            match self.opts.diagnostics_format {
+                DiagnosticsFormat::Text => self.render(diag, color, &mut out),
+                DiagnosticsFormat::Json => {
+                    out.push_str(&self.render_json(diag));
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+
+    // --diagnostics-format=json。1つの診断を1行のJSONにする。補足はnotesに入れる
+    //   {"file":"a.c","line":3,"column":11,"length":5,"severity":"error","code":null,"message":"...","notes":[]}
+    // columnはテキストの形式と同じ表示上の桁、lengthは範囲のバイト数。codeは警告の名前
+    fn render_json(&self, diag: &Diagnostic) -> String {
+        let severity = match diag.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        let (file, line, column, length) = match diag.span {
+            Some(span) => {
+                let loc = self.source_location(span.start);
+                (
+                    loc.file,
+                    loc.line.to_string(),
+                    loc.col.to_string(),
+                    span.len.to_string(),
+                )
+            }
+            None => (
+                self.cur_file.clone(),
+                "null".to_string(),
+                "null".to_string(),
+                "null".to_string(),
+            ),
+        };
+        let code = diag
+            .warning
+            .map_or("null".to_string(), |warning| json_string(warning.name()));
+        let notes: Vec<String> = diag
+            .notes
+            .iter()
+            .map(|note| self.render_json(note))
+            .collect();
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"length\":{},\"severity\":\"{}\",\"code\":{},\"message\":{},\"notes\":[{}]}}",
+            json_string(&file),
+            line,
+            column,
+            length,
+            severity,
+            code,
+            json_string(&diag.message),
+            notes.join(",")
+        )
+    }
+
+    // gcc, clangと同じ形式にする。エディタがfile:line:colから該当箇所に飛べるように
+    //   file:line:col: error: message
+    //      3 |   int x = p + p;
+    //        |           ^~~~~
+    fn render(&self, diag: &Diagnostic, color: bool, out: &mut String) {
+        let paint = |code: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", code, text)
+            } else {
+                text.to_string()
+            }
+        };
+        let (severity, severity_color) = match diag.severity {
+            Severity::Error => ("error:", "1;31"),
+            Severity::Warning => ("warning:", "1;35"),
+            Severity::Note => ("note:", "1;36"),
+        };
+        // 警告には、切り替えるためのオプションを付けておく
+        let message = match (diag.warning, diag.severity) {
+            (Some(warning), Severity::Error) => {
+                format!("{} [-Werror={}]", diag.message, warning.name())
+            }
+            (Some(warning), _) => format!("{} [-W{}]", diag.message, warning.name()),
+            (None, _) => diag.message.clone(),
+        };
+
+        let Some(span) = diag.span else {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                paint("1", &format!("{}:", self.cur_file)),
+                paint(severity_color, severity),
+                message
+            ));
+            for note in &diag.notes {
+                self.render(note, color, out);
+            }
+            return;
+        };
+
+        let loc = self.source_location(span.start);
+        out.push_str(&format!(
+            "{} {} {}\n",
+            paint("1", &format!("{}:{}:{}:", loc.file, loc.line, loc.col)),
+            paint(severity_color, severity),
+            message
+        ));
+
+        // 行の中の範囲を表示幅で数える。タブは8桁ごとに揃えて空白に展開する
+        let start = loc.pos - loc.line_start;
+        let end = (span.start + span.len).clamp(loc.pos, loc.line_end) - loc.line_start;
+        if let Some(line) = self.snippet_line(&loc, end) {
+            let (text, prefix_width) = expand_tabs(&line, start);
+            let (_, end_width) = expand_tabs(&line, end);
+            let marker = format!(
+                "^{}",
+                "~".repeat((end_width - prefix_width).saturating_sub(1))
+            );
+            let gutter = loc.line.to_string().len().max(4);
+            out.push_str(&format!(" {:>gutter$} | {}\n", loc.line, text));
+            out.push_str(&format!(
+                " {:>gutter$} | {}{}\n",
+                "",
+                " ".repeat(prefix_width),
+                paint("1;32", &marker)
+            ));
+        }
+        for note in &diag.notes {
+            self.render(note, color, out);
+        }
+    }
+
+    // 表示する行。元のファイルの行が範囲の終わりまでプリプロセス後と同じなら、元の行(コメントも残っている)
+    // 違えばマクロの展開などで桁が合わないので表示しない。元のファイルが読めなければ(--stdinなど)プリプロセス後の行
+    fn snippet_line(&self, loc: &SourceLocation, end: usize) -> Option<String> {
+        let line = &self.input_copy[loc.line_start..loc.line_end];
+        let original = loc.path.as_ref().and_then(|path| {
+            let text = match self.original_input {
+                Some(input) if *path == self.line_markers[0].file => input.to_string(),
+                _ => std::fs::read_to_string(path).ok()?,
+            };
+            text.lines().nth(loc.line - 1).map(str::to_string)
+        });
+        match original {
+            Some(original) if original.get(..end) == line.get(..end) => Some(original),
+            Some(_) => None,
+            None => Some(line.to_string()),
+        }
+    }
+
+    // posがある行と、その中の位置。colは1始まりの表示上の桁
+    pub fn source_location(&self, pos: usize) -> SourceLocation {
+        let input = self.input_copy;
+        // 入力の終わりを指す場合は、最後の行の末尾を指す
+        let mut pos = pos.min(input.len());
+        if pos == input.len() && input.ends_with('\n') {
+            pos -= 1;
+        }
+        let line_start = input[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[pos..].find('\n').map_or(input.len(), |i| pos + i);
+        // 直前の行番号の印から数える。印がなければ入力の先頭から
+        let markers = &self.line_markers;
+        let (file, path, line) =
+            match markers[..markers.partition_point(|m| m.pos <= line_start)].last() {
+                Some(m) => {
+                    let file = if m.file == markers[0].file {
+                        self.cur_file.clone()
+                    } else {
+                        m.file.clone()
+                    };
+                    (
+                        file,
+                        Some(m.file.clone()),
+                        m.line + input[m.pos..line_start].matches('\n').count(),
+                    )
+                }
+                None => (
+                    self.cur_file.clone(),
+                    None,
+                    input[..line_start].matches('\n').count() + 1,
+                ),
+            };
+        let (_, width) = expand_tabs(&input[line_start..line_end], pos - line_start);
+        SourceLocation {
+            pos,
+            file,
+            path,
+            line,
+            col: width + 1,
+            line_start,
+            line_end,
+        }
+    }
+}
+
+pub struct SourceLocation {
+    pos: usize,
+    pub file: String,     // 表示するファイル名
+    path: Option<String>, // 行番号の印が指す元のファイル。行を表示するのに読む
+    pub line: usize,
+    pub col: usize,
+    line_start: usize,
+    line_end: usize,
+}
+
+// JSONの文字列リテラルにする
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// タブを空白に展開した行と、先頭からposバイト目までの表示幅を返す
+fn expand_tabs(line: &str, pos: usize) -> (String, usize) {
+    let mut text = String::new();
+    let mut width = 0;
+    let mut width_at_pos = None;
+    for (i, c) in line.char_indices() {
+        if i >= pos && width_at_pos.is_none() {
+            width_at_pos = Some(width);
+        }
+        if c == '\t' {
+            let n = 8 - width % 8;
+            text.push_str(&" ".repeat(n));
+            width += n;
+        } else {
+            text.push(c);
+            width += char_width(c);
+        }
+    }
+    (text, width_at_pos.unwrap_or(width))
+}
+
+// 端末上での文字の幅。日本語などの全角文字は2桁になる
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
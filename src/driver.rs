@@ -0,0 +1,520 @@
+// gccと同じように使えるコマンドラインのドライバ。CC=jff としてMakefileから呼べるようにする
+// プリプロセス、アセンブル、リンクは外部のコマンドに任せる。複数のファイルは並列にコンパイルする
+use std::fs::File;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::thread;
+
+use crate::types::{CompileOptions, DiagnosticsFormat, Dump, Warning};
+
+// コンパイルするスレッドの名前。このスレッドのpanicは内部エラーとしてcompileが報告する
+pub const COMPILE_THREAD: &str = "cc1";
+
+const USAGE: &str =
+    "usage: jff [-E | -S | -c] [-o <file>] [-I <dir>] [-D <name>[=<value>]] [-U <name>]
+           [-L <dir>] [-l <lib>] <file>...
+
+  -E          preprocess only
+  -S          compile only; write assembly (.s)
+  -c          compile and assemble; write an object file (.o)
+  -o <file>   write output to <file> (- for stdout)
+  -e <source> compile <source> given on the command line
+  -, --stdin  read C source from stdin
+  -W<warning>, -Wno-<warning>
+              enable or disable a warning: unused-variable, unused-parameter,
+              implicit-function-declaration, overflow, conversion, return-type,
+              shadow, or a group: all, extra, unused
+  -Werror[=<warning>]
+              turn all warnings (or one warning) into errors
+  -w          disable all warnings
+  -fdiagnostics-color[=always|never|auto]
+              colorize diagnostics (default: auto, when stderr is a terminal)
+  --diagnostics-format=text|json
+              print diagnostics as text or as one JSON object per line
+  --dump-tokens
+              print the tokens instead of compiling
+  --dump-ast[=json]
+              print the syntax tree with types and source ranges instead of compiling
+  --dump-ir   print the intermediate representation instead of compiling
+
+The preprocessor, assembler and linker can be overridden with JFF_CPP, JFF_AS and JFF_LD.";
+
+#[derive(Default)]
+struct Options {
+    output: Option<String>,
+    opt_e: bool,
+    opt_s: bool,
+    opt_c: bool,
+    cpp_args: Vec<String>, // -I, -D, -U はそのままプリプロセッサに渡す
+    ld_args: Vec<String>,  // -L, -l はそのままリンカに渡す
+    inputs: Vec<Input>,
+    compile: CompileOptions,
+}
+
+#[derive(Clone)]
+enum Input {
+    File(String),   // "-"は標準入力
+    Inline(String), // -e で渡されたソース
+}
+
+#[derive(PartialEq)]
+enum InputKind {
+    C,
+    Preprocessed, // .i
+    Asm,          // .s
+    Object,       // .o, .a など。リンカにそのまま渡す
+}
+
+fn input_kind(input: &Input) -> InputKind {
+    let Input::File(path) = input else {
+        return InputKind::C;
+    };
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("i") => InputKind::Preprocessed,
+        Some("s") => InputKind::Asm,
+        Some("o" | "a" | "so" | "dylib") => InputKind::Object,
+        _ => InputKind::C,
+    }
+}
+
+fn fatal(msg: &str) -> ! {
+    eprintln!("jff: error: {}", msg);
+    process::exit(1);
+}
+
+// -o out と -oout のどちらも受け付ける
+fn ignored(arg: &str) {
+    eprintln!(
+        "jff: warning: argument unused during compilation: '{}'",
+        arg
+    );
+}
+
+fn take_value(args: &[String], i: &mut usize, flag: &str) -> Option<String> {
+    let arg = &args[*i];
+    if arg == flag {
+        *i += 1;
+        match args.get(*i) {
+            Some(value) => Some(value.clone()),
+            None => fatal(&format!("missing argument to '{}'", flag)),
+        }
+    } else {
+        arg.strip_prefix(flag).map(|value| value.to_string())
+    }
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut opts = Options::default();
+    opts.compile.color = std::io::stderr().is_terminal();
+    opts.compile.warnings = Warning::defaults();
+    let mut no_warnings = false;
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if arg == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        if let Some(output) = take_value(args, &mut i, "-o") {
+            opts.output = Some(output);
+        } else if arg == "-e" {
+            // -eで始まるほかのオプションと紛れないように、ソースは常に次の引数で受け取る
+            let source = take_value(args, &mut i, "-e").unwrap();
+            opts.inputs.push(Input::Inline(source));
+        } else if arg == "--stdin" {
+            opts.inputs.push(Input::File("-".to_string()));
+        } else if let Some(flag) = ["-I", "-D", "-U"].iter().find(|f| arg.starts_with(**f)) {
+            let value = take_value(args, &mut i, flag).unwrap();
+            opts.cpp_args.push(format!("{}{}", flag, value));
+        } else if let Some(flag) = ["-L", "-l"].iter().find(|f| arg.starts_with(**f)) {
+            let value = take_value(args, &mut i, flag).unwrap();
+            opts.ld_args.push(format!("{}{}", flag, value));
+        } else if let Some(when) = arg.strip_prefix("-fdiagnostics-color") {
+            opts.compile.color = match when {
+                "" | "=always" => true,
+                "=never" => false,
+                "=auto" => std::io::stderr().is_terminal(),
+                _ => fatal(&format!("unknown argument: '{}'", arg)),
+            };
+        } else if arg == "-fno-diagnostics-color" {
+            opts.compile.color = false;
+        } else if let Some(format) = arg
+            .strip_prefix("--diagnostics-format=")
+            .or_else(|| arg.strip_prefix("-fdiagnostics-format="))
+        {
+            opts.compile.diagnostics_format = match format {
+                "text" => DiagnosticsFormat::Text,
+                "json" => DiagnosticsFormat::Json,
+                _ => fatal(&format!("unknown diagnostics format: '{}'", format)),
+            };
+        } else if arg == "--dump-tokens" {
+            opts.compile.dump = Some(Dump::Tokens);
+        } else if arg == "--dump-ast" {
+            opts.compile.dump = Some(Dump::Ast);
+        } else if arg == "--dump-ast=json" {
+            opts.compile.dump = Some(Dump::AstJson);
+        } else if arg == "--dump-ir" {
+            opts.compile.dump = Some(Dump::Ir);
+        } else if arg == "-E" {
+            opts.opt_e = true;
+        } else if arg == "-S" {
+            opts.opt_s = true;
+        } else if arg == "-c" {
+            opts.opt_c = true;
+        } else if arg.starts_with("-Wl,") {
+            opts.ld_args.push(arg.to_string());
+        } else if arg == "-w" {
+            no_warnings = true;
+        } else if let Some(name) = arg.strip_prefix("-W") {
+            parse_warning_option(&mut opts.compile, name);
+        } else if ["-O", "-g", "-std=", "-pedantic"]
+            .iter()
+            .any(|f| arg.starts_with(f))
+        {
+            // 最適化やデバッグ情報のオプションは今のところ無視する
+        } else if [
+            "-fPIC", "-fpic", "-fPIE", "-fpie", "-pipe", "-MD", "-MMD", "-MP",
+        ]
+        .contains(&arg)
+        {
+            // ビルドシステムが付けるオプション。出力に影響しないか対応していないので、警告して無視する
+            ignored(arg);
+        } else if let Some(flag) = ["-MF", "-MT", "-MQ"].iter().find(|f| arg.starts_with(**f)) {
+            let value = take_value(args, &mut i, flag).unwrap();
+            ignored(&format!("{} {}", flag, value));
+        } else if arg.starts_with('-') && arg != "-" {
+            fatal(&format!("unknown argument: '{}'", arg));
+        } else {
+            opts.inputs.push(Input::File(arg.to_string()));
+        }
+        i += 1;
+    }
+
+    if no_warnings {
+        opts.compile.warnings.clear();
+    }
+    if opts.inputs.is_empty() {
+        fatal("no input files");
+    }
+    if opts.output.is_some() && opts.inputs.len() > 1 && (opts.opt_e || opts.opt_s || opts.opt_c) {
+        fatal("cannot specify '-o' with '-c', '-S' or '-E' with multiple files");
+    }
+    opts
+}
+
+// -Wの後ろの部分。知らない警告の名前は、gcc向けのMakefileでも使えるように無視する
+fn parse_warning_option(opts: &mut CompileOptions, name: &str) {
+    match name {
+        "error" => opts.werror = true,
+        "no-error" => opts.werror = false,
+        _ => {
+            if let Some(name) = name.strip_prefix("error=") {
+                for warning in Warning::from_name(name) {
+                    opts.warnings.insert(warning);
+                    opts.werror_for.insert(warning);
+                }
+            } else if let Some(name) = name.strip_prefix("no-error=") {
+                for warning in Warning::from_name(name) {
+                    opts.werror_for.remove(&warning);
+                }
+            } else if let Some(name) = name.strip_prefix("no-") {
+                for warning in Warning::from_name(name) {
+                    opts.warnings.remove(&warning);
+                }
+            } else {
+                opts.warnings.extend(Warning::from_name(name));
+            }
+        }
+    }
+}
+
+// 外部コマンドは環境変数で差し替えられる。JFF_AS="llvm-mc -filetype=obj" のように引数も書ける
+fn tool(var: &str, default: &str) -> Vec<String> {
+    let cmd = std::env::var(var).unwrap_or_else(|_| default.to_string());
+    let argv: Vec<String> = cmd.split_whitespace().map(|s| s.to_string()).collect();
+    if argv.is_empty() {
+        fatal(&format!("{} is empty", var));
+    }
+    argv
+}
+
+struct Driver {
+    opts: Options,
+    tmpfiles: Vec<PathBuf>,
+}
+
+impl Driver {
+    fn fail(&self) -> ! {
+        self.cleanup();
+        process::exit(1);
+    }
+
+    fn cleanup(&self) {
+        for path in &self.tmpfiles {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn create_tmpfile(&mut self, ext: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "jff-{}-{}.{}",
+            process::id(),
+            self.tmpfiles.len(),
+            ext
+        ));
+        self.tmpfiles.push(path.clone());
+        path.to_string_lossy().into_owned()
+    }
+
+    // -oがなければ、gccと同じく入力のファイル名の拡張子を変えてカレントディレクトリに出力する
+    // 標準入力と-eのソースは、gccの標準入力と同じく-.sや-.oになる
+    fn output_path(&self, input: &Input, ext: &str) -> String {
+        if let Some(output) = &self.opts.output {
+            return output.clone();
+        }
+        let stem = match input {
+            Input::File(path) => Path::new(path)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.clone()),
+            Input::Inline(_) => "-".to_string(),
+        };
+        format!("{}.{}", stem, ext)
+    }
+
+    fn run_command(&self, argv: &[String]) {
+        match Command::new(&argv[0]).args(&argv[1..]).status() {
+            Ok(status) if status.success() => {}
+            Ok(_) => self.fail(),
+            Err(err) => {
+                eprintln!("jff: error: failed to run '{}': {}", argv[0], err);
+                self.fail();
+            }
+        }
+    }
+
+    // "-"なら標準出力に書く
+    fn write_output(&self, path: &str, contents: &str) {
+        if path == "-" {
+            print!("{}", contents);
+            return;
+        }
+        if let Err(err) = std::fs::write(path, contents) {
+            eprintln!("jff: error: cannot write '{}': {}", path, err);
+            self.fail();
+        }
+    }
+
+    // プリプロセスした結果をoutputに書き出す(-E)か、outputがなければ文字列で返す
+    // -eのソースはプリプロセッサの標準入力から渡す
+    fn preprocess(&self, input: &Input, output: Option<&str>) -> String {
+        let argv = tool("JFF_CPP", "cc -E");
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]).args(&self.opts.cpp_args);
+        match input {
+            Input::File(path) => cmd.arg(path).stdin(Stdio::inherit()),
+            Input::Inline(_) => cmd.arg("-").stdin(Stdio::piped()),
+        };
+        match output {
+            Some("-") => cmd.stdout(Stdio::inherit()),
+            Some(output) => match File::create(output) {
+                Ok(file) => cmd.stdout(file),
+                Err(err) => {
+                    eprintln!("jff: error: cannot open '{}': {}", output, err);
+                    self.fail();
+                }
+            },
+            None => cmd.stdout(Stdio::piped()),
+        };
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                eprintln!("jff: error: failed to run '{}': {}", argv[0], err);
+                self.fail();
+            }
+        };
+        // 出力を読みながら書き込まないと、パイプが詰まることがあるので別スレッドで渡す
+        if let Input::Inline(source) = input {
+            let mut stdin = child.stdin.take().unwrap();
+            let source = source.clone();
+            thread::spawn(move || stdin.write_all(source.as_bytes()));
+        }
+        match child.wait_with_output() {
+            Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).into_owned(),
+            _ => {
+                if let Some(output) = output.filter(|o| *o != "-") {
+                    let _ = std::fs::remove_file(output);
+                }
+                self.fail();
+            }
+        }
+    }
+
+    fn assemble(&self, input: &str, output: &str) {
+        let mut argv = tool("JFF_AS", "as");
+        argv.extend(["-o".to_string(), output.to_string(), input.to_string()]);
+        self.run_command(&argv);
+    }
+
+    fn link(&self, inputs: &[String], output: &str) {
+        let mut argv = tool("JFF_LD", "cc");
+        argv.extend(["-o".to_string(), output.to_string()]);
+        argv.extend(inputs.iter().cloned());
+        argv.extend(self.opts.ld_args.iter().cloned());
+        self.run_command(&argv);
+    }
+
+    // 翻訳単位ごとに別のスレッドでコンパイルする。Ctxとコード生成の状態は翻訳単位ごとに持つので、
+    // 互いに干渉しない。パーサは再帰が深くなるので、スタックは大きめに取る
+    // エラーはすべてのファイルについて入力の順に表示してから終了する
+    // unitsは(名前, プリプロセスしたソース, -eの元のソース)
+    fn compile_all(&self, units: &[(String, String, Option<String>)]) -> Vec<String> {
+        thread::scope(|s| {
+            let handles: Vec<_> = units
+                .iter()
+                .map(|(name, source, original)| {
+                    thread::Builder::new()
+                        .name(COMPILE_THREAD.to_string())
+                        .stack_size(256 * 1024 * 1024)
+                        .spawn_scoped(s, move || {
+                            crate::compile(source, name, original.as_deref(), &self.opts.compile)
+                        })
+                        .unwrap_or_else(|err| {
+                            eprintln!("jff: error: cannot create a thread: {}", err);
+                            self.fail();
+                        })
+                })
+                .collect();
+            let results: Vec<_> = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| self.fail()))
+                .collect();
+            let mut asms = Vec::new();
+            let mut failed = false;
+            for (asm, diags) in results {
+                eprint!("{}", diags);
+                match asm {
+                    Some(asm) => asms.push(asm),
+                    None => failed = true,
+                }
+            }
+            if failed {
+                self.fail();
+            }
+            asms
+        })
+    }
+
+    fn run(&mut self) {
+        let inputs = self.opts.inputs.clone();
+        for input in &inputs {
+            if let Input::File(path) = input {
+                if path != "-" {
+                    if let Err(err) = std::fs::metadata(path) {
+                        eprintln!("jff: error: cannot open '{}': {}", path, err);
+                        self.fail();
+                    }
+                }
+            }
+        }
+
+        if self.opts.opt_e {
+            for input in &inputs {
+                if input_kind(input) == InputKind::C {
+                    let output = self.opts.output.clone().unwrap_or("-".to_string());
+                    self.preprocess(input, Some(&output));
+                }
+            }
+            return;
+        }
+
+        // まずすべてのCのソースをプリプロセスして、まとめてコンパイルする
+        // コンパイルエラーはその場で終了するので、一時ファイルはコンパイルが終わってから作る
+        let mut units = Vec::new();
+        for input in &inputs {
+            let name = match input {
+                Input::File(path) if path == "-" => "<stdin>".to_string(),
+                Input::File(path) => path.clone(),
+                Input::Inline(_) => "<command line>".to_string(),
+            };
+            match input_kind(input) {
+                InputKind::C => {
+                    let original = match input {
+                        Input::Inline(source) => Some(source.clone()),
+                        Input::File(_) => None,
+                    };
+                    units.push((name, self.preprocess(input, None), original))
+                }
+                InputKind::Preprocessed => match std::fs::read_to_string(&name) {
+                    Ok(source) => units.push((name, source, None)),
+                    Err(err) => {
+                        eprintln!("jff: error: cannot open '{}': {}", name, err);
+                        self.fail();
+                    }
+                },
+                _ => {}
+            }
+        }
+        let mut asms = self.compile_all(&units).into_iter();
+
+        // ダンプはアセンブルせず、すべての翻訳単位の分をまとめて出力する
+        if self.opts.compile.dump.is_some() {
+            let output = self.opts.output.clone().unwrap_or("-".to_string());
+            self.write_output(&output, &asms.collect::<String>());
+            return;
+        }
+
+        let mut ld_inputs = Vec::new();
+        for input in &inputs {
+            let kind = input_kind(input);
+            let asm = match (&kind, input) {
+                (InputKind::Object, Input::File(path)) => {
+                    ld_inputs.push(path.clone());
+                    continue;
+                }
+                (InputKind::Asm, Input::File(path)) => {
+                    if self.opts.opt_s {
+                        continue;
+                    }
+                    path.clone()
+                }
+                _ => {
+                    let asm = asms.next().unwrap();
+                    if self.opts.opt_s {
+                        let output = self.output_path(input, "s");
+                        self.write_output(&output, &asm);
+                        continue;
+                    }
+                    let tmp = self.create_tmpfile("s");
+                    self.write_output(&tmp, &asm);
+                    tmp
+                }
+            };
+
+            if self.opts.opt_c {
+                let output = self.output_path(input, "o");
+                self.assemble(&asm, &output);
+                continue;
+            }
+            let obj = self.create_tmpfile("o");
+            self.assemble(&asm, &obj);
+            ld_inputs.push(obj);
+        }
+
+        if !self.opts.opt_s && !self.opts.opt_c {
+            let output = self.opts.output.clone().unwrap_or("a.out".to_string());
+            self.link(&ld_inputs, &output);
+        }
+    }
+}
+
+pub fn main(args: Vec<String>) {
+    let mut driver = Driver {
+        opts: parse_args(&args),
+        tmpfiles: Vec::new(),
+    };
+    driver.run();
+    driver.cleanup();
+}
@@ -0,0 +1,370 @@
+// --dump-tokens, --dump-ast, --dump-ast=json, --dump-ir。パーサのデバッグや、外部のツールからフロントエンドを使うための出力
+// line:colはプリプロセッサの行番号の印をたどった元のファイルでの位置、offsetはプリプロセス後のソースでのバイト位置
+use std::{cell::RefCell, rc::Rc};
+
+use crate::diagnostic::json_string;
+use crate::lower::lower;
+use crate::type_utils::type_name;
+use crate::types::*;
+
+// 子ノード。BlockのbodyやFuncCallのargsは並び
+enum Child<'a> {
+    One(&'a Node),
+    Many(&'a [Node]),
+}
+
+impl Ctx<'_> {
+    // 1行に1トークン
+    //   keyword 'int' <1:1>
+    //   num '42' 'int' <1:21>
+    pub fn dump_tokens(&mut self) -> String {
+        self.tokens = self.tokenize();
+        self.convert_keywords();
+        let mut out = String::new();
+        for tok in &self.tokens {
+            let text = &self.input_copy[tok.start..tok.start + tok.len];
+            let loc = self.source_location(tok.start);
+            let (kind, ty) = match &tok.kind {
+                TokenKind::Punct { .. } => ("punct", None),
+                TokenKind::Num { ty, .. } => ("num", Some(ty)),
+                TokenKind::FNum { .. } => ("fnum", None),
+                TokenKind::Ident { .. } => ("ident", None),
+                TokenKind::Keyword { .. } => ("keyword", None),
+                TokenKind::Str { ty, .. } => ("str", Some(ty)),
+                TokenKind::Eof => ("eof", None),
+            };
+            out.push_str(&format!("{} '{}'", kind, text));
+            if let Some(ty) = ty {
+                out.push_str(&format!(" '{}'", type_name(ty)));
+            }
+            out.push_str(&format!(" <{}:{}>\n", loc.line, loc.col));
+        }
+        out
+    }
+
+    // 字下げした木。ノードごとに種類、型(add_typeで付いたもの)、範囲を出す
+    //   Function main 'int' <1:12-3:1>
+    //     Block <1:12-3:1>
+    //       Return 'int' <2:3-2:11>
+    //         Num 1 'int' <2:10>
+    pub fn dump_ast(&self) -> String {
+        let mut out = String::new();
+        for decl in self.top_level_decls() {
+            match decl {
+                Decl::Var(var) => {
+                    let var = var.borrow();
+                    out.push_str(&format!(
+                        "GlobalVar {} '{}' {}\n",
+                        var.name,
+                        type_name(&var.ty),
+                        self.span_text(var.span)
+                    ));
+                }
+                Decl::Func(func, body) => {
+                    out.push_str(&format!(
+                        "Function {} '{}' {}\n",
+                        func.name,
+                        type_name(&func.ty),
+                        self.span_text(body.span)
+                    ));
+                    for arg in &func.args {
+                        out.push_str(&format!("  Param {}\n", self.node_text(arg)));
+                    }
+                    self.dump_node(body, 1, &mut out);
+                }
+            }
+        }
+        out
+    }
+
+    fn dump_node(&self, node: &Node, depth: usize, out: &mut String) {
+        out.push_str(&format!(
+            "{}{} {}\n",
+            "  ".repeat(depth),
+            kind_name(node),
+            self.node_text(node)
+        ));
+        for (_, child) in children(node) {
+            match child {
+                Child::One(child) => self.dump_node(child, depth + 1, out),
+                Child::Many(nodes) => {
+                    for child in nodes {
+                        self.dump_node(child, depth + 1, out);
+                    }
+                }
+            }
+        }
+    }
+
+    // 種類の後ろに付ける、値や名前、型、範囲
+    fn node_text(&self, node: &Node) -> String {
+        let mut text = String::new();
+        if let Some(detail) = self.node_detail(node) {
+            text.push_str(&detail);
+            text.push(' ');
+        }
+        if let Some(ty) = &node.ty {
+            text.push_str(&format!("'{}' ", type_name(ty)));
+        }
+        text.push_str(&self.span_text(node.span));
+        text
+    }
+
+    fn node_detail(&self, node: &Node) -> Option<String> {
+        match &node.kind {
+            NodeKind::Num { val } => Some(val.to_string()),
+            NodeKind::Var { .. } if is_string_literal(node) => {
+                Some(self.source_text(node.span).to_string())
+            }
+            NodeKind::Var { var } | NodeKind::MemZero { var } => Some(var.borrow().name.clone()),
+            NodeKind::FuncCall { name, .. } => Some(name.clone()),
+            NodeKind::Member { member, .. } => Some(member.name.clone()),
+            _ => None,
+        }
+    }
+
+    fn source_text(&self, span: Span) -> &str {
+        &self.input_copy[span.start..span.start + span.len]
+    }
+
+    // <line:col-line:col>。1文字なら<line:col>
+    fn span_text(&self, span: Span) -> String {
+        let start = self.source_location(span.start);
+        let end = self.source_location(span.start + span.len.saturating_sub(1));
+        if span.len <= 1 {
+            format!("<{}:{}>", start.line, start.col)
+        } else {
+            format!("<{}:{}-{}:{}>", start.line, start.col, end.line, end.col)
+        }
+    }
+
+    // --dump-ir。関数ごとに、lower.rsで作ったIRをそのまま出す
+    pub fn dump_ir(&self) -> String {
+        lower(self)
+            .iter()
+            .map(|func| func.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // --dump-ast=json。翻訳単位全体で1つのJSON
+    //   {"file":"a.c","decls":[{"kind":"Function","name":"main","type":"int","params":[],"body":{...}}]}
+    // ノードは{"kind","type","span",値や名前,子ノード}。子ノードはlhs, rhs, cond, bodyなどの名前で入れる
+    pub fn dump_ast_json(&self) -> String {
+        let decls: Vec<String> = self
+            .top_level_decls()
+            .into_iter()
+            .map(|decl| match decl {
+                Decl::Var(var) => {
+                    let var = var.borrow();
+                    format!(
+                        "{{\"kind\":\"GlobalVar\",\"name\":{},\"type\":{},\"span\":{}}}",
+                        json_string(&var.name),
+                        json_string(&type_name(&var.ty)),
+                        self.span_json(var.span)
+                    )
+                }
+                Decl::Func(func, body) => {
+                    let params: Vec<String> =
+                        func.args.iter().map(|arg| self.node_json(arg)).collect();
+                    format!(
+                        "{{\"kind\":\"Function\",\"name\":{},\"type\":{},\"params\":[{}],\"body\":{}}}",
+                        json_string(&func.name),
+                        json_string(&type_name(&func.ty)),
+                        params.join(","),
+                        self.node_json(body)
+                    )
+                }
+            })
+            .collect();
+        format!(
+            "{{\"file\":{},\"decls\":[{}]}}\n",
+            json_string(&self.cur_file),
+            decls.join(",")
+        )
+    }
+
+    fn node_json(&self, node: &Node) -> String {
+        let mut fields = vec![
+            format!("\"kind\":\"{}\"", kind_name(node)),
+            format!(
+                "\"type\":{}",
+                node.ty
+                    .as_ref()
+                    .map_or("null".to_string(), |ty| json_string(&type_name(ty)))
+            ),
+            format!("\"span\":{}", self.span_json(node.span)),
+        ];
+        match &node.kind {
+            NodeKind::Num { val } => fields.push(format!("\"value\":{}", val)),
+            NodeKind::Var { .. } if is_string_literal(node) => fields.push(format!(
+                "\"value\":{}",
+                json_string(self.source_text(node.span))
+            )),
+            _ => {
+                if let Some(name) = self.node_detail(node) {
+                    fields.push(format!("\"name\":{}", json_string(&name)));
+                }
+            }
+        }
+        for (name, child) in children(node) {
+            let value = match child {
+                Child::One(child) => self.node_json(child),
+                Child::Many(nodes) => {
+                    let nodes: Vec<String> = nodes.iter().map(|n| self.node_json(n)).collect();
+                    format!("[{}]", nodes.join(","))
+                }
+            };
+            fields.push(format!("\"{}\":{}", name, value));
+        }
+        format!("{{{}}}", fields.join(","))
+    }
+
+    fn span_json(&self, span: Span) -> String {
+        let start = self.source_location(span.start);
+        let end = self.source_location(span.start + span.len.saturating_sub(1));
+        format!(
+            "{{\"offset\":{},\"length\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+            span.start, span.len, start.line, start.col, end.line, end.col
+        )
+    }
+
+    // グローバル変数と関数定義を、ソース上の順に並べる。文字列リテラルや関数内のstatic変数は
+    // グローバル変数として確保しているが、式の中に出てくるので除く
+    fn top_level_decls(&self) -> Vec<Decl<'_>> {
+        let mut decls: Vec<(usize, Decl)> = Vec::new();
+        for var in &self.gvars {
+            let v = var.borrow();
+            if v.name == v.asm_name && !matches!(v.init_gval, Some(InitGval::Str(_))) {
+                decls.push((v.span.start, Decl::Var(var)));
+            }
+        }
+        for func in self.functions.values() {
+            if let Some(body) = func.body.as_ref().filter(|_| func.is_def) {
+                decls.push((body.span.start, Decl::Func(func, body)));
+            }
+        }
+        decls.sort_by_key(|(start, _)| *start);
+        decls.into_iter().map(|(_, decl)| decl).collect()
+    }
+}
+
+enum Decl<'a> {
+    Var(&'a Rc<RefCell<Var>>),
+    Func(&'a Function, &'a Node),
+}
+
+fn is_string_literal(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::Var { var } => matches!(var.borrow().init_gval, Some(InitGval::Str(_))),
+        _ => false,
+    }
+}
+
+fn kind_name(node: &Node) -> &'static str {
+    match &node.kind {
+        NodeKind::Add { .. } => "Add",
+        NodeKind::Sub { .. } => "Sub",
+        NodeKind::Mul { .. } => "Mul",
+        NodeKind::Div { .. } => "Div",
+        NodeKind::Mod { .. } => "Mod",
+        NodeKind::Neg { .. } => "Neg",
+        NodeKind::Eq { .. } => "Eq",
+        NodeKind::Ne { .. } => "Ne",
+        NodeKind::Lt { .. } => "Lt",
+        NodeKind::Le { .. } => "Le",
+        NodeKind::Gt { .. } => "Gt",
+        NodeKind::Ge { .. } => "Ge",
+        NodeKind::And { .. } => "And",
+        NodeKind::Or { .. } => "Or",
+        NodeKind::ExprStmt { .. } => "ExprStmt",
+        NodeKind::Num { .. } => "Num",
+        NodeKind::NdAssign { .. } => "Assign",
+        NodeKind::Var { .. } if is_string_literal(node) => "Str",
+        NodeKind::Var { .. } => "Var",
+        NodeKind::Return { .. } => "Return",
+        NodeKind::Block { .. } => "Block",
+        NodeKind::If { .. } => "If",
+        NodeKind::For { .. } => "For",
+        NodeKind::While { .. } => "While",
+        NodeKind::Addr { .. } => "Addr",
+        NodeKind::Deref { .. } => "Deref",
+        NodeKind::FuncCall { .. } => "FuncCall",
+        NodeKind::GNUStmtExpr { .. } => "StmtExpr",
+        NodeKind::BitAnd { .. } => "BitAnd",
+        NodeKind::BitXor { .. } => "BitXor",
+        NodeKind::BitOr { .. } => "BitOr",
+        NodeKind::BitNot { .. } => "BitNot",
+        NodeKind::Not { .. } => "Not",
+        NodeKind::Shl { .. } => "Shl",
+        NodeKind::Shr { .. } => "Shr",
+        NodeKind::Cond { .. } => "Cond",
+        NodeKind::Member { .. } => "Member",
+        NodeKind::Cast { .. } => "Cast",
+        NodeKind::MemZero { .. } => "MemZero",
+    }
+}
+
+fn children(node: &Node) -> Vec<(&'static str, Child<'_>)> {
+    use Child::*;
+    match &node.kind {
+        NodeKind::Add { lhs, rhs }
+        | NodeKind::Sub { lhs, rhs }
+        | NodeKind::Mul { lhs, rhs }
+        | NodeKind::Div { lhs, rhs }
+        | NodeKind::Mod { lhs, rhs }
+        | NodeKind::Eq { lhs, rhs }
+        | NodeKind::Ne { lhs, rhs }
+        | NodeKind::Lt { lhs, rhs }
+        | NodeKind::Le { lhs, rhs }
+        | NodeKind::Gt { lhs, rhs }
+        | NodeKind::Ge { lhs, rhs }
+        | NodeKind::And { lhs, rhs }
+        | NodeKind::Or { lhs, rhs }
+        | NodeKind::NdAssign { lhs, rhs }
+        | NodeKind::BitAnd { lhs, rhs }
+        | NodeKind::BitXor { lhs, rhs }
+        | NodeKind::BitOr { lhs, rhs }
+        | NodeKind::Shl { lhs, rhs }
+        | NodeKind::Shr { lhs, rhs } => vec![("lhs", One(lhs)), ("rhs", One(rhs))],
+        NodeKind::Neg { lhs }
+        | NodeKind::ExprStmt { lhs }
+        | NodeKind::Return { lhs }
+        | NodeKind::Addr { lhs }
+        | NodeKind::Deref { lhs }
+        | NodeKind::BitNot { lhs }
+        | NodeKind::Not { lhs }
+        | NodeKind::Member { lhs, .. }
+        | NodeKind::Cast { lhs } => vec![("lhs", One(lhs))],
+        NodeKind::Block { body } | NodeKind::GNUStmtExpr { body } => vec![("body", Many(body))],
+        NodeKind::FuncCall { args, .. } => vec![("args", Many(args))],
+        NodeKind::If { cond, then, els } => {
+            let mut children = vec![("cond", One(cond)), ("then", One(then))];
+            if let Some(els) = els {
+                children.push(("els", One(els)));
+            }
+            children
+        }
+        NodeKind::For {
+            init,
+            cond,
+            inc,
+            body,
+        } => {
+            let mut children = vec![("init", One(init))];
+            if let Some(cond) = cond {
+                children.push(("cond", One(cond)));
+            }
+            if let Some(inc) = inc {
+                children.push(("inc", One(inc)));
+            }
+            children.push(("body", One(body)));
+            children
+        }
+        NodeKind::While { cond, body } => vec![("cond", One(cond)), ("body", One(body))],
+        NodeKind::Cond { cond, then, els } => {
+            vec![("cond", One(cond)), ("then", One(then)), ("els", One(els))]
+        }
+        NodeKind::Num { .. } | NodeKind::Var { .. } | NodeKind::MemZero { .. } => Vec::new(),
+    }
+}
@@ -0,0 +1,335 @@
+// 中間表現(IR)。ASTからlower.rsで作り、codegenはこれを読んでアセンブリを出す
+// 関数は基本ブロックの並びで、各ブロックは最後が必ずJmp, Br, Retのどれかで終わる
+// 値はすべて仮想レジスタ(Reg)に入れる。仮想レジスタはいくつ使ってもよく、実際のレジスタやスタックへの割り当てはcodegenが決める
+// 仮想レジスタの値は、今までのx0と同じく型に合わせて64bitに符号拡張(unsignedならゼロ拡張)しておく
+// メモリの読み書きはLoad, Storeだけで行い、ローカル変数はSlot(スタック上の領域)のアドレスを通して読み書きする
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg(pub usize);
+
+pub type BlockId = usize;
+
+// 読み書きや拡張に使う整数の型。ポインタは8byteのunsigned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntTy {
+    pub size: usize,
+    pub is_unsigned: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    LShr, // 論理シフト
+    AShr, // 算術シフト
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // 符号なしの比較。ポインタ同士もこちら
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnOp {
+    Neg,
+    BitNot,
+}
+
+#[derive(Debug, Clone)]
+pub enum Inst {
+    Imm {
+        dst: Reg,
+        val: i64,
+    },
+    Mov {
+        dst: Reg,
+        src: Reg,
+    },
+    // index番目の引数
+    Param {
+        dst: Reg,
+        index: usize,
+    },
+    SlotAddr {
+        dst: Reg,
+        slot: usize,
+    },
+    GlobalAddr {
+        dst: Reg,
+        name: String,
+    },
+    // addrからtyの大きさを読み込んで64bitに拡張する
+    Load {
+        dst: Reg,
+        addr: Reg,
+        ty: IntTy,
+    },
+    // srcの下位sizeバイトを書き込む
+    Store {
+        addr: Reg,
+        src: Reg,
+        size: usize,
+    },
+    // 構造体の代入。srcのアドレスからdstのアドレスへsizeバイト写す
+    MemCopy {
+        dst: Reg,
+        src: Reg,
+        size: usize,
+    },
+    MemZero {
+        addr: Reg,
+        size: usize,
+    },
+    Bin {
+        op: BinOp,
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    // 比較の結果を0か1で返す
+    Cmp {
+        op: CmpOp,
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Un {
+        op: UnOp,
+        dst: Reg,
+        src: Reg,
+    },
+    // tyの大きさに切り詰めて、64bitに拡張し直す。キャストと、intなどの演算結果に使う
+    Ext {
+        dst: Reg,
+        src: Reg,
+        ty: IntTy,
+    },
+    // ビットフィールドの読み書き。srcのoffsetビット目からwidthビットを取り出して拡張する
+    BitExtract {
+        dst: Reg,
+        src: Reg,
+        offset: usize,
+        width: usize,
+        is_unsigned: bool,
+    },
+    // baseのoffsetビット目からwidthビットを、srcの下位ビットで置き換えたもの
+    BitInsert {
+        dst: Reg,
+        base: Reg,
+        src: Reg,
+        offset: usize,
+        width: usize,
+    },
+    Call {
+        dst: Reg,
+        name: String,
+        args: Vec<Reg>,
+    },
+    Jmp(BlockId),
+    // condが0でなければthen、0ならelsへ
+    Br {
+        cond: Reg,
+        then: BlockId,
+        els: BlockId,
+    },
+    Ret(Option<Reg>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+}
+
+// スタック上に確保するローカル変数の領域。オフセットはcodegenが決める
+#[derive(Debug, Clone)]
+pub struct Slot {
+    pub name: String, // 表示用
+    pub size: usize,
+    pub align: usize,
+    pub ty: Option<IntTy>, // 整数やポインタの変数の型。アドレスを取られていなければ仮想レジスタに置き換えられる
+}
+
+#[derive(Debug, Clone)]
+pub struct IrFunction {
+    pub name: String,
+    pub is_static: bool,
+    pub slots: Vec<Slot>,
+    pub blocks: Vec<Block>, // blocks[0]が入口
+    pub nregs: usize,       // 使った仮想レジスタの数
+}
+
+impl Inst {
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, Inst::Jmp(_) | Inst::Br { .. } | Inst::Ret(_))
+    }
+
+    // 値を書き込む仮想レジスタ
+    pub fn def(&self) -> Option<Reg> {
+        match self {
+            Inst::Imm { dst, .. }
+            | Inst::Mov { dst, .. }
+            | Inst::Param { dst, .. }
+            | Inst::SlotAddr { dst, .. }
+            | Inst::GlobalAddr { dst, .. }
+            | Inst::Load { dst, .. }
+            | Inst::Bin { dst, .. }
+            | Inst::Cmp { dst, .. }
+            | Inst::Un { dst, .. }
+            | Inst::Ext { dst, .. }
+            | Inst::BitExtract { dst, .. }
+            | Inst::BitInsert { dst, .. }
+            | Inst::Call { dst, .. } => Some(*dst),
+            Inst::Store { .. }
+            | Inst::MemCopy { .. }
+            | Inst::MemZero { .. }
+            | Inst::Jmp(_)
+            | Inst::Br { .. }
+            | Inst::Ret(_) => None,
+        }
+    }
+
+    // 値を読む仮想レジスタ
+    pub fn uses(&self) -> Vec<Reg> {
+        match self {
+            Inst::Imm { .. }
+            | Inst::Param { .. }
+            | Inst::SlotAddr { .. }
+            | Inst::GlobalAddr { .. }
+            | Inst::Jmp(_)
+            | Inst::Ret(None) => vec![],
+            Inst::Mov { src, .. }
+            | Inst::Un { src, .. }
+            | Inst::Ext { src, .. }
+            | Inst::BitExtract { src, .. } => vec![*src],
+            Inst::Load { addr, .. } | Inst::MemZero { addr, .. } => vec![*addr],
+            Inst::Store { addr, src, .. } => vec![*addr, *src],
+            Inst::MemCopy { dst, src, .. } => vec![*dst, *src],
+            Inst::Bin { lhs, rhs, .. } | Inst::Cmp { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Inst::BitInsert { base, src, .. } => vec![*base, *src],
+            Inst::Call { args, .. } => args.clone(),
+            Inst::Br { cond, .. } => vec![*cond],
+            Inst::Ret(Some(val)) => vec![*val],
+        }
+    }
+
+    // 分岐先のブロック
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Inst::Jmp(target) => vec![*target],
+            Inst::Br { then, els, .. } => vec![*then, *els],
+            _ => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for IntTy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.is_unsigned { "u" } else { "i" };
+        write!(f, "{}{}", sign, self.size * 8)
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Imm { dst, val } => write!(f, "{} = imm {}", dst, val),
+            Inst::Mov { dst, src } => write!(f, "{} = mov {}", dst, src),
+            Inst::Param { dst, index } => write!(f, "{} = param {}", dst, index),
+            Inst::SlotAddr { dst, slot } => write!(f, "{} = slot {}", dst, slot),
+            Inst::GlobalAddr { dst, name } => write!(f, "{} = global {}", dst, name),
+            Inst::Load { dst, addr, ty } => write!(f, "{} = load {} {}", dst, ty, addr),
+            Inst::Store { addr, src, size } => write!(f, "store {} {}, {}", size, addr, src),
+            Inst::MemCopy { dst, src, size } => write!(f, "memcpy {}, {}, {}", dst, src, size),
+            Inst::MemZero { addr, size } => write!(f, "memzero {}, {}", addr, size),
+            Inst::Bin { op, dst, lhs, rhs } => {
+                let op = format!("{:?}", op).to_lowercase();
+                write!(f, "{} = {} {}, {}", dst, op, lhs, rhs)
+            }
+            Inst::Cmp { op, dst, lhs, rhs } => {
+                let op = format!("{:?}", op).to_lowercase();
+                write!(f, "{} = cmp {} {}, {}", dst, op, lhs, rhs)
+            }
+            Inst::Un { op, dst, src } => {
+                let op = format!("{:?}", op).to_lowercase();
+                write!(f, "{} = {} {}", dst, op, src)
+            }
+            Inst::Ext { dst, src, ty } => write!(f, "{} = ext {} {}", dst, ty, src),
+            Inst::BitExtract {
+                dst,
+                src,
+                offset,
+                width,
+                is_unsigned,
+            } => {
+                let op = if *is_unsigned { "ubfx" } else { "sbfx" };
+                write!(f, "{} = {} {}, {}, {}", dst, op, src, offset, width)
+            }
+            Inst::BitInsert {
+                dst,
+                base,
+                src,
+                offset,
+                width,
+            } => write!(f, "{} = bfi {}, {}, {}, {}", dst, base, src, offset, width),
+            Inst::Call { dst, name, args } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{} = call {}({})", dst, name, args.join(", "))
+            }
+            Inst::Jmp(target) => write!(f, "jmp bb{}", target),
+            Inst::Br { cond, then, els } => write!(f, "br {}, bb{}, bb{}", cond, then, els),
+            Inst::Ret(Some(val)) => write!(f, "ret {}", val),
+            Inst::Ret(None) => write!(f, "ret"),
+        }
+    }
+}
+
+// --dump-ir
+//   function main
+//     slot 0: x (4 bytes, align 4)
+//   bb0:
+//     %0 = slot 0
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "function {}", self.name)?;
+        for (i, slot) in self.slots.iter().enumerate() {
+            writeln!(
+                f,
+                "  slot {}: {} ({} bytes, align {})",
+                i, slot.name, slot.size, slot.align
+            )?;
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "bb{}:", i)?;
+            for inst in &block.insts {
+                writeln!(f, "  {}", inst)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,576 @@
+// ASTをIRに変換する。式は値を入れた仮想レジスタを返し、文は基本ブロックに命令を積んでいく
+// 構造体や配列の値は、今までのcodegenと同じくアドレスで持つ
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ir::*;
+use crate::type_utils::{copy_type, is_integer};
+use crate::types::*;
+
+struct Lower<'a, 'b> {
+    ctx: &'a Ctx<'b>, // エラーの報告先
+    func: IrFunction,
+    cur: BlockId,                               // 命令を積んでいるブロック
+    slots: HashMap<*const RefCell<Var>, usize>, // ローカル変数とSlotの対応
+}
+
+// 定義されている関数をソース上の順にIRにする
+pub fn lower(ctx: &Ctx) -> Vec<IrFunction> {
+    let mut funcs: Vec<&Function> = ctx.functions.values().filter(|f| f.is_def).collect();
+    funcs.sort_by_key(|func| func.body.as_ref().map_or(0, |body| body.span.start));
+    funcs
+        .into_iter()
+        .map(|func| lower_function(ctx, func))
+        .collect()
+}
+
+fn lower_function(ctx: &Ctx, func: &Function) -> IrFunction {
+    let mut lower = Lower {
+        ctx,
+        func: IrFunction {
+            name: func.name.clone(),
+            is_static: func.is_static,
+            slots: Vec::new(),
+            blocks: vec![Block::default()],
+            nregs: 0,
+        },
+        cur: 0,
+        slots: HashMap::new(),
+    };
+    // 関数を抜けた時点で、すべてのスコープはexited_scopeに入っている
+    // 関数内のstatic変数はグローバル変数として確保されるので、スタックには置かない
+    for scope in &func.exited_scope {
+        for var in &scope.variables {
+            let v = var.borrow();
+            if !v.is_local {
+                continue;
+            }
+            lower.slots.insert(Rc::as_ptr(var), lower.func.slots.len());
+            lower.func.slots.push(Slot {
+                name: v.name.clone(),
+                size: v.ty.size,
+                align: v.align,
+                ty: (!is_aggregate(&v.ty)).then(|| int_ty(&v.ty)),
+            });
+        }
+    }
+
+    // 引数のレジスタの値を、引数の変数に書き込む
+    // 引数のレジスタはほかの計算で壊れるので、先にすべて仮想レジスタに移しておく
+    let params: Vec<Reg> = (0..func.args.len())
+        .map(|index| lower.emit_def(|dst| Inst::Param { dst, index }))
+        .collect();
+    for (arg, val) in func.args.iter().zip(params) {
+        let addr = lower.lower_addr(arg);
+        let size = match &arg.kind {
+            NodeKind::Var { var } => var.borrow().ty.size,
+            _ => 8,
+        };
+        lower.emit(Inst::Store {
+            addr,
+            src: val,
+            size,
+        });
+    }
+    if let Some(body) = &func.body {
+        lower.lower_stmt(body);
+    }
+    // 最後まで来た場合。mainは0を返す
+    if func.name == "main" {
+        let zero = lower.imm(0);
+        lower.emit(Inst::Ret(Some(zero)));
+    } else {
+        lower.emit(Inst::Ret(None));
+    }
+    remove_unreachable(&mut lower.func);
+    lower.func
+}
+
+// returnの後ろのように、入口からたどり着けないブロックを取り除き、残ったブロックの番号を詰める
+fn remove_unreachable(func: &mut IrFunction) {
+    let mut reachable = vec![false; func.blocks.len()];
+    let mut stack = vec![0];
+    while let Some(block) = stack.pop() {
+        if reachable[block] {
+            continue;
+        }
+        reachable[block] = true;
+        if let Some(last) = func.blocks[block].insts.last() {
+            stack.extend(last.successors());
+        }
+    }
+    let mut new_id = vec![0; func.blocks.len()];
+    let mut blocks = Vec::new();
+    for (i, block) in func.blocks.drain(..).enumerate() {
+        if reachable[i] {
+            new_id[i] = blocks.len();
+            blocks.push(block);
+        }
+    }
+    for block in &mut blocks {
+        match block.insts.last_mut() {
+            Some(Inst::Jmp(target)) => *target = new_id[*target],
+            Some(Inst::Br { then, els, .. }) => {
+                *then = new_id[*then];
+                *els = new_id[*els];
+            }
+            _ => {}
+        }
+    }
+    func.blocks = blocks;
+}
+
+impl Lower<'_, '_> {
+    fn new_reg(&mut self) -> Reg {
+        self.func.nregs += 1;
+        Reg(self.func.nregs - 1)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.func.blocks.push(Block::default());
+        self.func.blocks.len() - 1
+    }
+
+    // 今のブロックに命令を積む。returnの後のように、すでに終わっているブロックには積まない
+    fn emit(&mut self, inst: Inst) {
+        let block = &mut self.func.blocks[self.cur];
+        if block.insts.last().is_some_and(|last| last.is_terminator()) {
+            return;
+        }
+        block.insts.push(inst);
+    }
+
+    // 結果を新しい仮想レジスタに入れる命令を積む
+    fn emit_def(&mut self, f: impl FnOnce(Reg) -> Inst) -> Reg {
+        let dst = self.new_reg();
+        self.emit(f(dst));
+        dst
+    }
+
+    fn imm(&mut self, val: i64) -> Reg {
+        self.emit_def(|dst| Inst::Imm { dst, val })
+    }
+
+    fn jump(&mut self, target: BlockId) {
+        self.emit(Inst::Jmp(target));
+    }
+
+    fn branch(&mut self, cond: Reg, then: BlockId, els: BlockId) {
+        self.emit(Inst::Br { cond, then, els });
+    }
+
+    fn bin(&mut self, op: BinOp, lhs: Reg, rhs: Reg) -> Reg {
+        self.emit_def(|dst| Inst::Bin { op, dst, lhs, rhs })
+    }
+
+    fn cmp(&mut self, op: CmpOp, lhs: Reg, rhs: Reg) -> Reg {
+        self.emit_def(|dst| Inst::Cmp { op, dst, lhs, rhs })
+    }
+
+    // 配列や構造体はアドレスのまま値として扱う
+    fn load(&mut self, addr: Reg, ty: &Type) -> Reg {
+        if is_aggregate(ty) {
+            return addr;
+        }
+        let ty = int_ty(ty);
+        self.emit_def(|dst| Inst::Load { dst, addr, ty })
+    }
+
+    // intなどの演算は64bitで行うので、結果を型の大きさに切り詰めて拡張し直す
+    fn extend_result(&mut self, val: Reg, ty: &Type) -> Reg {
+        if !is_integer(ty) || ty.size == 8 {
+            return val;
+        }
+        let ty = int_ty(ty);
+        self.emit_def(|dst| Inst::Ext { dst, src: val, ty })
+    }
+
+    fn cast(&mut self, val: Reg, from: &Type, to: &Type) -> Reg {
+        if !is_integer(to) || to.size == 8 {
+            return val;
+        }
+        if is_integer(from) && from.size == to.size && from.is_unsigned == to.is_unsigned {
+            return val;
+        }
+        let ty = int_ty(to);
+        self.emit_def(|dst| Inst::Ext { dst, src: val, ty })
+    }
+
+    fn lower_addr(&mut self, node: &Node) -> Reg {
+        match &node.kind {
+            NodeKind::Var { var } => {
+                if let Some(&slot) = self.slots.get(&Rc::as_ptr(var)) {
+                    return self.emit_def(|dst| Inst::SlotAddr { dst, slot });
+                }
+                let name = var.borrow().asm_name.clone();
+                self.emit_def(|dst| Inst::GlobalAddr { dst, name })
+            }
+            NodeKind::Deref { lhs } => self.lower_expr(lhs),
+            NodeKind::Member { lhs, member } => {
+                let base = self.lower_addr(lhs);
+                if member.offset == 0 {
+                    return base;
+                }
+                let offset = self.imm(member.offset as i64);
+                self.bin(BinOp::Add, base, offset)
+            }
+            _ => self.ctx.error_span(node.span, "not an lvalue"),
+        }
+    }
+
+    fn lower_expr(&mut self, node: &Node) -> Reg {
+        let ty = node.ty.as_ref();
+        match &node.kind {
+            NodeKind::Num { val } => self.imm(*val as i64),
+            NodeKind::Var { .. } | NodeKind::Deref { .. } => {
+                let addr = self.lower_addr(node);
+                self.load(addr, &copy_type(node))
+            }
+            NodeKind::Member { member, .. } => {
+                let addr = self.lower_addr(node);
+                let val = self.load(addr, &copy_type(node));
+                // ビットフィールドは記憶単位ごと読み込んでから、該当するビットを取り出して拡張する
+                if !member.is_bitfield {
+                    return val;
+                }
+                let (offset, width) = (member.bit_offset, member.bit_width);
+                let is_unsigned = member.ty.is_unsigned;
+                self.emit_def(|dst| Inst::BitExtract {
+                    dst,
+                    src: val,
+                    offset,
+                    width,
+                    is_unsigned,
+                })
+            }
+            NodeKind::Cast { lhs } => {
+                let val = self.lower_expr(lhs);
+                self.cast(val, &copy_type(lhs), &copy_type(node))
+            }
+            NodeKind::Add { lhs, rhs }
+            | NodeKind::Sub { lhs, rhs }
+            | NodeKind::Mul { lhs, rhs }
+            | NodeKind::Div { lhs, rhs }
+            | NodeKind::Mod { lhs, rhs }
+            | NodeKind::Shl { lhs, rhs }
+            | NodeKind::Shr { lhs, rhs } => {
+                let is_unsigned = ty.is_some_and(|ty| ty.is_unsigned);
+                let op = match (&node.kind, is_unsigned) {
+                    (NodeKind::Add { .. }, _) => BinOp::Add,
+                    (NodeKind::Sub { .. }, _) => BinOp::Sub,
+                    (NodeKind::Mul { .. }, _) => BinOp::Mul,
+                    (NodeKind::Div { .. }, true) => BinOp::UDiv,
+                    (NodeKind::Div { .. }, false) => BinOp::SDiv,
+                    (NodeKind::Mod { .. }, true) => BinOp::URem,
+                    (NodeKind::Mod { .. }, false) => BinOp::SRem,
+                    (NodeKind::Shl { .. }, _) => BinOp::Shl,
+                    // 値は64bitに拡張されているので、unsignedなら論理シフト、それ以外は算術シフトでよい
+                    (_, true) => BinOp::LShr,
+                    (_, false) => BinOp::AShr,
+                };
+                let l = self.lower_expr(lhs);
+                let r = self.lower_expr(rhs);
+                let val = self.bin(op, l, r);
+                self.extend_result(val, &copy_type(node))
+            }
+            // 両辺とも拡張済みなので、ビット演算の結果は拡張し直さなくてよい
+            NodeKind::BitAnd { lhs, rhs }
+            | NodeKind::BitXor { lhs, rhs }
+            | NodeKind::BitOr { lhs, rhs } => {
+                let op = match &node.kind {
+                    NodeKind::BitAnd { .. } => BinOp::And,
+                    NodeKind::BitXor { .. } => BinOp::Xor,
+                    _ => BinOp::Or,
+                };
+                let l = self.lower_expr(lhs);
+                let r = self.lower_expr(rhs);
+                self.bin(op, l, r)
+            }
+            NodeKind::Neg { lhs } | NodeKind::BitNot { lhs } => {
+                let op = match &node.kind {
+                    NodeKind::Neg { .. } => UnOp::Neg,
+                    _ => UnOp::BitNot,
+                };
+                let src = self.lower_expr(lhs);
+                let val = self.emit_def(|dst| Inst::Un { op, dst, src });
+                self.extend_result(val, &copy_type(node))
+            }
+            NodeKind::Not { lhs } => {
+                let val = self.lower_expr(lhs);
+                let zero = self.imm(0);
+                self.cmp(CmpOp::Eq, val, zero)
+            }
+            NodeKind::Eq { lhs, rhs }
+            | NodeKind::Ne { lhs, rhs }
+            | NodeKind::Lt { lhs, rhs }
+            | NodeKind::Le { lhs, rhs }
+            | NodeKind::Gt { lhs, rhs }
+            | NodeKind::Ge { lhs, rhs } => {
+                let unsigned = is_unsigned_cmp(lhs);
+                let op = match (&node.kind, unsigned) {
+                    (NodeKind::Eq { .. }, _) => CmpOp::Eq,
+                    (NodeKind::Ne { .. }, _) => CmpOp::Ne,
+                    (NodeKind::Lt { .. }, false) => CmpOp::Lt,
+                    (NodeKind::Lt { .. }, true) => CmpOp::Ult,
+                    (NodeKind::Le { .. }, false) => CmpOp::Le,
+                    (NodeKind::Le { .. }, true) => CmpOp::Ule,
+                    (NodeKind::Gt { .. }, false) => CmpOp::Gt,
+                    (NodeKind::Gt { .. }, true) => CmpOp::Ugt,
+                    (_, false) => CmpOp::Ge,
+                    (_, true) => CmpOp::Uge,
+                };
+                let l = self.lower_expr(lhs);
+                let r = self.lower_expr(rhs);
+                self.cmp(op, l, r)
+            }
+            // 左辺で結果が決まれば、右辺は評価しない。&&なら0、||なら1を入れて合流する
+            NodeKind::And { lhs, rhs } | NodeKind::Or { lhs, rhs } => {
+                let is_and = matches!(node.kind, NodeKind::And { .. });
+                let result = self.new_reg();
+                let (rhs_bb, short_bb, join_bb) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                let l = self.lower_expr(lhs);
+                if is_and {
+                    self.branch(l, rhs_bb, short_bb);
+                } else {
+                    self.branch(l, short_bb, rhs_bb);
+                }
+                self.cur = rhs_bb;
+                let r = self.lower_expr(rhs);
+                let zero = self.imm(0);
+                let val = self.cmp(CmpOp::Ne, r, zero);
+                self.emit(Inst::Mov {
+                    dst: result,
+                    src: val,
+                });
+                self.jump(join_bb);
+                self.cur = short_bb;
+                let val = self.imm(if is_and { 0 } else { 1 });
+                self.emit(Inst::Mov {
+                    dst: result,
+                    src: val,
+                });
+                self.jump(join_bb);
+                self.cur = join_bb;
+                result
+            }
+            // 両方の枝で同じ仮想レジスタに値を入れて、合流したところで使う
+            NodeKind::Cond { cond, then, els } => {
+                let result = self.new_reg();
+                let (then_bb, els_bb, join_bb) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                let c = self.lower_expr(cond);
+                self.branch(c, then_bb, els_bb);
+                self.cur = then_bb;
+                let val = self.lower_expr(then);
+                self.emit(Inst::Mov {
+                    dst: result,
+                    src: val,
+                });
+                self.jump(join_bb);
+                self.cur = els_bb;
+                let val = self.lower_expr(els);
+                self.emit(Inst::Mov {
+                    dst: result,
+                    src: val,
+                });
+                self.jump(join_bb);
+                self.cur = join_bb;
+                result
+            }
+            NodeKind::NdAssign { lhs, rhs } => self.lower_assign(node, lhs, rhs),
+            NodeKind::Addr { lhs } => self.lower_addr(lhs),
+            NodeKind::FuncCall { name, args } => {
+                let args: Vec<Reg> = args.iter().map(|arg| self.lower_expr(arg)).collect();
+                let name = name.clone();
+                self.emit_def(|dst| Inst::Call { dst, name, args })
+            }
+            // 最後の式文の値が、式全体の値になる
+            NodeKind::GNUStmtExpr { body } => {
+                let Some((last, rest)) = body.split_last() else {
+                    return self.imm(0);
+                };
+                for stmt in rest {
+                    self.lower_stmt(stmt);
+                }
+                match &last.kind {
+                    NodeKind::ExprStmt { lhs } => self.lower_expr(lhs),
+                    _ => {
+                        self.lower_stmt(last);
+                        self.imm(0)
+                    }
+                }
+            }
+            NodeKind::MemZero { var } => {
+                let size = var.borrow().ty.size;
+                let addr = self.lower_addr(&Node {
+                    kind: NodeKind::Var { var: var.clone() },
+                    ty: None,
+                    span: node.span,
+                });
+                self.emit(Inst::MemZero { addr, size });
+                addr
+            }
+            _ => self
+                .ctx
+                .error_span(node.span, "cannot generate code for this expression"),
+        }
+    }
+
+    // 代入式の値は、代入した後の左辺の値
+    fn lower_assign(&mut self, node: &Node, lhs: &Node, rhs: &Node) -> Reg {
+        let ty = copy_type(node);
+        let bitfield = match &lhs.kind {
+            NodeKind::Member { member, .. } if member.is_bitfield => Some(member.clone()),
+            _ => None,
+        };
+        let addr = self.lower_addr(lhs);
+        let val = self.lower_expr(rhs);
+        if is_aggregate(&ty) {
+            self.emit(Inst::MemCopy {
+                dst: addr,
+                src: val,
+                size: ty.size,
+            });
+            return val;
+        }
+        let Some(member) = bitfield else {
+            self.emit(Inst::Store {
+                addr,
+                src: val,
+                size: ty.size,
+            });
+            return val;
+        };
+        // ビットフィールドは記憶単位を読み込み、該当するビットを書き換えて書き戻す
+        // 代入式の値は、ビットフィールドに収まるように切り詰めた値になる
+        let unit = IntTy {
+            size: member.ty.size,
+            is_unsigned: true,
+        };
+        let (offset, width) = (member.bit_offset, member.bit_width);
+        let base = self.emit_def(|dst| Inst::Load {
+            dst,
+            addr,
+            ty: unit,
+        });
+        let merged = self.emit_def(|dst| Inst::BitInsert {
+            dst,
+            base,
+            src: val,
+            offset,
+            width,
+        });
+        self.emit(Inst::Store {
+            addr,
+            src: merged,
+            size: member.ty.size,
+        });
+        let is_unsigned = member.ty.is_unsigned;
+        self.emit_def(|dst| Inst::BitExtract {
+            dst,
+            src: val,
+            offset: 0,
+            width,
+            is_unsigned,
+        })
+    }
+
+    fn lower_stmt(&mut self, node: &Node) {
+        match &node.kind {
+            NodeKind::ExprStmt { lhs } => {
+                self.lower_expr(lhs);
+            }
+            NodeKind::Return { lhs } => {
+                let val = self.lower_expr(lhs);
+                self.emit(Inst::Ret(Some(val)));
+                // 後ろに続く文は到達しないブロックに入れる
+                self.cur = self.new_block();
+            }
+            NodeKind::Block { body } => {
+                for stmt in body {
+                    self.lower_stmt(stmt);
+                }
+            }
+            NodeKind::If { cond, then, els } => {
+                let then_bb = self.new_block();
+                let els_bb = els.as_ref().map(|_| self.new_block());
+                let join_bb = self.new_block();
+                let c = self.lower_expr(cond);
+                self.branch(c, then_bb, els_bb.unwrap_or(join_bb));
+                self.cur = then_bb;
+                self.lower_stmt(then);
+                self.jump(join_bb);
+                if let (Some(els), Some(els_bb)) = (els, els_bb) {
+                    self.cur = els_bb;
+                    self.lower_stmt(els);
+                    self.jump(join_bb);
+                }
+                self.cur = join_bb;
+            }
+            NodeKind::For {
+                init,
+                cond,
+                inc,
+                body,
+            } => {
+                let (cond_bb, body_bb, end_bb) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                self.lower_stmt(init);
+                self.jump(cond_bb);
+                self.cur = cond_bb;
+                match cond {
+                    Some(cond) => {
+                        let c = self.lower_expr(cond);
+                        self.branch(c, body_bb, end_bb);
+                    }
+                    None => self.jump(body_bb),
+                }
+                self.cur = body_bb;
+                self.lower_stmt(body);
+                if let Some(inc) = inc {
+                    self.lower_expr(inc);
+                }
+                self.jump(cond_bb);
+                self.cur = end_bb;
+            }
+            NodeKind::While { cond, body } => {
+                let (cond_bb, body_bb, end_bb) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                self.jump(cond_bb);
+                self.cur = cond_bb;
+                let c = self.lower_expr(cond);
+                self.branch(c, body_bb, end_bb);
+                self.cur = body_bb;
+                self.lower_stmt(body);
+                self.jump(cond_bb);
+                self.cur = end_bb;
+            }
+            _ => self
+                .ctx
+                .error_span(node.span, "cannot generate code for this statement"),
+        }
+    }
+}
+
+fn is_aggregate(ty: &Type) -> bool {
+    matches!(
+        ty.kind,
+        TypeKind::Array { .. } | TypeKind::Struct { .. } | TypeKind::Union { .. }
+    )
+}
+
+// 読み書きに使う整数の型。ポインタは8byteのunsignedとして扱う
+fn int_ty(ty: &Type) -> IntTy {
+    IntTy {
+        size: ty.size,
+        is_unsigned: ty.is_unsigned || matches!(ty.kind, TypeKind::Ptr { .. }),
+    }
+}
+
+// ポインタ同士の比較も符号なしで行う
+fn is_unsigned_cmp(node: &Node) -> bool {
+    let ty = node.ty.as_ref().unwrap();
+    ty.is_unsigned || matches!(ty.kind, TypeKind::Ptr { .. } | TypeKind::Array { .. })
+}
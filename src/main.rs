@@ -1,37 +1,90 @@
-use core::panic;
 use std::collections::HashMap;
 mod tokenize;
 mod types;
-use types::Ctx;
+mod warning;
+use types::{CompileOptions, Ctx, Dump, Scope};
 mod codegen;
+mod ir;
+mod lower;
 mod parse;
+mod regalloc;
 use codegen::*;
+mod diagnostic;
+mod driver;
+mod dump;
 mod new_node;
+mod sema;
 mod type_utils;
 
+use diagnostic::{panic_message, Abort, Fatal};
+use std::panic::{self, AssertUnwindSafe};
+use types::Diagnostic;
+
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        panic!("{}: invalid number of arguments", args[0]);
-    }
-    let input = read_file(args[1].clone().as_str());
+    // Rustのpanicのメッセージやバックトレースは利用者に見せない。
+    // コンパイル中のpanicはcompileで受け止めて、内部エラーとして報告する
+    panic::set_hook(Box::new(|info| {
+        if std::thread::current().name() != Some(driver::COMPILE_THREAD) {
+            eprintln!(
+                "jff: internal compiler error: {}",
+                panic_message(info.payload())
+            );
+        }
+    }));
+    driver::main(std::env::args().collect());
+}
+
+// 1つの翻訳単位をコンパイルして、アセンブリと表示する診断メッセージを返す。エラーがあればアセンブリはNone
+// --dump-tokensなどが指定されていれば、アセンブリの代わりにその出力を返す
+// nameはエラーメッセージに出すファイル名。Ctxは翻訳単位ごとに作るので、ほかのファイルの変数や型は見えない
+pub fn compile(
+    input: &str,
+    name: &str,
+    original: Option<&str>,
+    opts: &CompileOptions,
+) -> (Option<String>, String) {
     let mut ctx = Ctx {
-        input: input.as_str(),
-        input_copy: input.as_str(),
+        input,
+        input_copy: input,
+        original_input: original,
         tokens: Vec::new(),
         consumed_tokens: Vec::new(),
         gvars: Vec::new(),
         cur_func: "".to_string(),
-        cur_file: args[1].clone(),
+        cur_file: name.to_string(),
+        line_markers: Vec::new(),
         functions: HashMap::new(),
+        global_scope: Scope::default(),
+        struct_types: Vec::new(),
+        diags: Default::default(),
+        opts: opts.clone(),
     };
-    ctx.parse();
-    codegen(ctx);
-}
-
-fn read_file(file_path: &str) -> String {
-    match std::fs::read_to_string(file_path) {
-        Ok(content) => content,
-        Err(_) => file_path.to_string(),
-    }
+    let asm = match panic::catch_unwind(AssertUnwindSafe(|| {
+        if ctx.opts.dump == Some(Dump::Tokens) {
+            let tokens = ctx.dump_tokens();
+            return (!ctx.has_errors()).then_some(tokens);
+        }
+        ctx.parse();
+        if ctx.has_errors() {
+            return None;
+        }
+        match ctx.opts.dump {
+            Some(Dump::Ast) => Some(ctx.dump_ast()),
+            Some(Dump::AstJson) => Some(ctx.dump_ast_json()),
+            Some(Dump::Ir) => Some(ctx.dump_ir()),
+            _ => Some(codegen(&ctx)),
+        }
+    })) {
+        Ok(asm) => asm,
+        Err(payload) if payload.is::<Abort>() || payload.is::<Fatal>() => None,
+        Err(payload) => {
+            // すでにエラーがあれば、それに巻き込まれて起きたものなので報告しない
+            if !ctx.has_errors() {
+                let msg = format!("internal compiler error: {}", panic_message(&*payload));
+                ctx.diags.borrow_mut().push(Diagnostic::error(None, &msg));
+            }
+            None
+        }
+    };
+    (asm, ctx.render_diagnostics())
 }
@@ -15,10 +15,12 @@ impl Ctx<'_> {
             scope_idx: -1, // 最初のスコープは-1にすることで、enter_scopeで良い感じに辻褄合わせ。でも、普通にわかりづらいから後で直す
             exited_scope: Vec::new(),
             is_def: true,
+            is_static: false,
+            span: self.name_span(name),
         }
     }
 
-    pub fn new_if(&mut self, cond: Node, then: Node, els: Option<Node>) -> Node {
+    pub fn new_if(&mut self, cond: Node, then: Node, els: Option<Node>, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::If {
                 cond: Box::new(cond),
@@ -26,6 +28,7 @@ impl Ctx<'_> {
                 els: els.map(Box::new),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
@@ -37,6 +40,7 @@ impl Ctx<'_> {
         cond: Option<Node>,
         inc: Option<Node>,
         body: Node,
+        span: Span,
     ) -> Node {
         let mut node = Node {
             kind: NodeKind::For {
@@ -46,296 +50,420 @@ impl Ctx<'_> {
                 body: Box::new(body),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_while(&mut self, cond: Node, body: Node) -> Node {
+    pub fn new_while(&mut self, cond: Node, body: Node, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::While {
                 cond: Box::new(cond),
                 body: Box::new(body),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_block(&mut self, body: Vec<Node>) -> Node {
+    pub fn new_block(&mut self, body: Vec<Node>, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::Block { body },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn null_stmt(&self) -> Node {
+    pub fn null_stmt(&self, span: Span) -> Node {
         Node {
             kind: NodeKind::Block { body: Vec::new() },
             ty: None,
+            span,
         }
     }
 
     pub fn new_expr_stmt(&mut self, lhs: Node) -> Node {
+        let span = lhs.span;
         let mut node = Node {
             kind: NodeKind::ExprStmt { lhs: Box::new(lhs) },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_assign(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::NdAssign {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_return(&mut self, lhs: Node) -> Node {
+    pub fn new_return(&mut self, lhs: Node, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::Return { lhs: Box::new(lhs) },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_eq(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Eq {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_ne(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Ne {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_lt(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Lt {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_le(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Le {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_gt(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Gt {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_ge(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Ge {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_and(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::And {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_or(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Or {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_neg(&mut self, lhs: Node) -> Node {
+    pub fn new_neg(&mut self, lhs: Node, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::Neg { lhs: Box::new(lhs) },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_addr(&mut self, lhs: Node) -> Node {
+    pub fn new_addr(&mut self, lhs: Node, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::Addr { lhs: Box::new(lhs) },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_deref(&mut self, lhs: Node, tok: Token) -> Node {
+    pub fn new_deref(&mut self, lhs: Node, span: Span) -> Node {
         let mut node = Node {
-            kind: NodeKind::Deref {
-                lhs: Box::new(lhs),
-                tok,
-            },
+            kind: NodeKind::Deref { lhs: Box::new(lhs) },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_mul(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Mul {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_div(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Div {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_mod(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::Mod {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_num(&mut self, val: isize) -> Node {
+    pub fn new_num(&mut self, val: isize, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::Num { val },
             ty: Some(new_int_ty()),
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
-    pub fn new_long(&mut self, val: isize) -> Node {
+    pub fn new_long(&mut self, val: isize, span: Span) -> Node {
         Node {
             kind: NodeKind::Num { val },
             ty: Some(new_long_ty()),
+            span,
         }
     }
 
-    pub fn new_var(&mut self, var: Rc<RefCell<Var>>) -> Node {
+    pub fn new_var(&mut self, var: Rc<RefCell<Var>>, span: Span) -> Node {
         let mut node = Node {
             kind: NodeKind::Var { var },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_bit_and(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::BitAnd {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_bit_xor(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::BitXor {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
     pub fn new_bit_or(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut node = Node {
             kind: NodeKind::BitOr {
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             },
             ty: None,
+            span,
         };
         self.add_type(&mut node);
         node
     }
 
+    pub fn new_bit_not(&mut self, lhs: Node, span: Span) -> Node {
+        let mut node = Node {
+            kind: NodeKind::BitNot { lhs: Box::new(lhs) },
+            ty: None,
+            span,
+        };
+        self.add_type(&mut node);
+        node
+    }
+
+    pub fn new_not(&mut self, lhs: Node, span: Span) -> Node {
+        let mut node = Node {
+            kind: NodeKind::Not { lhs: Box::new(lhs) },
+            ty: None,
+            span,
+        };
+        self.add_type(&mut node);
+        node
+    }
+
+    pub fn new_shl(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
+        let mut node = Node {
+            kind: NodeKind::Shl {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+            ty: None,
+            span,
+        };
+        self.add_type(&mut node);
+        node
+    }
+
+    pub fn new_shr(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
+        let mut node = Node {
+            kind: NodeKind::Shr {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+            ty: None,
+            span,
+        };
+        self.add_type(&mut node);
+        node
+    }
+
+    pub fn new_cond(&mut self, cond: Node, then: Node, els: Node) -> Node {
+        let span = cond.span.to(els.span);
+        let mut node = Node {
+            kind: NodeKind::Cond {
+                cond: Box::new(cond),
+                then: Box::new(then),
+                els: Box::new(els),
+            },
+            ty: None,
+            span,
+        };
+        self.add_type(&mut node);
+        node
+    }
+
+    pub fn new_member(&mut self, lhs: Node, member: Member, span: Span) -> Node {
+        Node {
+            ty: Some(member.ty.clone()),
+            span,
+            kind: NodeKind::Member {
+                lhs: Box::new(lhs),
+                member,
+            },
+        }
+    }
+
+    pub fn new_memzero(&mut self, var: Rc<RefCell<Var>>, span: Span) -> Node {
+        Node {
+            kind: NodeKind::MemZero { var },
+            ty: None,
+            span,
+        }
+    }
+
     pub fn new_cast(&mut self, lhs: Node, ty: Type) -> Node {
+        let span = lhs.span;
         let mut node = Node {
             kind: NodeKind::Cast { lhs: Box::new(lhs) },
             ty: Some(ty),
+            span,
         };
         self.add_type(&mut node);
         node
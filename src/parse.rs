@@ -1,7 +1,8 @@
 use std::cmp::{self};
 use std::{cell::RefCell, mem::swap, rc::Rc};
 
-use crate::tokenize::equal;
+use crate::diagnostic::recover;
+use crate::tokenize::{equal, string_elem_type};
 use crate::type_utils::*;
 use crate::types::*;
 
@@ -13,106 +14,273 @@ fn align_to(n: usize, to: usize) -> usize {
     }
 }
 
+fn struct_type_id(ty: &Type) -> usize {
+    match &ty.kind {
+        TypeKind::Struct { id, .. } | TypeKind::Union { id, .. } => *id,
+        _ => unreachable!("not a struct nor union"),
+    }
+}
+
+// メンバーの実際のアラインメント。packedなら、指定がない限り1バイト境界に詰める
+fn member_align(member: &Member, is_packed: bool) -> usize {
+    match (member.align, is_packed) {
+        (0, true) => 1,
+        (0, false) => member.ty.align,
+        (align, true) => align,
+        (align, false) => cmp::max(align, member.ty.align),
+    }
+}
+
+// メンバーのオフセットを決めて、構造体の型を作る
+// alignはaligned属性で指定された構造体全体のアラインメントで、指定がなければ0
+fn struct_layout(
+    members: Vec<Member>,
+    id: usize,
+    tag: String,
+    is_packed: bool,
+    align: usize,
+) -> Type {
+    let mut members = members;
+    // ビットフィールドがあるので、ビット単位で位置を数える
+    let mut bits = 0;
+    // alignmentを加える
+    let mut max_align = 1;
+    for member in &mut members {
+        member.align = member_align(member, is_packed);
+        let unit = member.ty.size * 8;
+        if member.is_bitfield && member.bit_width == 0 {
+            // int : 0; は次のビットフィールドを新しい記憶単位から始めさせる
+            bits = align_to(bits, unit);
+        } else if member.is_bitfield {
+            // AArch64のABIでは、ビットフィールドは型の大きさの記憶単位をまたがないように詰める
+            if bits / unit != (bits + member.bit_width - 1) / unit {
+                bits = align_to(bits, unit);
+            }
+            member.offset = bits / unit * member.ty.size;
+            member.bit_offset = bits % unit;
+            bits += member.bit_width;
+        } else {
+            // char data[]; のようなフレキシブル配列メンバーは大きさ0として扱う
+            bits = align_to(bits, member.align * 8);
+            member.offset = bits / 8;
+            bits += member.ty.size * 8;
+        }
+        // 名前のないビットフィールドは構造体のアラインメントに影響しない
+        if !(member.is_bitfield && member.name.is_empty()) {
+            max_align = cmp::max(max_align, member.align);
+        }
+    }
+    max_align = cmp::max(max_align, align);
+    let size = align_to(align_to(bits, 8) / 8, max_align);
+    Type {
+        kind: TypeKind::Struct { members, id, tag },
+        size,
+        align: max_align,
+        is_unsigned: false,
+    }
+}
+
+// unionのメンバーはすべてオフセット0
+fn union_layout(
+    members: Vec<Member>,
+    id: usize,
+    tag: String,
+    is_packed: bool,
+    align: usize,
+) -> Type {
+    let mut members = members;
+    let mut max_size = 0;
+    let mut max_align = 1;
+    for member in &mut members {
+        member.align = member_align(member, is_packed);
+        max_size = cmp::max(max_size, member.ty.size);
+        // 名前のないビットフィールドはunionのアラインメントに影響しない
+        if !(member.is_bitfield && member.name.is_empty()) {
+            max_align = cmp::max(max_align, member.align);
+        }
+    }
+    max_align = cmp::max(max_align, align);
+    max_size = align_to(max_size, max_align);
+    Type {
+        kind: TypeKind::Union { members, id, tag },
+        size: max_size,
+        align: max_align,
+        is_unsigned: false,
+    }
+}
+
 //
 // union, struct
 //
 impl Ctx<'_> {
-    fn union_decl(&mut self) -> Type {
+    // struct, unionに共通する、タグの処理とメンバーの読み込み
+    // struct T { ... } の場合は、メンバーより先にタグを登録しておき、struct T *next; のような自己参照を書けるようにする
+    // struct T; や、定義より前に書かれた struct T * は不完全型になり、あとの定義で完成する
+    fn struct_union_decl(&mut self, is_union: bool) -> Type {
+        // struct __attribute__((packed)) T { ... } と struct T { ... } __attribute__((packed)) のどちらも書ける
+        let (mut is_packed, mut align) = self.attribute_list();
         let mut tag = String::new();
         if let TokenKind::Ident { name } = &self.get_tok(0).kind {
             tag = name.clone();
             self.advance(1);
         }
 
-        // union型の値を宣言する時;
+        // 型の値を宣言する時;
         if !tag.is_empty() && !self.hequal("{") {
-            // findtag return ty
-            if let Some(ty) = self.find_tag(tag) {
-                return ty;
+            // struct T; はいまのスコープに新しい型を宣言する。それ以外は外側のスコープのタグも探す
+            let found = if self.hequal(";") {
+                self.find_tag_in_cur_scope(&tag)
             } else {
-                self.error_tok(self.get_tok(0), "unknown struct type");
+                self.find_tag(tag.clone())
+            };
+            if let Some(ty) = found {
+                return ty;
             }
+            let ty = self.new_incomplete_type(is_union, &tag);
+            self.push_tag(tag, ty.clone());
+            return ty;
         }
 
         self.skip("{");
-        let mut members = self.struct_members();
-        let mut max_size = 0;
-        let mut max_align = 0;
-        for member in &mut members {
-            max_size = cmp::max(max_size, member.ty.size);
-            max_align = cmp::max(max_align, member.ty.align);
-        }
-        if max_align > 8 {
-            max_align = 8;
-        }
-        max_size = align_to(max_size, max_align);
-        let ty = Type {
-            kind: TypeKind::Union { members },
-            size: max_size,
-            align: max_align,
+        // 同じスコープで前方宣言されていれば、その型を完成させる
+        let declared = if tag.is_empty() {
+            None
+        } else {
+            self.find_tag_in_cur_scope(&tag)
         };
+        let id =
+            match declared.as_ref().map(|ty| &ty.kind) {
+                Some(
+                    TypeKind::Struct { members, id, .. } | TypeKind::Union { members, id, .. },
+                ) if members.is_empty() && self.struct_types[*id].is_none() => *id,
+                _ => {
+                    let ty = self.new_incomplete_type(is_union, &tag);
+                    if !tag.is_empty() {
+                        self.push_tag(tag.clone(), ty.clone());
+                    }
+                    struct_type_id(&ty)
+                }
+            };
 
+        let members = self.struct_members();
+        let (packed, aligned) = self.attribute_list();
+        is_packed |= packed;
+        align = cmp::max(align, aligned);
+        if is_packed && members.iter().any(|member| member.is_bitfield) {
+            self.error_tok(self.get_tok(-1), "packed bit-fields are not supported");
+        }
+        let ty = if is_union {
+            union_layout(members, id, tag.clone(), is_packed, align)
+        } else {
+            struct_layout(members, id, tag.clone(), is_packed, align)
+        };
+        self.struct_types[id] = Some(ty.clone());
+        // struct S x; のように、先に宣言したグローバル変数の型も完成させる
+        for var in &self.gvars {
+            let mut v = var.borrow_mut();
+            if let TypeKind::Struct {
+                members,
+                id: var_id,
+                ..
+            }
+            | TypeKind::Union {
+                members,
+                id: var_id,
+                ..
+            } = &v.ty.kind
+            {
+                if members.is_empty() && *var_id == id {
+                    v.align = cmp::max(v.align, ty.align);
+                    v.ty = ty.clone();
+                }
+            }
+        }
         if !tag.is_empty() {
-            self.push_tag(tag, ty.clone());
+            for struct_tag in self.cur_scope().tags.iter_mut().rev() {
+                if struct_tag.tag == tag {
+                    struct_tag.ty = ty.clone();
+                    break;
+                }
+            }
         }
         ty
     }
 
-    fn struct_decl(&mut self) -> Type {
-        let mut tag = String::new();
-        if let TokenKind::Ident { name } = &self.get_tok(0).kind {
-            tag = name.clone();
-            self.advance(1);
-        }
-
-        // 方の値を宣言する時;
-        if !tag.is_empty() && !self.hequal("{") {
-            // findtag return ty
-            if let Some(ty) = self.find_tag(tag) {
-                return ty;
-            } else {
-                self.error_tok(self.get_tok(0), "unknown struct type");
+    fn new_incomplete_type(&mut self, is_union: bool, tag: &str) -> Type {
+        let id = self.struct_types.len();
+        self.struct_types.push(None);
+        let kind = if is_union {
+            TypeKind::Union {
+                members: Vec::new(),
+                id,
+                tag: tag.to_string(),
+            }
+        } else {
+            TypeKind::Struct {
+                members: Vec::new(),
+                id,
+                tag: tag.to_string(),
             }
-        }
-
-        self.skip("{");
-        let mut members = self.struct_members();
-        let mut offset = 0;
-        // alignmentを加える
-        let mut max_align = 0;
-        for member in &mut members {
-            offset = align_to(offset, member.ty.align);
-            max_align = cmp::max(max_align, member.ty.align);
-            member.offset = offset;
-            offset += member.ty.size;
-        }
-        if max_align > 8 {
-            max_align = 8;
-        }
-        offset = align_to(offset, max_align);
-        let ty = Type {
-            kind: TypeKind::Struct { members },
-            size: offset,
-            align: max_align,
         };
-
-        if !tag.is_empty() {
-            self.push_tag(tag, ty.clone());
+        Type {
+            kind,
+            size: 0,
+            align: 1,
+            is_unsigned: false,
         }
-        ty
     }
 
     fn struct_members(&mut self) -> Vec<Member> {
         let mut members: Vec<Member> = Vec::new();
         while !self.consume("}") {
-            let base_ty = self.declspec();
+            let (attr, base_ty) = self.declspec_attr();
+            if attr.is_static || attr.is_extern {
+                self.error_tok(self.get_tok(-1), "storage class is not allowed here");
+            }
             while !self.consume(";") {
-                let (ty, name, _) = self.declarator(base_ty.clone());
-                let member = Member {
+                let tok = self.get_tok(0).clone();
+                // フレキシブル配列メンバーは最後のメンバーでなければならない
+                if let Some(TypeKind::Array { len, .. }) = members.last().map(|m| &m.ty.kind) {
+                    if *len < 0 {
+                        self.error_tok(&tok, "flexible array member must be the last member");
+                    }
+                }
+                // int : 3; のような名前のないビットフィールドは、declaratorを持たない
+                let (ty, name) = if self.hequal(":") {
+                    (base_ty.clone(), String::new())
+                } else {
+                    let (ty, name, _) = self.declarator(base_ty.clone());
+                    (ty, name)
+                };
+                // int x __attribute__((aligned(8))); のように、メンバーごとにアラインメントを指定できる
+                let align = cmp::max(attr.align, self.attribute_list().1);
+                if self.is_incomplete_struct(&ty) {
+                    self.error_tok(&tok, "field has incomplete type");
+                }
+                let mut member = Member {
                     name,
                     ty,
-                    offset: 0, // あとでstruct_declで更新する
+                    offset: 0, // あとでstruct_layoutで更新する
+                    is_bitfield: false,
+                    bit_offset: 0,
+                    bit_width: 0,
+                    align,
                 };
+                if self.hequal(":") {
+                    let tok = self.advance(1);
+                    let width = self.const_expr();
+                    if !is_integer(&member.ty) {
+                        self.error_tok(&tok, "bit-field has non-integer type");
+                    }
+                    if width < 0 || width as usize > member.ty.size * 8 {
+                        self.error_tok(&tok, "invalid bit-field width");
+                    }
+                    if width == 0 && !member.name.is_empty() {
+                        self.error_tok(&tok, "named bit-field has zero width");
+                    }
+                    member.is_bitfield = true;
+                    member.bit_width = width as usize;
+                }
                 members.push(member);
                 if self.hequal(",") {
                     self.advance(1);
@@ -125,7 +293,7 @@ impl Ctx<'_> {
 
     // tyはTyStruct限定の想定
     fn get_struct_member(&mut self, ty: Type, name: String) -> Member {
-        if let TypeKind::Struct { members } | TypeKind::Union { members } = ty.kind {
+        if let TypeKind::Struct { members, .. } | TypeKind::Union { members, .. } = ty.kind {
             for member in &members {
                 if member.name == name {
                     return member.clone();
@@ -148,29 +316,35 @@ impl Ctx<'_> {
                 self.error_tok(&self.tokens[0], "expected identifier");
             };
             // nodeを作る。lhsがx.aのxの方。lhsのoffsetから、memberのoffsetを足したところのデータを取得する形になる
-            let member = self.get_struct_member(copy_type(&lhs), name); // 可読性ゴミ
-            Node {
-                kind: NodeKind::Member {
-                    lhs: Box::new(lhs.clone()),
-                    member: member.clone(),
-                },
-                ty: Some(member.ty),
+            let ty = self.complete_type(copy_type(&lhs));
+            if self.is_incomplete_struct(&ty) {
+                self.error_tok(self.get_tok(-1), "incomplete struct type");
             }
+            let member = self.get_struct_member(ty, name); // 可読性ゴミ
+            let span = lhs.span.to(self.tok_span(self.get_tok(-1)));
+            self.new_member(lhs, member, span)
         } else {
             self.error_tok(&self.tokens[0], "not a struct nor union");
         }
     }
 
+    fn find_tag_in_cur_scope(&mut self, tag: &str) -> Option<Type> {
+        let struct_tags = &self.cur_scope().tags;
+        struct_tags
+            .iter()
+            .rev()
+            .find(|struct_tag| struct_tag.tag == tag)
+            .map(|struct_tag| struct_tag.ty.clone())
+    }
+
     fn push_tag(&mut self, tag: String, ty: Type) {
-        let function = self.get_func();
-        let struct_tags = &mut function.scopes[function.scope_idx as usize].tags;
+        let struct_tags = &mut self.cur_scope().tags;
         let struct_tag = StructTag { tag, ty };
         struct_tags.push(struct_tag);
     }
 
     fn find_tag(&mut self, tag: String) -> Option<Type> {
-        let function = self.get_func();
-        for scope in function.scopes.iter().rev() {
+        for scope in self.visible_scopes() {
             for struct_tag in &scope.tags {
                 if struct_tag.tag == tag {
                     return Some(struct_tag.clone().ty);
@@ -201,54 +375,60 @@ impl Ctx<'_> {
         }
 
         self.skip("{");
-        let member = self.enum_members();
+        // メンバの値の式で同じenumの前のメンバを参照できるように、メンバより先に登録しておく
         let ty = Type {
-            kind: TypeKind::Enum { members: member },
+            kind: TypeKind::Enum {
+                members: Vec::new(),
+                tag: tag.clone(),
+            },
             size: 4,
             align: 4,
+            is_unsigned: false,
         };
-        if !tag.is_empty() {
-            self.push_enum(tag, ty.clone());
-        } else {
-            self.push_enum("".to_string(), ty.clone());
-        }
-        ty
+        self.push_enum(tag, ty);
+        self.enum_members()
     }
 
-    fn enum_members(&mut self) -> Vec<EnumMember> {
-        let mut members: Vec<EnumMember> = Vec::new();
+    // 登録したenumにメンバを追加していき、最終的な型を返す
+    fn enum_members(&mut self) -> Type {
         let mut i = 0;
         while !self.consume("}") {
             let name = self.get_ident();
             // 代入
             if self.consume("=") {
-                i = self.get_num();
+                i = self.const_expr();
             }
             let mem = EnumMember {
                 name,
                 ty: new_int_ty(),
                 val: i,
             };
-            members.push(mem);
+            self.push_enum_member(mem);
             i += 1;
             if self.consume(",") {
                 continue;
             }
         }
-        members
+        let enums = &self.cur_scope().enums;
+        enums.last().unwrap().ty.clone()
     }
 
     fn push_enum(&mut self, name: String, ty: Type) {
-        let function = self.get_func();
-        let enums = &mut function.scopes[function.scope_idx as usize].enums;
+        let enums = &mut self.cur_scope().enums;
         let enm = Enum { tag: name, ty };
         enums.push(enm);
     }
 
+    // 直前にpush_enumしたenumにメンバを追加する
+    fn push_enum_member(&mut self, mem: EnumMember) {
+        let enums = &mut self.cur_scope().enums;
+        if let TypeKind::Enum { members, .. } = &mut enums.last_mut().unwrap().ty.kind {
+            members.push(mem);
+        }
+    }
+
     pub fn find_enum(&mut self, name: String) -> Option<Enum> {
-        let func = self.get_func();
-        let scopes = &func.scopes;
-        for scope in scopes.iter().rev() {
+        for scope in self.visible_scopes() {
             for enm in &scope.enums {
                 if enm.tag == name {
                     return Some(enm.clone());
@@ -259,22 +439,14 @@ impl Ctx<'_> {
     }
 
     // 変数にenumのメンバを代入する際に使用
-    pub fn find_enum_member(&mut self, name: &str) -> Option<Node> {
-        let func = self.get_func();
-        let scopes = &func.scopes;
-        let mut val = None;
-        for scope in scopes.iter().rev() {
-            for enm in &scope.enums {
-                if let TypeKind::Enum { members: list } = &enm.ty.kind {
-                    for mem in list {
-                        if mem.name == name {
-                            val = Some(mem.val);
-                        }
-                    }
-                }
-            }
-        }
-        val.map(|val| self.new_num(val))
+    pub fn find_enum_member(&mut self, name: &str, span: Span) -> Option<Node> {
+        let val = self.visible_scopes().into_iter().find_map(|scope| {
+            scope.enums.iter().find_map(|enm| match &enm.ty.kind {
+                TypeKind::Enum { members, .. } => members.iter().find(|mem| mem.name == name),
+                _ => None,
+            })
+        });
+        val.map(|mem| mem.val).map(|val| self.new_num(val, span))
     }
 }
 
@@ -293,19 +465,101 @@ impl Ctx<'_> {
         ty
     }
 
+    // 記憶域クラスと_Alignas。declspec_attrで型の前後から読む
+    // storage_class = ("static" | "extern" | "_Alignas" "(" (typename | const_expr) ")")*
+    fn storage_class(&mut self) -> VarAttr {
+        let mut attr = VarAttr::default();
+        loop {
+            if self.consume("static") {
+                attr.is_static = true;
+            } else if self.consume("extern") {
+                attr.is_extern = true;
+            } else if self.consume("_Alignas") {
+                self.skip("(");
+                let align = if self.is_typename(&self.tokens[0].clone()) {
+                    self.typename().align
+                } else {
+                    self.alignment()
+                };
+                self.skip(")");
+                attr.align = cmp::max(attr.align, align);
+            } else {
+                break;
+            }
+        }
+        attr
+    }
+
+    // static int x; と int static x; のように、記憶域クラスと_Alignasは型の前後どちらにも書ける
+    fn declspec_attr(&mut self) -> (VarAttr, Type) {
+        let mut attr = self.storage_class();
+        let ty = self.declspec();
+        let after = self.storage_class();
+        attr.is_static |= after.is_static;
+        attr.is_extern |= after.is_extern;
+        attr.align = cmp::max(attr.align, after.align);
+        if attr.is_static && attr.is_extern {
+            self.error_tok(
+                self.get_tok(-1),
+                "static and extern may not be used together",
+            );
+        }
+        (attr, ty)
+    }
+
+    // アラインメントを指定する定数式。2のべき乗でなければならない。0は指定なしと同じ
+    fn alignment(&mut self) -> usize {
+        let tok = self.get_tok(0).clone();
+        let align = self.const_expr();
+        if align < 0 || (align as usize).count_ones() > 1 {
+            self.error_tok(&tok, "requested alignment is not a positive power of 2");
+        }
+        align as usize
+    }
+
+    // GNUの属性。packedとaligned(N)だけを扱い、(is_packed, align)を返す。alignは指定がなければ0
+    // attribute_list = ("__attribute__" "(" "(" attribute ("," attribute)* ")" ")")*
+    // attribute = "packed" | "aligned" ("(" const_expr ")")?
+    fn attribute_list(&mut self) -> (bool, usize) {
+        let mut is_packed = false;
+        let mut align = 0;
+        while self.consume("__attribute__") {
+            self.skip("(");
+            self.skip("(");
+            let mut first = true;
+            while !self.consume(")") {
+                if !first {
+                    self.skip(",");
+                }
+                first = false;
+                // 属性名はキーワードではなく識別子として読まれている
+                match self.get_ident().as_str() {
+                    "packed" | "__packed__" => is_packed = true,
+                    "aligned" | "__aligned__" => {
+                        // 値を省略すると、そのターゲットで最大のアラインメントになる
+                        if self.consume("(") {
+                            align = cmp::max(align, self.alignment());
+                            self.skip(")");
+                        } else {
+                            align = cmp::max(align, 16);
+                        }
+                    }
+                    _ => self.error_tok(self.get_tok(-1), "unknown attribute"),
+                }
+            }
+            self.skip(")");
+        }
+        (is_packed, align)
+    }
+
+    // unsigned long int のような整数型の組み合わせは、それぞれのキーワードの個数を数えて決める
+    // declspec = ("char" | "short" | "int" | "long" | "signed" | "unsigned")+
+    //          | struct_decl | union_decl | enum_decl | typedef_name
     fn declspec(&mut self) -> Type {
-        if self.consume("int") {
-            return new_int_ty();
-        } else if self.consume("short") {
-            return new_short();
-        } else if self.consume("long") {
-            return new_long_ty();
-        } else if self.consume("char") {
-            return new_char_ty();
-        } else if self.consume("struct") {
-            return self.struct_decl();
+        if self.consume("struct") {
+            return self.struct_union_decl(false);
         } else if self.consume("union") {
-            return self.union_decl();
+            return self.struct_union_decl(true);
         } else if self.consume("enum") {
             return self.enum_decl();
         } else if let TokenKind::Ident { name } = &self.tokens[0].kind {
@@ -315,6 +569,57 @@ impl Ctx<'_> {
             }
         }
 
+        const CHAR: usize = 1;
+        const SHORT: usize = 1 << 2;
+        const INT: usize = 1 << 4;
+        const LONG: usize = 1 << 6;
+        const SIGNED: usize = 1 << 9;
+        const UNSIGNED: usize = 1 << 10;
+        let mut counter = 0;
+        let mut ty = None;
+        loop {
+            let tok = self.get_tok(0).clone();
+            counter += match () {
+                _ if equal(&tok, "char") => CHAR,
+                _ if equal(&tok, "short") => SHORT,
+                _ if equal(&tok, "int") => INT,
+                _ if equal(&tok, "long") => LONG,
+                _ if equal(&tok, "signed") => SIGNED,
+                _ if equal(&tok, "unsigned") => UNSIGNED,
+                _ => break,
+            };
+            self.advance(1);
+            // long long はlongと同じ8バイトとして扱う。signedだけならint
+            let is = |list: &[usize]| list.contains(&counter);
+            ty = Some(if is(&[CHAR, SIGNED + CHAR]) {
+                new_char_ty()
+            } else if is(&[UNSIGNED + CHAR]) {
+                new_unsigned(new_char_ty())
+            } else if is(&[SHORT, SHORT + INT, SIGNED + SHORT, SIGNED + SHORT + INT]) {
+                new_short()
+            } else if is(&[UNSIGNED + SHORT, UNSIGNED + SHORT + INT]) {
+                new_unsigned(new_short())
+            } else if is(&[INT, SIGNED, SIGNED + INT]) {
+                new_int_ty()
+            } else if is(&[UNSIGNED, UNSIGNED + INT]) {
+                new_unsigned(new_int_ty())
+            } else if is(&[LONG, LONG + INT, LONG + LONG, LONG + LONG + INT])
+                || is(&[SIGNED + LONG, SIGNED + LONG + INT])
+                || is(&[SIGNED + LONG + LONG, SIGNED + LONG + LONG + INT])
+            {
+                new_long_ty()
+            } else if is(&[UNSIGNED + LONG, UNSIGNED + LONG + INT])
+                || is(&[UNSIGNED + LONG + LONG, UNSIGNED + LONG + LONG + INT])
+            {
+                new_unsigned(new_long_ty())
+            } else {
+                self.error_tok(&tok, "invalid type");
+            });
+        }
+        if let Some(ty) = ty {
+            return ty;
+        }
+
         if let Some(last) = self.consumed_tokens.last() {
             // ネスト小さくしたい
             if let TokenKind::Keyword { name } = &last.kind {
@@ -323,59 +628,787 @@ impl Ctx<'_> {
                 }
             }
         }
-        self.error_tok(&self.tokens[0], "type expected");
+        self.error_tok(&self.tokens[0], "type expected");
+    }
+
+    // return (Type, name, is_function)
+    // グローバル変数か、関数かの判定に使う。parseのはじめ以外では使用しない
+    fn declarator(&mut self, ty: Type) -> (Type, String, bool) {
+        let mut ty = ty;
+        let is_func: bool;
+        while self.consume("*") {
+            ty = new_ptr_to(ty);
+        }
+        let name = self.get_ident();
+        (ty, is_func) = self.type_suffix(ty);
+        (ty, name, is_func)
+    }
+
+    // 配列であればそれも含めた型を返す
+    // "(" の有無で関数かの判定をする
+    // return (Type, is_function)
+    fn type_suffix(&mut self, ty: Type) -> (Type, bool) {
+        if self.hequal("[") {
+            self.advance(1);
+            // int x[] = {...} のように長さが省略された場合は、初期化子から長さを決める
+            if self.consume("]") {
+                let (ty, _) = self.type_suffix(ty);
+                return (new_array_ty(ty, -1), false);
+            }
+            let tok = self.tokens[0].clone();
+            let size = self.const_expr();
+            if size < 0 {
+                self.error_tok(&tok, "array size is negative");
+            }
+            self.skip("]");
+            let (ty, _) = self.type_suffix(ty);
+            return (new_array_ty(ty, size), false);
+        }
+        if self.hequal("(") {
+            return (ty, true);
+        }
+        (ty, false)
+    }
+
+    fn declaration(&mut self) -> Node {
+        let start = self.get_tok(0).clone();
+        let (attr, base_ty) = self.declspec_attr();
+        let mut body = Vec::new();
+        while !self.consume(";") {
+            let (ty, name, _) = self.declarator(base_ty.clone());
+            // int x __attribute__((aligned(16))); のように、declaratorの後ろに属性を書ける
+            let mut attr = attr;
+            attr.align = cmp::max(attr.align, self.attribute_list().1);
+
+            // 関数内のextern宣言は、ほかで定義されたグローバル変数を指す
+            // 外側のローカル変数を隠せるように、今のスコープにもグローバル変数を入れておく
+            if attr.is_extern {
+                let var = self.declare_gvar(name.as_str(), ty, attr);
+                self.declare_extern_lvar(name.as_str(), var);
+                self.skip_declarator_end();
+                continue;
+            }
+            if attr.is_static {
+                let var = self.create_static_lvar(name.as_str(), ty);
+                let align = cmp::max(var.borrow().align, attr.align);
+                var.borrow_mut().align = align;
+                if self.consume("=") {
+                    self.gvar_initializer(var);
+                }
+                self.skip_declarator_end();
+                continue;
+            }
+
+            // spはx29と同じく16バイト境界にあるので、それより大きいアラインメントは保証できない
+            if cmp::max(attr.align, ty.align) > 16 {
+                self.error_tok(
+                    self.get_tok(-1),
+                    "alignment greater than 16 is not supported for local variables",
+                );
+            }
+            if self.is_incomplete_struct(&ty) {
+                let msg = format!("storage size of '{}' isn't known", name);
+                self.report(Diagnostic::error(Some(self.name_span(&name)), &msg));
+            }
+            let node = self.create_lvar(name.clone().as_str(), ty, false);
+            if let NodeKind::Var { var } = &node.kind {
+                let align = cmp::max(var.borrow().align, attr.align);
+                var.borrow_mut().align = align;
+            }
+
+            if self.hequal("=") {
+                self.advance(1);
+                let NodeKind::Var { var } = &node.kind else {
+                    unreachable!("create_lvar must return a variable node");
+                };
+                body.extend(self.lvar_initializer(var.clone(), node.span));
+            } else {
+                let node = self.new_expr_stmt(node);
+                body.push(node);
+            }
+            self.skip_declarator_end();
+        }
+        let span = self.span_from(&start);
+        self.new_block(body, span)
+    }
+
+    // 宣言子の後ろは、次の宣言子へ続く,か、宣言の終わりの;でなければならない。;はdeclarationのループで読む
+    fn skip_declarator_end(&mut self) {
+        if !self.hequal(";") {
+            self.skip(",");
+        }
+    }
+}
+
+//
+// initializer
+//
+fn new_initializer(ty: Type, is_flexible: bool) -> Initializer {
+    let mut init = Initializer {
+        ty: ty.clone(),
+        expr: None,
+        children: Vec::new(),
+        is_flexible: false,
+        member_idx: 0,
+    };
+    match &ty.kind {
+        TypeKind::Array { ptr_to, len } => {
+            if is_flexible && *len < 0 {
+                init.is_flexible = true;
+                return init;
+            }
+            for _ in 0..*len {
+                init.children
+                    .push(new_initializer((**ptr_to).clone(), false));
+            }
+        }
+        TypeKind::Struct { members, .. } | TypeKind::Union { members, .. } => {
+            for (i, member) in members.iter().enumerate() {
+                // フレキシブル配列メンバーは、初期化子の要素数に合わせて大きさが決まる
+                let is_last = i == members.len() - 1;
+                let is_struct = matches!(ty.kind, TypeKind::Struct { .. });
+                init.children.push(new_initializer(
+                    member.ty.clone(),
+                    is_flexible && is_struct && is_last,
+                ));
+            }
+        }
+        _ => {}
+    }
+    init
+}
+
+// 名前のないビットフィールドは初期化子の対象にならないので飛ばす
+fn skip_unnamed_members(init: &Initializer, i: usize) -> usize {
+    let mut i = i;
+    if let TypeKind::Struct { members, .. } = &init.ty.kind {
+        while i < members.len() && members[i].is_bitfield && members[i].name.is_empty() {
+            i += 1;
+        }
+    }
+    i
+}
+
+// 長さが未定の配列の場合は、必要な分だけ要素を増やしてから返す
+fn array_child(init: &mut Initializer, i: usize) -> Option<&mut Initializer> {
+    if init.is_flexible {
+        if let TypeKind::Array { ptr_to, .. } = &init.ty.kind {
+            while init.children.len() <= i {
+                init.children
+                    .push(new_initializer((**ptr_to).clone(), false));
+            }
+        }
+    }
+    init.children.get_mut(i)
+}
+
+// char x[] = "abc"; や int x[] = L"abc"; のように、要素の型が文字列リテラルと同じ配列は文字列リテラルで初期化できる
+// char, signed char, unsigned charの配列はどれでも""で初期化できる
+fn is_string_array(ty: &Type, str_ty: &Type) -> bool {
+    if let TypeKind::Array { ptr_to, .. } = &ty.kind {
+        let elem = string_elem_type(str_ty);
+        return is_integer(ptr_to)
+            && ptr_to.size == elem.size
+            && (elem.size == 1 || ptr_to.is_unsigned == elem.is_unsigned);
+    }
+    false
+}
+
+// ポインタや配列が指す先の型の名前。ポインタ同士の引き算で、型が同じかを比べるのに使う
+fn pointee_name(node: &Node) -> String {
+    match node.ty.as_ref().map(|ty| &ty.kind) {
+        Some(TypeKind::Ptr { ptr_to } | TypeKind::Array { ptr_to, .. }) => type_name(ptr_to),
+        _ => String::new(),
+    }
+}
+
+impl Ctx<'_> {
+    // initializer = string_initializer | array_initializer | struct_initializer
+    //             | union_initializer | "{" assign "}" | assign
+    fn initializer(&mut self, ty: Type) -> Initializer {
+        let mut init = new_initializer(ty, true);
+        self.initializer2(&mut init);
+        // int x[] = {1, 2, 3}; なら、ここでint[3]に確定する
+        if init.is_flexible {
+            if let TypeKind::Array { ptr_to, .. } = &init.ty.kind {
+                init.ty = new_array_ty((**ptr_to).clone(), init.children.len() as isize);
+            }
+        }
+        // struct { int n; char s[]; } x = {3, "ab"}; なら、sの大きさの分だけxを大きくする
+        if let TypeKind::Struct { members, id, tag } = &init.ty.kind {
+            if let Some(child) = init.children.last_mut() {
+                if child.is_flexible {
+                    if let TypeKind::Array { ptr_to, .. } = &child.ty.kind {
+                        child.ty = new_array_ty((**ptr_to).clone(), child.children.len() as isize);
+                    }
+                    let mut members = members.clone();
+                    let last = members.last_mut().unwrap();
+                    last.ty = child.ty.clone();
+                    let size = align_to(last.offset + last.ty.size, init.ty.align);
+                    init.ty = Type {
+                        kind: TypeKind::Struct {
+                            members,
+                            id: *id,
+                            tag: tag.clone(),
+                        },
+                        size,
+                        align: init.ty.align,
+                        is_unsigned: false,
+                    };
+                }
+            }
+        }
+        init
+    }
+
+    fn initializer2(&mut self, init: &mut Initializer) {
+        let kind = init.ty.kind.clone();
+        match kind {
+            TypeKind::Array { .. } => {
+                if let TokenKind::Str { str, ty } = &self.tokens[0].kind {
+                    if is_string_array(&init.ty, ty) {
+                        let str = str.clone();
+                        self.advance(1);
+                        self.string_initializer(init, &str);
+                        return;
+                    }
+                }
+                if self.hequal("{") {
+                    self.array_initializer1(init);
+                } else {
+                    self.array_initializer2(init, 0);
+                }
+            }
+            TypeKind::Struct { .. } => {
+                if self.hequal("{") {
+                    self.struct_initializer1(init);
+                    return;
+                }
+                // struct T x = y; のように、構造体の値そのもので初期化する場合
+                let pos = self.consumed_tokens.len();
+                let mut expr = self.assign();
+                self.add_type(&mut expr);
+                if let Some(Type {
+                    kind: TypeKind::Struct { .. },
+                    ..
+                }) = &expr.ty
+                {
+                    init.expr = Some(expr);
+                    return;
+                }
+                // {}が省略されている場合は、読んだトークンを戻してメンバを順番に初期化する
+                self.rewind(pos);
+                self.struct_initializer2(init, 0);
+            }
+            TypeKind::Union { .. } => self.union_initializer(init),
+            _ => {
+                // int x = {3}; も許す
+                if self.consume("{") {
+                    init.expr = Some(self.assign());
+                    self.consume(",");
+                    self.skip("}");
+                    return;
+                }
+                init.expr = Some(self.assign());
+            }
+        }
+    }
+
+    // char x[] = "abc";
+    fn string_initializer(&mut self, init: &mut Initializer, str: &[u8]) {
+        let TypeKind::Array { ptr_to, .. } = &init.ty.kind else {
+            unreachable!();
+        };
+        let elem = (**ptr_to).clone();
+        let str_len = str.len() / elem.size;
+        if init.is_flexible {
+            array_child(init, str_len - 1);
+        }
+        let len = cmp::min(init.children.len(), str_len);
+        for (i, c) in str.chunks(elem.size).take(len).enumerate() {
+            let mut buf = [0; 8];
+            buf[..elem.size].copy_from_slice(c);
+            let val = truncate(u64::from_le_bytes(buf) as isize, &elem);
+            let span = self.tok_span(self.get_tok(-1));
+            init.children[i].expr = Some(self.new_num(val, span));
+        }
+    }
+
+    // "{" initializer ("," initializer)* ","? "}"
+    fn array_initializer1(&mut self, init: &mut Initializer) {
+        self.skip("{");
+        let mut i = 0;
+        let mut first = true;
+        while !self.consume_end() {
+            if !first {
+                self.skip(",");
+            }
+            first = false;
+
+            if self.hequal("[") {
+                i = self.array_designator(init);
+                let child = array_child(init, i).unwrap();
+                self.designation(child);
+                i += 1;
+                continue;
+            }
+
+            if let Some(child) = array_child(init, i) {
+                self.initializer2(child);
+            } else {
+                self.skip_excess_element();
+            }
+            i += 1;
+        }
+    }
+
+    // {}が省略された配列の初期化。int x[2][2] = {1, 2, 3, 4}; の内側の配列など
+    fn array_initializer2(&mut self, init: &mut Initializer, start: usize) {
+        let mut i = start;
+        while i < init.children.len() && !self.is_end() {
+            let pos = self.consumed_tokens.len();
+            if i > 0 {
+                self.skip(",");
+            }
+            if self.hequal("[") || self.hequal(".") {
+                self.rewind(pos);
+                return;
+            }
+            self.initializer2(&mut init.children[i]);
+            i += 1;
+        }
+    }
+
+    // "{" initializer ("," initializer)* ","? "}"
+    fn struct_initializer1(&mut self, init: &mut Initializer) {
+        self.skip("{");
+        let mut i = 0;
+        let mut first = true;
+        while !self.consume_end() {
+            if !first {
+                self.skip(",");
+            }
+            first = false;
+
+            if self.hequal(".") {
+                i = self.struct_designator(init);
+                self.designation(&mut init.children[i]);
+                i += 1;
+                continue;
+            }
+
+            i = skip_unnamed_members(init, i);
+            if i < init.children.len() {
+                self.initializer2(&mut init.children[i]);
+            } else {
+                self.skip_excess_element();
+            }
+            i += 1;
+        }
+    }
+
+    // {}が省略された構造体の初期化
+    fn struct_initializer2(&mut self, init: &mut Initializer, start: usize) {
+        let mut i = skip_unnamed_members(init, start);
+        let mut first = start == 0;
+        while i < init.children.len() && !self.is_end() {
+            let pos = self.consumed_tokens.len();
+            if !first {
+                self.skip(",");
+            }
+            first = false;
+            if self.hequal("[") || self.hequal(".") {
+                self.rewind(pos);
+                return;
+            }
+            self.initializer2(&mut init.children[i]);
+            i = skip_unnamed_members(init, i + 1);
+        }
+    }
+
+    // unionは1つのメンバのみ初期化する。指示子がなければ最初のメンバ
+    fn union_initializer(&mut self, init: &mut Initializer) {
+        if init.children.is_empty() {
+            self.error_tok(&self.tokens[0], "cannot initialize an empty union");
+        }
+        if self.hequal("{") && equal(&self.tokens[1], ".") {
+            self.advance(1);
+            init.member_idx = self.struct_designator(init);
+            let idx = init.member_idx;
+            self.designation(&mut init.children[idx]);
+            self.consume(",");
+            self.skip("}");
+            return;
+        }
+        if self.consume("{") {
+            self.initializer2(&mut init.children[0]);
+            self.consume(",");
+            self.skip("}");
+            return;
+        }
+
+        // union T x = y;
+        let pos = self.consumed_tokens.len();
+        let mut expr = self.assign();
+        self.add_type(&mut expr);
+        if let Some(Type {
+            kind: TypeKind::Union { .. },
+            ..
+        }) = &expr.ty
+        {
+            init.expr = Some(expr);
+            return;
+        }
+        self.rewind(pos);
+        self.initializer2(&mut init.children[0]);
+    }
+
+    // 指示子の後に続く初期化。.a.b = 1 や [1][2] = 3 のように指示子が連なる場合もある
+    fn designation(&mut self, init: &mut Initializer) {
+        if self.hequal("[") {
+            if !matches!(init.ty.kind, TypeKind::Array { .. }) {
+                self.error_tok(&self.tokens[0], "array index in non-array initializer");
+            }
+            let i = self.array_designator(init);
+            let child = array_child(init, i).unwrap();
+            self.designation(child);
+            self.array_initializer2(init, i + 1);
+            return;
+        }
+        if self.hequal(".") {
+            match init.ty.kind {
+                TypeKind::Struct { .. } => {
+                    let i = self.struct_designator(init);
+                    self.designation(&mut init.children[i]);
+                    init.expr = None;
+                    self.struct_initializer2(init, i + 1);
+                }
+                TypeKind::Union { .. } => {
+                    init.member_idx = self.struct_designator(init);
+                    let idx = init.member_idx;
+                    self.designation(&mut init.children[idx]);
+                }
+                _ => self.error_tok(
+                    &self.tokens[0],
+                    "field name not in struct or union initializer",
+                ),
+            }
+            return;
+        }
+        self.skip("=");
+        self.initializer2(init);
+    }
+
+    // "[" const_expr "]"
+    fn array_designator(&mut self, init: &Initializer) -> usize {
+        self.skip("[");
+        let tok = self.tokens[0].clone();
+        let i = self.const_expr();
+        if i < 0 || (!init.is_flexible && i as usize >= init.children.len()) {
+            self.error_tok(&tok, "array designator index exceeds array bounds");
+        }
+        self.skip("]");
+        i as usize
+    }
+
+    // "." ident
+    fn struct_designator(&mut self, init: &Initializer) -> usize {
+        self.skip(".");
+        let tok = self.tokens[0].clone();
+        let name = self.get_ident();
+        if let TypeKind::Struct { members, .. } | TypeKind::Union { members, .. } = &init.ty.kind {
+            if let Some(i) = members.iter().position(|m| m.name == name) {
+                return i;
+            }
+        }
+        self.error_tok(&tok, "struct has no such member");
+    }
+
+    // 配列の長さを超えた初期化子は読み飛ばす
+    fn skip_excess_element(&mut self) {
+        if self.consume("{") {
+            self.skip_excess_element();
+            while self.consume(",") {
+                if self.hequal("}") {
+                    break;
+                }
+                self.skip_excess_element();
+            }
+            self.skip("}");
+            return;
+        }
+        self.assign();
+    }
+
+    fn is_end(&self) -> bool {
+        equal(&self.tokens[0], "}") || (equal(&self.tokens[0], ",") && equal(&self.tokens[1], "}"))
+    }
+
+    fn consume_end(&mut self) -> bool {
+        if self.consume("}") {
+            return true;
+        }
+        if self.hequal(",") && equal(&self.tokens[1], "}") {
+            self.advance(2);
+            return true;
+        }
+        false
+    }
+
+    // 初期化子を、変数への代入の列に変換する
+    // 配列や構造体は、まず全体を0で埋めてから、明示された要素のみ代入する
+    // spanは初期化する変数の位置。初期化のために作るノードはすべてそこを指す
+    fn lvar_initializer(&mut self, var: Rc<RefCell<Var>>, span: Span) -> Vec<Node> {
+        let ty = copy_var_type(&var);
+        let init = self.initializer(ty);
+        var.borrow_mut().ty = init.ty.clone();
+
+        let mut body = Vec::new();
+        if let TypeKind::Array { .. } | TypeKind::Struct { .. } | TypeKind::Union { .. } =
+            init.ty.kind
+        {
+            let memzero = self.new_memzero(var.clone(), span);
+            body.push(self.new_expr_stmt(memzero));
+        }
+        let lhs = self.new_var(var, span);
+        self.create_lvar_init(&init, lhs, &mut body);
+        body
+    }
+
+    fn create_lvar_init(&mut self, init: &Initializer, lhs: Node, body: &mut Vec<Node>) {
+        match &init.ty.kind {
+            TypeKind::Array { .. } => {
+                for (i, child) in init.children.iter().enumerate() {
+                    let idx = self.new_num(i as isize, lhs.span);
+                    let add = self.new_add(lhs.clone(), idx);
+                    let elem = self.new_deref(add, lhs.span);
+                    self.create_lvar_init(child, elem, body);
+                }
+            }
+            TypeKind::Struct { members, .. } if init.expr.is_none() => {
+                for (member, child) in members.iter().zip(&init.children) {
+                    let elem = self.new_member(lhs.clone(), member.clone(), lhs.span);
+                    self.create_lvar_init(child, elem, body);
+                }
+            }
+            TypeKind::Union { members, .. } if init.expr.is_none() => {
+                let idx = init.member_idx;
+                let span = lhs.span;
+                let elem = self.new_member(lhs, members[idx].clone(), span);
+                self.create_lvar_init(&init.children[idx], elem, body);
+            }
+            _ => {
+                if let Some(expr) = &init.expr {
+                    self.check_conversion(&init.ty, expr);
+                    let assign = self.new_assign(lhs, expr.clone());
+                    body.push(self.new_expr_stmt(assign));
+                }
+            }
+        }
+    }
+
+    // グローバル変数の初期化子を評価して、バイト列に書き込む
+    fn write_gvar_data(
+        &mut self,
+        init: &Initializer,
+        buf: &mut [u8],
+        relocs: &mut Vec<Relocation>,
+        offset: usize,
+    ) {
+        match &init.ty.kind {
+            TypeKind::Array { ptr_to, .. } => {
+                for (i, child) in init.children.iter().enumerate() {
+                    self.write_gvar_data(child, buf, relocs, offset + ptr_to.size * i);
+                }
+            }
+            TypeKind::Struct { members, .. } if init.expr.is_none() => {
+                for (member, child) in members.iter().zip(&init.children) {
+                    if member.is_bitfield {
+                        self.write_bitfield_data(member, child, buf, offset);
+                        continue;
+                    }
+                    self.write_gvar_data(child, buf, relocs, offset + member.offset);
+                }
+            }
+            TypeKind::Union { members, .. } if init.expr.is_none() => {
+                let member = &members[init.member_idx];
+                let child = &init.children[init.member_idx];
+                if member.is_bitfield {
+                    self.write_bitfield_data(member, child, buf, offset);
+                    return;
+                }
+                self.write_gvar_data(child, buf, relocs, offset);
+            }
+            _ => {
+                let Some(expr) = &init.expr else {
+                    return;
+                };
+                let mut label = None;
+                let val = self.eval2(expr, Some(&mut label));
+                if let Some(label) = label {
+                    if init.ty.size != 8 {
+                        self.error_tok(
+                            self.get_tok(-1),
+                            "initializer element is not computable at load time",
+                        );
+                    }
+                    relocs.push(Relocation {
+                        offset,
+                        label,
+                        addend: val,
+                    });
+                    return;
+                }
+                self.check_conversion(&init.ty, expr);
+                let bytes = (val as i64).to_le_bytes();
+                buf[offset..offset + init.ty.size].copy_from_slice(&bytes[..init.ty.size]);
+            }
+        }
     }
 
-    // return (Type, name, is_function)
-    // グローバル変数か、関数かの判定に使う。parseのはじめ以外では使用しない
-    fn declarator(&mut self, ty: Type) -> (Type, String, bool) {
-        let mut ty = ty;
-        let is_func: bool;
-        while self.consume("*") {
-            ty = new_ptr_to(ty);
-        }
-        let name = self.get_ident();
-        (ty, is_func) = self.type_suffix(ty);
-        (ty, name, is_func)
+    // ビットフィールドは記憶単位をほかのメンバーと共有するので、該当するビットだけを書き換える
+    fn write_bitfield_data(
+        &mut self,
+        member: &Member,
+        init: &Initializer,
+        buf: &mut [u8],
+        offset: usize,
+    ) {
+        let Some(expr) = &init.expr else {
+            return;
+        };
+        let val = self.eval(expr) as u64;
+        let pos = offset + member.offset;
+        let size = member.ty.size;
+        let mut unit = [0u8; 8];
+        unit[..size].copy_from_slice(&buf[pos..pos + size]);
+        let mask = u64::MAX >> (64 - member.bit_width) << member.bit_offset;
+        let unit = (u64::from_le_bytes(unit) & !mask) | ((val << member.bit_offset) & mask);
+        buf[pos..pos + size].copy_from_slice(&unit.to_le_bytes()[..size]);
     }
+}
 
-    // 配列であればそれも含めた型を返す
-    // "(" の有無で関数かの判定をする
-    // return (Type, is_function)
-    fn type_suffix(&mut self, ty: Type) -> (Type, bool) {
-        if self.hequal("[") {
-            self.advance(1);
-            let size = self.get_and_skip_number();
-            self.skip("]");
-            let (ty, _) = self.type_suffix(ty);
-            return (new_array_ty(ty, size as usize), false);
-        }
-        if self.hequal("(") {
-            return (ty, true);
-        }
-        (ty, false)
+//
+// constant expression
+//
+impl Ctx<'_> {
+    // 定数式。配列の長さ、enumの値、指示子の添字などに使用する
+    pub fn const_expr(&mut self) -> isize {
+        let node = self.conditional();
+        self.eval(&node)
     }
 
-    fn declaration(&mut self) -> Node {
-        let base_ty = self.declspec();
-        let mut body = Vec::new();
-        while !self.consume(";") {
-            let (ty, name, _) = self.declarator(base_ty.clone());
-            let mut node = self.create_lvar(name.clone().as_str(), ty, false);
+    // 定数式を評価する
+    fn eval(&self, node: &Node) -> isize {
+        self.eval2(node, None)
+    }
 
-            if self.hequal("=") {
-                self.advance(1);
-                let rhs = self.expr();
-                node = self.new_assign(node, rhs);
+    // グローバル変数の初期化では、ほかのグローバル変数のアドレス + 定数 も許す
+    // その場合はlabelに変数名が入り、定数部分が返る
+    fn eval2(&self, node: &Node, label: Option<&mut Option<String>>) -> isize {
+        let val = self.eval3(node, label);
+        // 実行時と同じく、結果を型の大きさで切り詰めてから符号拡張(ゼロ拡張)する
+        match &node.ty {
+            Some(ty) if is_integer(ty) => truncate(val, ty),
+            _ => val,
+        }
+    }
+
+    fn eval3(&self, node: &Node, label: Option<&mut Option<String>>) -> isize {
+        let is_unsigned = |node: &Node| match &node.ty {
+            Some(ty) => {
+                ty.is_unsigned || matches!(ty.kind, TypeKind::Ptr { .. } | TypeKind::Array { .. })
             }
-            let node = self.new_expr_stmt(node);
-            body.push(node);
-            if self.hequal(",") {
-                self.advance(1);
-                continue;
+            None => false,
+        };
+        match &node.kind {
+            NodeKind::Num { val } => *val,
+            NodeKind::Add { lhs, rhs } => self.eval2(lhs, label).wrapping_add(self.eval(rhs)),
+            NodeKind::Sub { lhs, rhs } => self.eval2(lhs, label).wrapping_sub(self.eval(rhs)),
+            NodeKind::Mul { lhs, rhs } => self.eval(lhs).wrapping_mul(self.eval(rhs)),
+            NodeKind::Div { lhs, rhs } | NodeKind::Mod { lhs, rhs } => {
+                let l = self.eval(lhs);
+                let r = self.eval(rhs);
+                if r == 0 {
+                    self.error_tok(self.get_tok(-1), "division by zero in constant expression");
+                }
+                match (&node.kind, is_unsigned(node)) {
+                    (NodeKind::Div { .. }, true) => ((l as u64) / (r as u64)) as isize,
+                    (NodeKind::Div { .. }, false) => l.wrapping_div(r),
+                    (_, true) => ((l as u64) % (r as u64)) as isize,
+                    (_, false) => l.wrapping_rem(r),
+                }
+            }
+            NodeKind::Neg { lhs } => self.eval(lhs).wrapping_neg(),
+            NodeKind::Not { lhs } => (self.eval(lhs) == 0) as isize,
+            NodeKind::BitNot { lhs } => !self.eval(lhs),
+            NodeKind::BitAnd { lhs, rhs } => self.eval(lhs) & self.eval(rhs),
+            NodeKind::BitXor { lhs, rhs } => self.eval(lhs) ^ self.eval(rhs),
+            NodeKind::BitOr { lhs, rhs } => self.eval(lhs) | self.eval(rhs),
+            NodeKind::Shl { lhs, rhs } => self.eval(lhs).wrapping_shl(self.eval(rhs) as u32),
+            NodeKind::Shr { lhs, rhs } if is_unsigned(node) => {
+                (self.eval(lhs) as u64).wrapping_shr(self.eval(rhs) as u32) as isize
+            }
+            NodeKind::Shr { lhs, rhs } => self.eval(lhs).wrapping_shr(self.eval(rhs) as u32),
+            NodeKind::Eq { lhs, rhs } => (self.eval(lhs) == self.eval(rhs)) as isize,
+            NodeKind::Ne { lhs, rhs } => (self.eval(lhs) != self.eval(rhs)) as isize,
+            NodeKind::Lt { lhs, rhs }
+            | NodeKind::Le { lhs, rhs }
+            | NodeKind::Gt { lhs, rhs }
+            | NodeKind::Ge { lhs, rhs }
+                if is_unsigned(lhs) =>
+            {
+                let l = self.eval(lhs) as u64;
+                let r = self.eval(rhs) as u64;
+                match node.kind {
+                    NodeKind::Lt { .. } => (l < r) as isize,
+                    NodeKind::Le { .. } => (l <= r) as isize,
+                    NodeKind::Gt { .. } => (l > r) as isize,
+                    _ => (l >= r) as isize,
+                }
+            }
+            NodeKind::Lt { lhs, rhs } => (self.eval(lhs) < self.eval(rhs)) as isize,
+            NodeKind::Le { lhs, rhs } => (self.eval(lhs) <= self.eval(rhs)) as isize,
+            NodeKind::Gt { lhs, rhs } => (self.eval(lhs) > self.eval(rhs)) as isize,
+            NodeKind::Ge { lhs, rhs } => (self.eval(lhs) >= self.eval(rhs)) as isize,
+            NodeKind::And { lhs, rhs } => (self.eval(lhs) != 0 && self.eval(rhs) != 0) as isize,
+            NodeKind::Or { lhs, rhs } => (self.eval(lhs) != 0 || self.eval(rhs) != 0) as isize,
+            NodeKind::Cond { cond, then, els } => {
+                if self.eval(cond) != 0 {
+                    self.eval2(then, label)
+                } else {
+                    self.eval2(els, label)
+                }
+            }
+            NodeKind::Cast { lhs } => self.eval2(lhs, label),
+            NodeKind::Addr { lhs } => self.eval_rval(lhs, label),
+            // 配列は先頭のアドレスになる
+            NodeKind::Var { .. } | NodeKind::Member { .. }
+                if matches!(copy_type(node).kind, TypeKind::Array { .. }) =>
+            {
+                self.eval_rval(node, label)
+            }
+            _ => self.error_tok(self.get_tok(-1), "not a compile-time constant"),
+        }
+    }
+
+    // 変数のアドレスを評価する
+    fn eval_rval(&self, node: &Node, label: Option<&mut Option<String>>) -> isize {
+        match &node.kind {
+            NodeKind::Var { var } => {
+                let var = var.borrow();
+                match label {
+                    Some(label) if !var.is_local => {
+                        *label = Some(var.asm_name.clone());
+                        0
+                    }
+                    _ => self.error_tok(self.get_tok(-1), "not a compile-time constant"),
+                }
             }
+            NodeKind::Deref { lhs, .. } => self.eval2(lhs, label),
+            NodeKind::Member { lhs, member } => self.eval_rval(lhs, label) + member.offset as isize,
+            _ => self.error_tok(self.get_tok(-1), "invalid initializer"),
         }
-        self.new_block(body)
     }
 }
 
@@ -386,15 +1419,14 @@ impl Ctx<'_> {
     fn stmt(&mut self) -> Node {
         match &self.tokens[0].kind {
             TokenKind::Keyword { name } if name == "return" => {
-                self.advance(1);
+                let tok = self.advance(1);
                 let expr = self.expr();
-                let node = self.new_return(expr);
                 self.skip(";");
-                return node;
+                let span = self.span_from(&tok);
+                return self.new_return(expr, span);
             }
             TokenKind::Keyword { name } if name == "if" => {
-                self.advance(1);
-                let node: Node;
+                let tok = self.advance(1);
                 self.skip("(");
                 let cond = self.expr();
                 self.skip(")");
@@ -404,11 +1436,12 @@ impl Ctx<'_> {
                     self.advance(1);
                     els = Some(self.stmt());
                 }
-                node = self.new_if(cond, then, els);
+                let span = self.span_from(&tok);
+                let node = self.new_if(cond, then, els, span);
                 return node;
             }
             TokenKind::Keyword { name } if name == "for" => {
-                self.advance(1);
+                let tok = self.advance(1);
                 self.enter_scope();
                 self.skip("(");
                 let init = if self.is_typename(&self.tokens[0].clone()) {
@@ -427,17 +1460,19 @@ impl Ctx<'_> {
                 }
                 self.skip(")");
                 let body = self.stmt();
-                let node = self.new_for(init, cond, inc, body);
+                let span = self.span_from(&tok);
+                let node = self.new_for(init, cond, inc, body, span);
                 self.leave_scope();
                 return node;
             }
             TokenKind::Keyword { name } if name == "while" => {
-                self.advance(1);
+                let tok = self.advance(1);
                 self.skip("(");
                 let cond = self.expr();
                 self.skip(")");
                 let body = self.stmt();
-                let node = self.new_while(cond, body);
+                let span = self.span_from(&tok);
+                let node = self.new_while(cond, body, span);
                 return node;
             }
             TokenKind::Punct { str } if str == "{" => {
@@ -452,44 +1487,73 @@ impl Ctx<'_> {
     }
 
     fn push_type(&mut self, deftype: TypedefType) {
-        let func = self.get_func();
-        let types = &mut func.scopes[func.scope_idx as usize].types;
+        let types = &mut self.cur_scope().types;
         types.push(deftype);
     }
 
+    // typedef int A, *B;
+    // typedefはstmtだが、Nodeを返さないという点で特殊。関数の外でも使える
+    fn parse_typedef(&mut self) {
+        self.skip("typedef");
+        let base_ty = self.declspec();
+        let mut first = true;
+        while !self.consume(";") {
+            if !first {
+                self.skip(",");
+            }
+            first = false;
+            let (ty, name, _) = self.declarator(base_ty.clone());
+            self.push_type(TypedefType { name, ty });
+        }
+    }
+
+    // 呼び出し元で{を読んでから呼ぶ
     fn compound_stmt(&mut self) -> Node {
+        let open = self.get_tok(-1).clone();
         let mut body = Vec::new();
         self.enter_scope();
         while !self.consume("}") {
-            if self.is_typename(&self.tokens[0].clone()) {
-                let mut node = self.declaration();
-                self.add_type(&mut node);
-                body.push(node);
-            } else if self.hequal("typedef") {
-                // typedefはstmtだが、Nodeを返さないという点で特殊なのでここに書いた
-                self.advance(1);
-                let base_ty = self.declspec();
-                let (ty, name, _) = self.declarator(base_ty);
-                let deftype = TypedefType {
-                    name: name.clone(),
-                    ty,
-                };
-                self.push_type(deftype);
-                self.skip(";");
-            } else {
-                let mut stmt = self.stmt();
-                self.add_type(&mut stmt);
-                body.push(stmt);
+            if self.at_eof() {
+                self.error_tok(self.get_tok(0), "expected '}'");
+            }
+            // エラーになった文は捨てて、次の文から解析を続ける
+            let start = self.consumed_tokens.len();
+            let scope_idx = self.get_func().scope_idx;
+            match recover(|| self.block_item()) {
+                Some(Some(node)) => body.push(node),
+                Some(None) => {}
+                None => {
+                    while self.get_func().scope_idx > scope_idx {
+                        self.leave_scope();
+                    }
+                    self.skip_to_sync(start);
+                }
             }
         }
         self.leave_scope();
-        self.new_block(body)
+        let span = self.span_from(&open);
+        self.new_block(body, span)
+    }
+
+    fn block_item(&mut self) -> Option<Node> {
+        if self.is_typename(&self.tokens[0].clone()) {
+            let mut node = self.declaration();
+            self.add_type(&mut node);
+            Some(node)
+        } else if self.hequal("typedef") {
+            self.parse_typedef();
+            None
+        } else {
+            let mut stmt = self.stmt();
+            self.add_type(&mut stmt);
+            Some(stmt)
+        }
     }
 
     fn expr_stmt(&mut self) -> Node {
         if self.hequal(";") {
-            self.advance(1);
-            return self.null_stmt();
+            let tok = self.advance(1);
+            return self.null_stmt(self.tok_span(&tok));
         }
         let expr = self.expr();
         let node = self.new_expr_stmt(expr);
@@ -502,12 +1566,15 @@ impl Ctx<'_> {
     }
 
     fn assign(&mut self) -> Node {
-        let mut node = self.bit();
+        let mut node = self.conditional();
         while !self.tokens.is_empty() {
             match &self.tokens[0].kind {
                 TokenKind::Punct { str } if str == "=" => {
                     self.advance(1);
                     let assign = self.assign();
+                    if let Some(ty) = &node.ty {
+                        self.check_conversion(ty, &assign);
+                    }
                     node = self.new_assign(node, assign);
                 }
                 TokenKind::Punct { str } if str == "+=" => {
@@ -558,12 +1625,35 @@ impl Ctx<'_> {
                     let bit_or = self.new_bit_or(node.clone(), assign);
                     node = self.new_assign(node, bit_or);
                 }
+                TokenKind::Punct { str } if str == "<<=" => {
+                    self.advance(1);
+                    let assign = self.assign();
+                    let shl = self.new_shl(node.clone(), assign);
+                    node = self.new_assign(node, shl);
+                }
+                TokenKind::Punct { str } if str == ">>=" => {
+                    self.advance(1);
+                    let assign = self.assign();
+                    let shr = self.new_shr(node.clone(), assign);
+                    node = self.new_assign(node, shr);
+                }
                 _ => break,
             }
         }
         node
     }
 
+    fn conditional(&mut self) -> Node {
+        let cond = self.bit();
+        if !self.consume("?") {
+            return cond;
+        }
+        let then = self.expr();
+        self.skip(":");
+        let els = self.conditional();
+        self.new_cond(cond, then, els)
+    }
+
     fn bit(&mut self) -> Node {
         let mut node = self.equality();
         while !self.tokens.is_empty() {
@@ -620,28 +1710,48 @@ impl Ctx<'_> {
     }
 
     fn relational(&mut self) -> Node {
-        let mut node = self.add();
+        let mut node = self.shift();
         while !self.tokens.is_empty() {
             match &self.tokens[0].kind {
                 TokenKind::Punct { str } if str == "<" => {
                     self.advance(1);
-                    let add = self.add();
-                    node = self.new_lt(node, add);
+                    let shift = self.shift();
+                    node = self.new_lt(node, shift);
                 }
                 TokenKind::Punct { str } if str == "<=" => {
                     self.advance(1);
-                    let add = self.add();
-                    node = self.new_le(node, add);
+                    let shift = self.shift();
+                    node = self.new_le(node, shift);
                 }
                 TokenKind::Punct { str } if str == ">" => {
                     self.advance(1);
-                    let add = self.add();
-                    node = self.new_gt(node, add);
+                    let shift = self.shift();
+                    node = self.new_gt(node, shift);
                 }
                 TokenKind::Punct { str } if str == ">=" => {
+                    self.advance(1);
+                    let shift = self.shift();
+                    node = self.new_ge(node, shift);
+                }
+                _ => break,
+            }
+        }
+        node
+    }
+
+    fn shift(&mut self) -> Node {
+        let mut node = self.add();
+        while !self.tokens.is_empty() {
+            match &self.tokens[0].kind {
+                TokenKind::Punct { str } if str == "<<" => {
                     self.advance(1);
                     let add = self.add();
-                    node = self.new_ge(node, add);
+                    node = self.new_shl(node, add);
+                }
+                TokenKind::Punct { str } if str == ">>" => {
+                    self.advance(1);
+                    let add = self.add();
+                    node = self.new_shr(node, add);
                 }
                 _ => break,
             }
@@ -670,6 +1780,7 @@ impl Ctx<'_> {
     }
 
     fn new_add(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut lhs = lhs;
         let mut rhs = rhs;
         self.add_type(&mut lhs);
@@ -682,12 +1793,13 @@ impl Ctx<'_> {
                     rhs: Box::new(rhs),
                 },
                 ty: None,
+                span,
             };
             self.add_type(&mut node);
             return node;
         }
         if is_pointer_node(&lhs) && is_pointer_node(&rhs) {
-            self.error_tok(&self.tokens[0], "cannot add pointers to pointers");
+            self.error_span(span, "cannot add pointers to pointers");
         }
         // canonicalize num + ptr -> ptr + num
         if is_integer_node(&lhs) && is_pointer_node(&rhs) {
@@ -696,8 +1808,8 @@ impl Ctx<'_> {
         // ptr + num
         if is_pointer_node(&lhs) && is_integer_node(&rhs) {
             // node.tyのkindのptr_toのsizeを取得してvalに足す
-            let size = get_pointer_or_array_size(&lhs);
-            let num = self.new_long(size as isize);
+            let size = self.pointee_size(&lhs);
+            let num = self.new_long(size as isize, rhs.span);
             let r = self.new_mul(rhs, num);
             let node = Node {
                 kind: NodeKind::Add {
@@ -705,15 +1817,15 @@ impl Ctx<'_> {
                     rhs: Box::new(r),
                 },
                 ty: Some(lhs.ty.clone().unwrap()),
+                span,
             };
             return node;
         }
-        eprintln!("lhs type: {:#?}", lhs.ty); // ここはaが入っていなければならないのに
-        eprintln!("rhs type: {:#?}", rhs.ty);
-        self.error_tok(&self.tokens[0], "invalid operands");
+        self.invalid_operands("+", &lhs, &rhs, span);
     }
 
     fn new_sub(&mut self, lhs: Node, rhs: Node) -> Node {
+        let span = lhs.span.to(rhs.span);
         let mut lhs = lhs;
         let mut rhs = rhs;
         self.add_type(&mut lhs);
@@ -726,14 +1838,15 @@ impl Ctx<'_> {
                     rhs: Box::new(rhs),
                 },
                 ty: None,
+                span,
             };
             self.add_type(&mut node);
             return node;
         }
         // ptr - num
         if is_pointer_node(&lhs) && is_integer_node(&rhs) {
-            let size = get_pointer_or_array_size(&lhs);
-            let num = self.new_num(size as isize);
+            let size = self.pointee_size(&lhs);
+            let num = self.new_num(size as isize, rhs.span);
             let r = self.new_mul(rhs, num);
             let node = Node {
                 kind: NodeKind::Sub {
@@ -741,31 +1854,32 @@ impl Ctx<'_> {
                     rhs: Box::new(r),
                 },
                 ty: Some(lhs.ty.clone().unwrap()),
+                span,
             };
             return node;
         }
-        // ptr - ptr
+        // ptr - ptr。指す先の型が違うと、何個分離れているかが決まらない
         if is_pointer_node(&lhs) && is_pointer_node(&rhs) {
-            let div_size = if let TypeKind::Ptr { ptr_to } = copy_type(&rhs).kind {
-                ptr_to.size
-            } else {
-                8 // とりあえず8
-            };
-            let mut n = Node {
+            if pointee_name(&lhs) != pointee_name(&rhs) {
+                self.invalid_operands("-", &lhs, &rhs, span);
+            }
+            let div_size = self.pointee_size(&rhs);
+            // 差はバイト数の整数として、指す先の大きさで割る
+            let n = Node {
                 kind: NodeKind::Sub {
                     lhs: Box::new(lhs),
                     rhs: Box::new(rhs),
                 },
-                ty: None,
+                ty: Some(new_long_ty()),
+                span,
             };
-            self.add_type(&mut n);
-            let num = self.new_long(div_size as isize);
+            let num = self.new_long(div_size as isize, span);
             let mut node = self.new_div(n, num);
             let ty = new_int_ty();
             node.ty = Some(ty);
             return node;
         }
-        self.error_tok(&self.tokens[0], "invalid operands");
+        self.invalid_operands("-", &lhs, &rhs, span);
     }
 
     fn mul(&mut self) -> Node {
@@ -780,12 +1894,12 @@ impl Ctx<'_> {
                 TokenKind::Punct { str } if str == "/" => {
                     self.advance(1);
                     let cast = self.cast();
-                    return self.new_div(node, cast);
+                    node = self.new_div(node, cast);
                 }
                 TokenKind::Punct { str } if str == "%" => {
                     self.advance(1);
                     let cast = self.cast();
-                    return self.new_mod(node, cast);
+                    node = self.new_mod(node, cast);
                 }
                 _ => break,
             }
@@ -795,11 +1909,12 @@ impl Ctx<'_> {
 
     fn cast(&mut self) -> Node {
         if self.hequal("(") && self.is_typename(&self.tokens[1].clone()) {
-            self.skip("(");
+            let tok = self.skip("(");
             let ty = self.typename();
             self.skip(")");
             let cast = self.cast();
-            let node = self.new_cast(cast, ty);
+            let mut node = self.new_cast(cast, ty);
+            node.span = self.span_from(&tok);
             return node;
         }
         self.unary()
@@ -811,31 +1926,48 @@ impl Ctx<'_> {
             return self.cast();
         }
         if self.hequal("-") {
-            self.advance(1);
+            let tok = self.advance(1);
             let cast = self.cast();
-            return self.new_neg(cast);
+            let span = self.span_from(&tok);
+            return self.new_neg(cast, span);
         }
         if self.hequal("&") {
-            self.advance(1);
+            let tok = self.advance(1);
             let cast = self.cast();
-            return self.new_addr(cast);
+            let span = self.span_from(&tok);
+            return self.new_addr(cast, span);
         }
         if self.hequal("*") {
-            self.advance(1);
+            let tok = self.advance(1);
+            let cast = self.cast();
+            let span = self.span_from(&tok);
+            return self.new_deref(cast, span);
+        }
+        if self.hequal("!") {
+            let tok = self.advance(1);
+            let cast = self.cast();
+            let span = self.span_from(&tok);
+            return self.new_not(cast, span);
+        }
+        if self.hequal("~") {
+            let tok = self.advance(1);
             let cast = self.cast();
-            return self.new_deref(cast, self.tokens[0].clone());
+            let span = self.span_from(&tok);
+            return self.new_bit_not(cast, span);
         }
         if self.hequal("++") {
-            self.advance(1);
+            let tok = self.advance(1);
             let unary = self.unary();
-            let num = self.new_num(1);
+            self.check_lvalue(&unary, "increment operand");
+            let num = self.new_num(1, self.tok_span(&tok));
             let add = self.new_add(unary.clone(), num);
             return self.new_assign(unary, add);
         }
         if self.hequal("--") {
-            self.advance(1);
+            let tok = self.advance(1);
             let unary = self.unary();
-            let num = self.new_num(1);
+            self.check_lvalue(&unary, "decrement operand");
+            let num = self.new_num(1, self.tok_span(&tok));
             let sub = self.new_sub(unary.clone(), num);
             return self.new_assign(unary, sub);
         }
@@ -849,8 +1981,9 @@ impl Ctx<'_> {
                 self.advance(1);
                 let idx = self.expr();
                 self.skip("]");
+                let span = node.span.to(self.tok_span(self.get_tok(-1)));
                 let add = self.new_add(node, idx);
-                node = self.new_deref(add, self.tokens[0].clone());
+                node = self.new_deref(add, span);
                 continue;
             }
             if self.hequal(".") {
@@ -860,7 +1993,8 @@ impl Ctx<'_> {
             }
             if self.hequal("->") {
                 let tok = self.advance(1);
-                node = self.new_deref(node, tok);
+                let span = node.span.to(self.tok_span(&tok));
+                node = self.new_deref(node, span);
                 node = self.struct_ref(node);
                 continue;
             }
@@ -881,23 +2015,32 @@ impl Ctx<'_> {
     fn primary(&mut self) -> Node {
         match &self.tokens[0].kind {
             TokenKind::Num { .. } => {
-                let num = self.get_and_skip_number();
-                self.new_num(num)
+                let (val, ty) = self.get_and_skip_number();
+                let span = self.tok_span(self.get_tok(-1));
+                let mut node = self.new_num(val, span);
+                node.ty = Some(ty);
+                node
             }
+            TokenKind::FNum { .. } => self.error_tok(
+                &self.tokens[0],
+                "floating-point literals are not supported yet",
+            ),
             // gnu statement expression
             TokenKind::Punct { str } if str == "(" && equal(&self.tokens[1].clone(), "{") => {
-                self.advance(2);
+                let tok = self.advance(1);
+                self.advance(1);
                 let compound_stmt = self.compound_stmt();
                 let body = match compound_stmt.kind {
                     NodeKind::Block { body } => body,
                     _ => unreachable!("gnu statement expression body is not block"),
                 };
+                self.skip(")");
                 let mut node = Node {
                     kind: NodeKind::GNUStmtExpr { body },
                     ty: None,
+                    span: self.span_from(&tok),
                 };
                 self.add_type(&mut node);
-                self.skip(")");
                 node
             }
             TokenKind::Punct { str } if str == "(" => {
@@ -907,47 +2050,82 @@ impl Ctx<'_> {
                 node
             }
             TokenKind::Keyword { name } if name == "sizeof" => {
-                self.advance(1);
+                let tok = self.advance(1);
                 // sizeof(type)
                 if self.hequal("(") && self.is_typename(&self.tokens[1].clone()) {
                     self.advance(1);
                     let ty = self.typename();
                     self.skip(")");
-                    return self.new_num(ty.size as isize);
+                    let span = self.span_from(&tok);
+                    return self.new_num(ty.size as isize, span);
                 }
                 // sizeof(ident)
                 let mut node = self.unary();
                 self.add_type(&mut node);
-                self.new_num(copy_type(&node).size as isize)
+                let span = self.span_from(&tok);
+                self.new_num(copy_type(&node).size as isize, span)
+            }
+            TokenKind::Keyword { name } if name == "_Alignof" || name == "alignof" => {
+                let tok = self.advance(1);
+                // _Alignof(type)
+                if self.hequal("(") && self.is_typename(&self.tokens[1].clone()) {
+                    self.advance(1);
+                    let ty = self.typename();
+                    self.skip(")");
+                    let span = self.span_from(&tok);
+                    return self.new_num(ty.align as isize, span);
+                }
+                // GNU拡張で、式のアラインメントも取れる。変数なら_Alignasなどで指定したものになる
+                let mut node = self.unary();
+                self.add_type(&mut node);
+                let span = self.span_from(&tok);
+                if let NodeKind::Var { var } = &node.kind {
+                    let align = var.borrow().align;
+                    return self.new_num(align as isize, span);
+                }
+                self.new_num(copy_type(&node).align as isize, span)
             }
-            TokenKind::Str { str } => {
+            TokenKind::Str { str, ty } => {
+                let (str, ty) = (str.clone(), ty.clone());
+                let tok = self.advance(1);
+                // 中身と要素の型が同じ文字列リテラルは、1つのシンボルにまとめる
+                let elem = string_elem_type(&ty);
+                let same = self.gvars.iter().find(|var| {
+                    let var = var.borrow();
+                    matches!(&var.init_gval, Some(InitGval::Str(s)) if *s == str) && {
+                        let var_elem = string_elem_type(&var.ty);
+                        var_elem.size == elem.size && var_elem.is_unsigned == elem.is_unsigned
+                    }
+                });
+                let span = self.tok_span(&tok);
+                if let Some(var) = same.cloned() {
+                    return self.new_var(var, span);
+                }
                 let name = format!("lC{}", self.gvars.len());
-                let var = self.create_gvar(
-                    name.as_str(),
-                    new_array_ty(new_char_ty(), str.len()),
-                    Some(InitGval::Str(str.clone())),
-                );
-                self.advance(1);
-                var
+                let mut node = self.create_gvar(name.as_str(), ty, Some(InitGval::Str(str)));
+                node.span = span;
+                node
             }
             TokenKind::Ident { name } => {
                 let name = name.clone();
-                self.advance(1);
+                let tok = self.advance(1);
                 let node: Node;
                 // funccall
                 if self.hequal("(") {
-                    node = self.funccall(&name);
+                    node = self.funccall(&name, &tok);
                     return node;
                 }
 
                 // variable
                 // まずローカル変数、グローバル変数から取得しようとし、そこになければenumのメンバを探す
                 if let Some(var) = self.find_var(&name) {
+                    var.borrow_mut().is_used = true;
                     node = Node {
                         kind: NodeKind::Var { var: var.clone() }, // Clone the Rc to increase the reference count
                         ty: Some(copy_var_type(&var)),
+                        span: self.tok_span(&tok),
                     };
-                } else if let Some(n) = self.find_enum_member(&name) {
+                } else if let Some(n) = self.find_enum_member(&name, self.tok_span(&tok)) {
                     node = n;
                 } else {
                     self.error_tok(self.get_tok(-1), "undefined variable");
@@ -964,7 +2142,15 @@ impl Ctx<'_> {
     }
 
     // ここ、関数のtyも返すようにしたいが、自分で定義したものだけで、includeしたものをどうするかわからん
-    fn funccall(&mut self, name: &str) -> Node {
+    // tokは関数名
+    fn funccall(&mut self, name: &str, tok: &Token) -> Node {
+        if !self.functions.contains_key(name) {
+            let msg = format!("implicit declaration of function '{}'", name);
+            self.warn(
+                Warning::ImplicitFunctionDeclaration,
+                Diagnostic::warning(self.tok_span(tok), &msg),
+            );
+        }
         self.advance(1);
         let mut args = Vec::new();
         while !self.consume(")") {
@@ -984,6 +2170,7 @@ impl Ctx<'_> {
                 name: name.to_string(),
                 args,
             },
+            span: self.span_from(tok),
             ty: Some(new_long_ty()), // 自分で定義するようになったら、また変数リストから、型を取り出して入れる。includeの場合はどうする？足し算とかできないよな。まあ後で考えるか。一旦chibiccにならってlong
         }
     }
@@ -993,11 +2180,17 @@ impl Ctx<'_> {
 // main process
 //
 impl Ctx<'_> {
-    pub fn new_func(&mut self, name: &str, ty: Type) {
+    // 定義の後に宣言だけが来た場合は、前の定義を返す。呼び出し側で関数の検査を終えてから戻す
+    pub fn new_func(&mut self, name: &str, ty: Type, attr: VarAttr) -> Option<Function> {
         // 関数の場合
+        // static int f(); と宣言された関数は、staticなしで定義してもstaticのまま
+        let is_static = attr.is_static || self.functions.get(name).is_some_and(|f| f.is_static);
+        let prev = self.functions.remove(name).filter(|f| f.is_def);
         // これから処理する関数名をセット。create_lvar, find_varで使用
         self.cur_func = name.to_string();
-        let func = self.create_func(name, ty);
+        let mut func = self.create_func(name, ty);
+        func.is_static = is_static;
+        let span = func.span;
         self.functions.insert(name.to_string(), func);
 
         self.enter_scope();
@@ -1014,28 +2207,51 @@ impl Ctx<'_> {
         if self.consume(";") {
             let func = self.get_func();
             func.is_def = false;
-            return;
+            return prev;
+        }
+
+        // 本体が2つあればエラー。後の本体も解析は続ける
+        if let Some(prev) = prev {
+            self.report(
+                Diagnostic::error(Some(span), &format!("redefinition of '{}'", name))
+                    .with_note(prev.span, "previous definition is here"),
+            );
         }
 
         self.skip("{");
 
         // 関数の中身の処理
         self.functions.get_mut(name).unwrap().body = Some(self.compound_stmt());
+        None
     }
 
     fn get_func(&mut self) -> &mut Function {
-        return self.functions.get_mut(&self.cur_func).unwrap();
+        self.functions.get_mut(&self.cur_func).unwrap()
+    }
+
+    // 現在のスコープ。関数の外ではファイルスコープになる
+    fn cur_scope(&mut self) -> &mut Scope {
+        if self.cur_func.is_empty() {
+            return &mut self.global_scope;
+        }
+        let func = self.get_func();
+        &mut func.scopes[func.scope_idx as usize]
+    }
+
+    // 内側のスコープから順に並べ、最後にファイルスコープを加えたもの。名前の探索に使う
+    pub fn visible_scopes(&self) -> Vec<&Scope> {
+        let mut scopes = Vec::new();
+        if let Some(func) = self.functions.get(&self.cur_func) {
+            scopes.extend(func.scopes.iter().rev());
+        }
+        scopes.push(&self.global_scope);
+        scopes
     }
 
     pub fn enter_scope(&mut self) {
         let func = self.get_func();
 
-        func.scopes.push(Scope {
-            variables: Vec::new(),
-            tags: Vec::new(),
-            types: Vec::new(),
-            enums: Vec::new(),
-        });
+        func.scopes.push(Scope::default());
         func.scope_idx += 1;
     }
 
@@ -1046,32 +2262,221 @@ impl Ctx<'_> {
         func.scope_idx -= 1;
     }
 
-    pub fn new_gvar(&mut self, name: &str, base_ty: Type, ty: Type) {
-        self.create_gvar(name, ty, None);
+    pub fn new_gvar(&mut self, name: &str, base_ty: Type, ty: Type, attr: VarAttr) {
+        self.gvar_declarator(name, ty, attr);
         while self.consume(",") {
             let (ty, name, _) = self.declarator(base_ty.clone()); // なぜclone
-            self.create_gvar(name.as_str(), ty, None);
+            self.gvar_declarator(name.as_str(), ty, attr);
         }
         self.skip(";");
     }
 
+    fn gvar_declarator(&mut self, name: &str, ty: Type, attr: VarAttr) {
+        let mut attr = attr;
+        attr.align = cmp::max(attr.align, self.attribute_list().1);
+        let var = self.declare_gvar(name, ty, attr);
+        if self.consume("=") {
+            if attr.is_extern {
+                self.error_tok(
+                    self.get_tok(-1),
+                    "extern variable cannot have an initializer",
+                );
+            }
+            self.gvar_initializer(var);
+        }
+    }
+
+    // グローバル変数を宣言する。同じ変数の宣言が複数ある場合は既存の変数を使う
+    // extern int x; は宣言だけ、int x; は仮定義(tentative definition)、int x = 1; は定義
+    // 仮定義はいくつあってもよく、定義があればそちらが優先される。定義が2つあるとエラー
+    fn declare_gvar(&mut self, name: &str, ty: Type, attr: VarAttr) -> Rc<RefCell<Var>> {
+        let is_tentative = !attr.is_extern && !self.hequal("=");
+        let found = self
+            .gvars
+            .iter()
+            .find(|var| var.borrow().asm_name == name)
+            .cloned();
+        if let Some(var) = found {
+            if attr.is_extern {
+                return var;
+            }
+            let (is_definition, was_tentative) = {
+                let var = var.borrow();
+                (var.is_definition, var.is_tentative)
+            };
+            let span = self.name_span(name);
+            if is_definition && !was_tentative && !is_tentative {
+                let prev = var.borrow().span;
+                self.error_diag(
+                    Diagnostic::error(Some(span), &format!("redefinition of '{}'", name))
+                        .with_note(prev, "previous definition is here"),
+                );
+            }
+            // staticかどうかは最初の宣言で決まる。食い違えばエラーにして、最初の宣言に従う
+            let (was_static, prev) = {
+                let var = var.borrow();
+                (var.is_static, var.span)
+            };
+            if was_static != attr.is_static {
+                let msg = if was_static {
+                    format!(
+                        "non-static declaration of '{}' follows static declaration",
+                        name
+                    )
+                } else {
+                    format!(
+                        "static declaration of '{}' follows non-static declaration",
+                        name
+                    )
+                };
+                self.report(
+                    Diagnostic::error(Some(span), &msg)
+                        .with_note(prev, "previous declaration is here"),
+                );
+            }
+            let mut v = var.borrow_mut();
+            // 初期化子のある定義の位置を覚えておく
+            if !is_tentative {
+                v.span = span;
+            }
+            // int x[]; int x[3]; のように、後の宣言で型が完成することがある
+            if !is_definition || !is_tentative || v.ty.size < ty.size {
+                v.align = cmp::max(v.align, ty.align);
+                v.ty = ty;
+            }
+            if !is_definition || !is_tentative {
+                v.is_tentative = is_tentative;
+            }
+            v.is_definition = true;
+            v.align = cmp::max(v.align, attr.align);
+            drop(v);
+            return var;
+        }
+
+        let node = self.create_gvar(name, ty, None);
+        let NodeKind::Var { var } = node.kind else {
+            unreachable!("create_gvar must return a variable node");
+        };
+        let mut v = var.borrow_mut();
+        v.is_static = attr.is_static;
+        v.is_definition = !attr.is_extern;
+        v.align = cmp::max(v.align, attr.align);
+        v.is_tentative = is_tentative;
+        drop(v);
+        var
+    }
+
+    // 関数内のstatic変数。名前の探索は関数のスコープで行うが、実体はグローバル変数として確保する
+    fn create_static_lvar(&mut self, name: &str, ty: Type) -> Rc<RefCell<Var>> {
+        let asm_name = format!("{}.{}.{}", self.cur_func, name, self.gvars.len());
+        let node = self.create_gvar(asm_name.as_str(), ty, None);
+        let NodeKind::Var { var } = node.kind else {
+            unreachable!("create_gvar must return a variable node");
+        };
+        let span = self.name_span(name);
+        if !self.check_redefinition(name, span, false) {
+            self.check_shadow(name, span);
+        }
+        {
+            let mut var = var.borrow_mut();
+            var.name = name.to_string();
+            var.is_static = true;
+            var.span = span;
+        }
+        self.cur_scope().variables.push(var.clone());
+        var
+    }
+
+    // 関数内のextern宣言。名前の探索は関数のスコープで行い、実体は同じ名前のグローバル変数を指す
+    fn declare_extern_lvar(&mut self, name: &str, var: Rc<RefCell<Var>>) {
+        if self
+            .cur_scope()
+            .variables
+            .iter()
+            .any(|v| Rc::ptr_eq(v, &var))
+        {
+            return;
+        }
+        let span = self.name_span(name);
+        if !self.check_redefinition(name, span, false) {
+            self.check_shadow(name, span);
+        }
+        self.cur_scope().variables.push(var);
+    }
+
+    fn gvar_initializer(&mut self, var: Rc<RefCell<Var>>) {
+        let ty = copy_var_type(&var);
+        let init = self.initializer(ty);
+        let mut bytes = vec![0; init.ty.size];
+        let mut relocs = Vec::new();
+        self.write_gvar_data(&init, &mut bytes, &mut relocs, 0);
+        let mut var = var.borrow_mut();
+        var.ty = init.ty;
+        var.init_gval = Some(InitGval::Data { bytes, relocs });
+    }
+
     pub fn parse(&mut self) {
         self.tokens = self.tokenize();
         self.convert_keywords();
 
         // グローバル変数の定義文をwhileで回す
-        while !self.tokens.is_empty() {
-            let base_ty = self.declspec();
-            let (ty, name, is_func) = self.declarator(base_ty.clone());
+        while !self.at_eof() {
+            let start = self.consumed_tokens.len();
+            if recover(|| self.top_level_decl()).is_none() {
+                // 関数の途中でエラーになっていれば、関数の外に戻す
+                self.cur_func = String::new();
+                self.skip_to_sync(start);
+                // ファイルスコープに余分な}があっても先に進めるように
+                if self.consumed_tokens.len() == start && !self.at_eof() {
+                    self.advance(1);
+                }
+            }
+        }
+        self.check_incomplete_gvars();
+    }
 
-            // 関数ではない場合
-            if !is_func {
-                self.new_gvar(name.as_str(), base_ty, ty);
-                continue;
+    // struct S x; struct S { ... }; のように、グローバル変数の型は後から完成してもよい
+    // ファイルの最後まで不完全なままの定義は、大きさがわからないので確保できない
+    fn check_incomplete_gvars(&self) {
+        for var in &self.gvars {
+            let var = var.borrow();
+            if var.is_definition && self.is_incomplete_struct(&var.ty) {
+                let msg = format!("storage size of '{}' isn't known", var.name);
+                self.report(Diagnostic::error(Some(var.span), &msg));
             }
-            // 関数の場合
-            self.new_func(name.as_str(), ty);
-            self.leave_scope();
+        }
+    }
+
+    fn top_level_decl(&mut self) {
+        if self.hequal("typedef") {
+            self.parse_typedef();
+            return;
+        }
+
+        let (attr, base_ty) = self.declspec_attr();
+        // struct point { int x; int y; }; のように、型の宣言のみの場合
+        if self.consume(";") {
+            return;
+        }
+        let (ty, name, is_func) = self.declarator(base_ty.clone());
+
+        // 関数ではない場合
+        if !is_func {
+            self.new_gvar(name.as_str(), base_ty, ty, attr);
+            return;
+        }
+        // 関数の場合
+        let prev = self.new_func(name.as_str(), ty, attr);
+        self.leave_scope();
+        // 関数の外に戻ったので、以降の宣言はファイルスコープに入る
+        self.cur_func = String::new();
+        let func = &self.functions[&name];
+        if let Some(body) = func.body.as_ref().filter(|_| func.is_def) {
+            self.check_unused(func);
+            self.check_return(&name, body);
+        }
+        if let Some(prev) = prev {
+            self.functions.insert(name, prev);
         }
     }
 }
@@ -1082,37 +2487,64 @@ impl Ctx<'_> {
     fn create_gvar(&mut self, name: &str, ty: Type, init_gval: Option<InitGval>) -> Node {
         let var = Rc::new(RefCell::new(Var {
             name: name.to_string(),
-            offset: self.gvars.len(), // Offset will be calculated later // あとで方を実装した際、そのsizeなりによって変更すべき。ここでやるか、codegenでやるかはあとで
+            asm_name: name.to_string(),
             ty: ty.clone(),
+            align: ty.align,
             is_param: false,
             is_local: false,
+            is_static: false,
+            is_definition: true,
+            is_tentative: false,
             init_gval,
+            span: self.name_span(name),
+            is_used: false,
         }));
 
         self.gvars.push(var.clone());
-        self.new_var(var)
+        let span = var.borrow().span;
+        self.new_var(var, span)
+    }
+
+    // 宣言した名前の位置。declaratorは名前しか返さないので、読んだトークンから探す
+    pub fn name_span(&self, name: &str) -> Span {
+        let tok = self
+            .consumed_tokens
+            .iter()
+            .rev()
+            .find(|tok| matches!(&tok.kind, TokenKind::Ident { name: n } if n == name))
+            .unwrap_or(self.get_tok(-1));
+        self.tok_span(tok)
     }
 
     // 関数定義用の
     fn create_lvar(&mut self, name: &str, ty: Type, is_def_arg: bool) -> Node {
-        // eprintln!("呼ばれた: {:#?}", &self.tokens[0]);
-        let function = self.get_func();
-
         let var = Rc::new(RefCell::new(Var {
             name: name.to_string(),
-            offset: 0, // Offset will be calculated later // あとで方を実装した際、そのsizeなりによって変更すべき。ここでやるか、codegenでやるかはあとで
-            // ここcodegenで改めてoffsetを割り当てているから意味ない説。グローバル変数はまた別で使えるかも。名前とか
+            asm_name: name.to_string(),
             ty: ty.clone(),
+            align: ty.align,
             is_param: is_def_arg,
             is_local: true,
+            is_static: false,
+            is_definition: true,
+            is_tentative: false,
             init_gval: None,
+            span: self.name_span(name),
+            is_used: false,
         }));
+        let redefined = self.check_redefinition(name, var.borrow().span, is_def_arg);
+        // 引数は宣言だけの場合もあるので、影になるかどうかは見ない
+        if !is_def_arg && !redefined {
+            self.check_shadow(name, var.borrow().span);
+        }
 
+        let function = self.get_func();
         function.scopes[function.scope_idx as usize]
             .variables
             .push(var.clone()); // コードがひどいが、まあ想定ではここが-になることはないはず
 
-        let mut node = self.new_var(var);
+        let span = self.name_span(name);
+        let mut node = self.new_var(var, span);
         // 関数定義の引数の場合、関数のargsにも追加
         if is_def_arg {
             let func = self.functions.get_mut(&self.cur_func).unwrap();
@@ -1129,7 +2561,7 @@ impl Ctx<'_> {
     }
 
     pub fn find_var(&mut self, name: &str) -> Option<Rc<RefCell<Var>>> {
-        for scope in self.get_func().scopes.iter().rev() {
+        for scope in self.visible_scopes() {
             for var in scope.variables.iter() {
                 if var.borrow().name == name {
                     return Some(var.clone());
@@ -1146,9 +2578,13 @@ impl Ctx<'_> {
     }
 
     // type
+    // 変数とtypedef名は同じ名前空間なので、内側のスコープで宣言された変数があれば型名ではない
+    // 同じスコープにある場合は、t t = 1; のように後から宣言した変数の方を優先する
     fn find_type(&mut self, name: String) -> Option<Type> {
-        let func = self.get_func();
-        for scope in func.scopes.iter().rev() {
+        for scope in self.visible_scopes() {
+            if scope.variables.iter().any(|var| var.borrow().name == name) {
+                return None;
+            }
             for deftype in &scope.types {
                 if deftype.name == name {
                     return Some(deftype.ty.clone());
@@ -1169,7 +2605,18 @@ impl Ctx<'_> {
             TokenKind::Keyword { name } => {
                 matches!(
                     name.as_str(),
-                    "int" | "short" | "long" | "char" | "struct" | "union" | "enum"
+                    "int"
+                        | "short"
+                        | "long"
+                        | "char"
+                        | "struct"
+                        | "union"
+                        | "enum"
+                        | "static"
+                        | "extern"
+                        | "_Alignas"
+                        | "signed"
+                        | "unsigned"
                 )
             }
             TokenKind::Ident { name } => self.find_type(name.to_string()).is_some(),
@@ -1187,15 +2634,4 @@ impl Ctx<'_> {
         self.advance(1);
         n
     }
-
-    fn get_num(&mut self) -> isize {
-        let n: isize;
-        if let TokenKind::Num { val } = &self.tokens[0].kind {
-            n = *val;
-        } else {
-            self.error_tok(&self.tokens[0], "expected number");
-        }
-        self.advance(1);
-        n
-    }
 }
@@ -0,0 +1,336 @@
+// レジスタ割り当て。codegenの前に関数ごとに行う
+// 1. アドレスを取られていないスカラーのローカル変数(Slot)を、仮想レジスタに置き換える
+// 2. 生存区間を求めて、線形スキャンで仮想レジスタに実際のレジスタを割り当てる。足りなければスタックに置く(スピル)
+// x0-x7は引数と返り値、x16, x17とx0-x2はcodegenの作業用、x18はプラットフォーム予約なので使わない
+use std::collections::HashMap;
+
+use crate::ir::*;
+
+// 関数呼び出しで壊れるレジスタ。呼び出しをまたがない値に使う
+const CALLER_SAVED: [u8; 7] = [9, 10, 11, 12, 13, 14, 15];
+// 関数呼び出しで保存されるレジスタ。使ったものはプロローグで保存し、エピローグで戻す
+const CALLEE_SAVED: [u8; 10] = [19, 20, 21, 22, 23, 24, 25, 26, 27, 28];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loc {
+    Reg(u8),      // x{n}
+    Stack(usize), // n番目のスピル領域
+}
+
+#[derive(Default)]
+pub struct Allocation {
+    pub locs: Vec<Loc>,        // 仮想レジスタごとの置き場所
+    pub callee_saved: Vec<u8>, // 使ったcallee-savedレジスタ
+    pub nspills: usize,        // スピル領域の数。1つ8byte
+}
+
+// 値を読み書きするのがLoad, Storeのアドレスとしてだけで、型も変数と同じなら、変数を仮想レジスタに置き換えられる
+// 置き換えた変数のSlotは取り除く
+pub fn promote_slots(func: &mut IrFunction) {
+    // SlotAddrの結果を入れた仮想レジスタと、そのSlot
+    let mut addr_slot: HashMap<Reg, usize> = HashMap::new();
+    for inst in func.blocks.iter().flat_map(|block| &block.insts) {
+        if let Inst::SlotAddr { dst, slot } = inst {
+            addr_slot.insert(*dst, *slot);
+        }
+    }
+    let mut promotable: Vec<bool> = func.slots.iter().map(|slot| slot.ty.is_some()).collect();
+    for inst in func.blocks.iter().flat_map(|block| &block.insts) {
+        let slot_of = |reg: &Reg| addr_slot.get(reg).copied();
+        match inst {
+            Inst::SlotAddr { .. } => {}
+            Inst::Load { addr, ty, .. } => {
+                if let Some(slot) = slot_of(addr) {
+                    promotable[slot] &= func.slots[slot].ty == Some(*ty);
+                }
+            }
+            Inst::Store { addr, src, size } => {
+                if let Some(slot) = slot_of(addr) {
+                    promotable[slot] &= func.slots[slot].ty.map(|ty| ty.size) == Some(*size);
+                }
+                if let Some(slot) = slot_of(src) {
+                    promotable[slot] = false;
+                }
+            }
+            // アドレスをそれ以外に使っていれば、ポインタ経由で読み書きされるかもしれない
+            _ => {
+                for reg in inst.uses() {
+                    if let Some(slot) = slot_of(&reg) {
+                        promotable[slot] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    // 置き換える変数に仮想レジスタを、残す変数に新しいSlotの番号を割り振る
+    let tys: Vec<Option<IntTy>> = func.slots.iter().map(|slot| slot.ty).collect();
+    let mut var_reg: Vec<Option<Reg>> = Vec::new();
+    let mut new_index: Vec<usize> = Vec::new();
+    let mut slots = Vec::new();
+    for (slot, promote) in func.slots.drain(..).zip(&promotable) {
+        if *promote {
+            var_reg.push(Some(Reg(func.nregs)));
+            func.nregs += 1;
+            new_index.push(0);
+        } else {
+            var_reg.push(None);
+            new_index.push(slots.len());
+            slots.push(slot);
+        }
+    }
+    func.slots = slots;
+
+    let promoted = |reg: &Reg| {
+        addr_slot
+            .get(reg)
+            .and_then(|slot| var_reg[*slot].map(|var| (var, tys[*slot])))
+    };
+    for block in &mut func.blocks {
+        let mut insts = Vec::new();
+        for inst in block.insts.drain(..) {
+            match inst {
+                Inst::SlotAddr { dst, slot } => {
+                    if var_reg[slot].is_none() {
+                        insts.push(Inst::SlotAddr {
+                            dst,
+                            slot: new_index[slot],
+                        });
+                    }
+                }
+                Inst::Load { dst, addr, .. } if promoted(&addr).is_some() => {
+                    let (var, _) = promoted(&addr).unwrap();
+                    insts.push(Inst::Mov { dst, src: var });
+                }
+                // 変数に書き込む値は、変数の型に切り詰めておく
+                Inst::Store { addr, src, .. } if promoted(&addr).is_some() => {
+                    let (var, ty) = promoted(&addr).unwrap();
+                    match ty {
+                        Some(ty) if ty.size < 8 => insts.push(Inst::Ext { dst: var, src, ty }),
+                        _ => insts.push(Inst::Mov { dst: var, src }),
+                    }
+                }
+                inst => insts.push(inst),
+            }
+        }
+        block.insts = insts;
+    }
+}
+
+// 生存区間。命令の番号(関数内の通し番号)の2倍+1を位置とし、ブロックの入口はその1つ前の偶数にする
+// 入口で生きている値が、最初の命令で読み終わった値とレジスタを共有しないようにするため。途中の穴は考えない
+struct Interval {
+    reg: Reg,
+    start: usize,
+    end: usize,
+    across_call: bool, // 関数呼び出しの後にも値を使う
+}
+
+// 仮想レジスタの集合。仮想レジスタの番号をビットの位置にする
+#[derive(Clone, PartialEq)]
+struct RegSet(Vec<u64>);
+
+impl RegSet {
+    fn new(nregs: usize) -> Self {
+        RegSet(vec![0; nregs.div_ceil(64)])
+    }
+
+    fn insert(&mut self, reg: Reg) {
+        self.0[reg.0 / 64] |= 1 << (reg.0 % 64);
+    }
+
+    fn remove(&mut self, reg: Reg) {
+        self.0[reg.0 / 64] &= !(1 << (reg.0 % 64));
+    }
+
+    fn union(&mut self, other: &RegSet) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a |= b;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Reg> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| Reg(i * 64 + bit))
+        })
+    }
+}
+
+// ブロックの入口で生きている(その先で値が読まれる)仮想レジスタ
+fn live_in(func: &IrFunction) -> Vec<RegSet> {
+    let n = func.blocks.len();
+    // ブロック内で書き込む前に読むもの(gen)と、書き込むもの(kill)
+    let mut gens = vec![RegSet::new(func.nregs); n];
+    let mut kills = vec![RegSet::new(func.nregs); n];
+    for (i, block) in func.blocks.iter().enumerate() {
+        for inst in block.insts.iter().rev() {
+            if let Some(dst) = inst.def() {
+                kills[i].insert(dst);
+                gens[i].remove(dst);
+            }
+            for reg in inst.uses() {
+                gens[i].insert(reg);
+            }
+        }
+    }
+    // 変わらなくなるまで、後ろのブロックから伝える
+    let mut live_in = gens.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..n).rev() {
+            let mut live = live_out(func, &live_in, i);
+            for (word, kill) in live.0.iter_mut().zip(&kills[i].0) {
+                *word &= !kill;
+            }
+            live.union(&gens[i]);
+            if live != live_in[i] {
+                live_in[i] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+fn live_out(func: &IrFunction, live_in: &[RegSet], block: BlockId) -> RegSet {
+    let mut live = RegSet::new(func.nregs);
+    if let Some(last) = func.blocks[block].insts.last() {
+        for succ in last.successors() {
+            live.union(&live_in[succ]);
+        }
+    }
+    live
+}
+
+fn intervals(func: &IrFunction) -> Vec<Interval> {
+    let live_in = live_in(func);
+    let mut range: Vec<Option<(usize, usize)>> = vec![None; func.nregs];
+    let mut extend = |reg: Reg, pos: usize| {
+        let r = range[reg.0].get_or_insert((pos, pos));
+        r.0 = r.0.min(pos);
+        r.1 = r.1.max(pos);
+    };
+    let mut across_call = vec![false; func.nregs];
+    let mut pos = 1;
+    for (i, block) in func.blocks.iter().enumerate() {
+        if block.insts.is_empty() {
+            continue;
+        }
+        let (entry, last) = (pos - 1, pos + 2 * (block.insts.len() - 1));
+        for reg in live_in[i].iter() {
+            extend(reg, entry);
+        }
+        let mut live = live_out(func, &live_in, i);
+        for reg in live.iter() {
+            extend(reg, last);
+        }
+        for inst in &block.insts {
+            if let Some(dst) = inst.def() {
+                extend(dst, pos);
+            }
+            for reg in inst.uses() {
+                extend(reg, pos);
+            }
+            pos += 2;
+        }
+        // 後ろから生きている値をたどり、呼び出しの後で生きているものに印を付ける
+        for inst in block.insts.iter().rev() {
+            if let Some(dst) = inst.def() {
+                live.remove(dst);
+            }
+            if let Inst::Call { .. } = inst {
+                for reg in live.iter() {
+                    across_call[reg.0] = true;
+                }
+            }
+            for reg in inst.uses() {
+                live.insert(reg);
+            }
+        }
+    }
+    range
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, r)| {
+            let (start, end) = r?;
+            Some(Interval {
+                reg: Reg(i),
+                start,
+                end,
+                across_call: across_call[i],
+            })
+        })
+        .collect()
+}
+
+// 線形スキャン。区間を始まりの順に見て、空いているレジスタを割り当てる
+// 空きがなければ、今の区間と使用中の区間のうち、最も遠くまで続くものをスピルする
+pub fn allocate(func: &IrFunction) -> Allocation {
+    let mut intervals = intervals(func);
+    intervals.sort_by_key(|interval| interval.start);
+    let mut locs = vec![Loc::Stack(0); func.nregs];
+    let mut nspills = 0;
+    let mut spill = |locs: &mut Vec<Loc>, reg: Reg| {
+        locs[reg.0] = Loc::Stack(nspills);
+        nspills += 1;
+    };
+    let mut free: Vec<u8> = CALLEE_SAVED
+        .iter()
+        .chain(&CALLER_SAVED)
+        .rev()
+        .copied()
+        .collect();
+    let mut active: Vec<(usize, Reg, u8)> = Vec::new(); // (end, 仮想レジスタ, 割り当てたレジスタ)
+    let mut callee_saved = Vec::new();
+
+    for cur in &intervals {
+        // 区間の終わりの命令では、値を読んでから結果を書くので、同じレジスタを使ってよい
+        active.retain(|&(end, _, phys)| {
+            if end <= cur.start {
+                free.push(phys);
+            }
+            end > cur.start
+        });
+        // 呼び出しをまたぐ値はcallee-savedに、それ以外はなるべくcaller-savedに置く
+        let usable = |phys: &u8| !cur.across_call || CALLEE_SAVED.contains(phys);
+        let pick = free
+            .iter()
+            .rposition(|phys| CALLER_SAVED.contains(phys) && usable(phys))
+            .or_else(|| free.iter().rposition(usable));
+        if let Some(i) = pick {
+            let phys = free.remove(i);
+            locs[cur.reg.0] = Loc::Reg(phys);
+            active.push((cur.end, cur.reg, phys));
+        } else {
+            let victim = active
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, _, phys))| usable(phys))
+                .max_by_key(|(_, (end, _, _))| *end)
+                .map(|(i, _)| i);
+            match victim {
+                Some(i) if active[i].0 > cur.end => {
+                    let (_, reg, phys) = active.remove(i);
+                    spill(&mut locs, reg);
+                    locs[cur.reg.0] = Loc::Reg(phys);
+                    active.push((cur.end, cur.reg, phys));
+                }
+                _ => spill(&mut locs, cur.reg),
+            }
+        }
+        if let Loc::Reg(phys) = locs[cur.reg.0] {
+            if CALLEE_SAVED.contains(&phys) && !callee_saved.contains(&phys) {
+                callee_saved.push(phys);
+            }
+        }
+    }
+    callee_saved.sort();
+    Allocation {
+        locs,
+        callee_saved,
+        nspills,
+    }
+}
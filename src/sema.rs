@@ -0,0 +1,204 @@
+// 意味の検査。構文としては正しくても、Cとしては意味をなさないものをエラーにする
+// 式の検査はadd_typeから、宣言の検査はcreate_lvarなどから呼ぶ。メッセージはgccに合わせる
+use crate::type_utils::*;
+use crate::types::*;
+
+impl Ctx<'_> {
+    // 同じスコープに同じ名前の変数があればエラー。関数本体の一番外側のブロックは、引数と同じスコープとみなす
+    // 2つ目の宣言を捨てても得るものはないので、解析は打ち切らずに続ける。エラーにしたらtrue
+    pub fn check_redefinition(&self, name: &str, span: Span, is_param: bool) -> bool {
+        let Some(func) = self.functions.get(&self.cur_func) else {
+            return false;
+        };
+        let Some(cur_scope) = func.scopes.last() else {
+            return false;
+        };
+        let mut scopes = vec![cur_scope];
+        if func.scopes.len() == 2 {
+            scopes.push(&func.scopes[0]);
+        }
+        let Some(prev) = scopes
+            .iter()
+            .flat_map(|scope| scope.variables.iter())
+            .find(|var| var.borrow().name == name)
+        else {
+            return false;
+        };
+        let msg = if is_param {
+            format!("redefinition of parameter '{}'", name)
+        } else {
+            format!("redefinition of '{}'", name)
+        };
+        self.report(
+            Diagnostic::error(Some(span), &msg)
+                .with_note(prev.borrow().span, "previous definition is here"),
+        );
+        true
+    }
+
+    // 代入や&、++の対象になれるか。whatは「lvalue required as ...」に続く、どこで必要なのかの説明
+    pub fn check_lvalue(&self, node: &Node, what: &str) {
+        if !is_lvalue(node) {
+            self.error_node(node, &format!("lvalue required as {}", what));
+        }
+    }
+
+    pub fn check_assign(&self, lhs: &Node, rhs: &Node, span: Span) {
+        self.check_lvalue(lhs, "left operand of assignment");
+        let (Some(to), Some(from)) = (&lhs.ty, &rhs.ty) else {
+            return;
+        };
+        if matches!(to.kind, TypeKind::Array { .. }) {
+            self.error_node(lhs, "assignment to expression with array type");
+        }
+        // 構造体は同じ型同士でしか代入できず、スカラーとも混ぜられない
+        // Cでは別々に宣言した構造体は別の型だが、chibiccと同じくメンバーが同じなら代入できることにしておく
+        let compatible = match (&to.kind, &from.kind) {
+            (
+                TypeKind::Struct {
+                    id: a, members: m1, ..
+                },
+                TypeKind::Struct {
+                    id: b, members: m2, ..
+                },
+            )
+            | (
+                TypeKind::Union {
+                    id: a, members: m1, ..
+                },
+                TypeKind::Union {
+                    id: b, members: m2, ..
+                },
+            ) => a == b || same_members(m1, m2),
+            _ => is_scalar(to) && is_scalar(from),
+        };
+        if !compatible {
+            self.error_span(
+                span,
+                &format!(
+                    "incompatible types when assigning to type '{}' from type '{}'",
+                    type_name(to),
+                    type_name(from)
+                ),
+            );
+        }
+    }
+
+    pub fn check_addr(&self, lhs: &Node) {
+        if let NodeKind::Member { member, .. } = &lhs.kind {
+            if member.is_bitfield {
+                self.error_node(
+                    lhs,
+                    &format!("cannot take address of bit-field '{}'", member.name),
+                );
+            }
+        }
+        self.check_lvalue(lhs, "unary '&' operand");
+    }
+
+    // *, /, %, &, ^, |, <<, >> は整数同士でしか使えない
+    pub fn check_integer_operands(&self, op: &str, lhs: &Node, rhs: &Node, span: Span) {
+        if !is_integer_node(lhs) || !is_integer_node(rhs) {
+            self.invalid_operands(op, lhs, rhs, span);
+        }
+    }
+
+    // 比較はスカラー同士なら何でもよい
+    pub fn check_scalar_operands(&self, op: &str, lhs: &Node, rhs: &Node, span: Span) {
+        if !is_scalar_node(lhs) || !is_scalar_node(rhs) {
+            self.invalid_operands(op, lhs, rhs, span);
+        }
+    }
+
+    pub fn invalid_operands(&self, op: &str, lhs: &Node, rhs: &Node, span: Span) -> ! {
+        let name = |node: &Node| node.ty.as_ref().map_or("?".to_string(), type_name);
+        self.error_span(
+            span,
+            &format!(
+                "invalid operands to binary {} (have '{}' and '{}')",
+                op,
+                name(lhs),
+                name(rhs)
+            ),
+        );
+    }
+
+    // 単項の-, ~は整数、!はスカラーにしか使えない
+    pub fn check_unary_operand(&self, op: &str, lhs: &Node, span: Span) {
+        let (ok, what) = match op {
+            "-" => (is_integer_node(lhs), "unary minus"),
+            "~" => (is_integer_node(lhs), "bit-complement"),
+            _ => (is_scalar_node(lhs), "unary exclamation mark"),
+        };
+        if !ok {
+            self.error_span(span, &format!("wrong type argument to {}", what));
+        }
+    }
+
+    // if, while, for, ?:の条件と、&&, ||の被演算子は、0と比べられるスカラーでなければならない
+    pub fn check_condition(&self, cond: &Node) {
+        let Some(ty) = &cond.ty else {
+            return;
+        };
+        if is_scalar(ty) {
+            return;
+        }
+        let kind = match ty.kind {
+            TypeKind::Union { .. } => "union",
+            _ => "struct",
+        };
+        self.error_node(
+            cond,
+            &format!("used {} type value where scalar is required", kind),
+        );
+    }
+}
+
+// アドレスを持つ式か。codegenのgen_addrで扱えるもの
+fn is_lvalue(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::Var { .. } | NodeKind::Deref { .. } => true,
+        NodeKind::Member { lhs, .. } => is_lvalue(lhs),
+        _ => false,
+    }
+}
+
+fn same_members(m1: &[Member], m2: &[Member]) -> bool {
+    m1.len() == m2.len()
+        && m1.iter().zip(m2).all(|(a, b)| {
+            a.name == b.name && a.offset == b.offset && type_name(&a.ty) == type_name(&b.ty)
+        })
+}
+
+fn is_scalar(ty: &Type) -> bool {
+    is_integer(ty) || matches!(ty.kind, TypeKind::Ptr { .. } | TypeKind::Array { .. })
+}
+
+fn is_scalar_node(node: &Node) -> bool {
+    node.ty.as_ref().is_some_and(is_scalar)
+}
+
+// エラーメッセージに出す、演算子の記号
+pub fn operator(kind: &NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Add { .. } => "+",
+        NodeKind::Sub { .. } | NodeKind::Neg { .. } => "-",
+        NodeKind::BitNot { .. } => "~",
+        NodeKind::Not { .. } => "!",
+        NodeKind::Mul { .. } => "*",
+        NodeKind::Div { .. } => "/",
+        NodeKind::Mod { .. } => "%",
+        NodeKind::BitAnd { .. } => "&",
+        NodeKind::BitXor { .. } => "^",
+        NodeKind::BitOr { .. } => "|",
+        NodeKind::Shl { .. } => "<<",
+        NodeKind::Shr { .. } => ">>",
+        NodeKind::Eq { .. } => "==",
+        NodeKind::Ne { .. } => "!=",
+        NodeKind::Lt { .. } => "<",
+        NodeKind::Le { .. } => "<=",
+        NodeKind::Gt { .. } => ">",
+        NodeKind::Ge { .. } => ">=",
+        _ => "?",
+    }
+}
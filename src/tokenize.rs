@@ -1,17 +1,205 @@
+use crate::type_utils::{
+    new_array_ty, new_char_ty, new_int_ty, new_long_ty, new_short, new_unsigned,
+};
 use crate::types::*;
 
-impl<'a> Ctx<'a> {
-    // 入力: 数字から始まる文字列　出力: 数字列。副作用: 文字列を数値の次の文字列まで進める
-    pub fn parse_and_skip_number(&mut self) -> isize {
-        let num: String = self
-            .input
-            .chars()
-            .take_while(|c| c.is_ascii_digit())
-            .collect();
-        self.input = &self.input[num.len()..];
-        num.parse().unwrap()
+impl Ctx<'_> {
+    // 数値リテラル。まずpp-numberとして英数字や.、e+のような指数部までまとめて切り出してから、
+    // 整数として読めなければ浮動小数点数として読む
+    fn read_number(&mut self) -> Token {
+        let start = self.current_input_position();
+        let bytes = self.input.as_bytes();
+        let mut len = 0;
+        loop {
+            if len + 1 < bytes.len()
+                && matches!(bytes[len], b'e' | b'E' | b'p' | b'P')
+                && matches!(bytes[len + 1], b'+' | b'-')
+            {
+                len += 2;
+            } else if len < bytes.len()
+                && (bytes[len].is_ascii_alphanumeric() || bytes[len] == b'.' || bytes[len] == b'_')
+            {
+                len += 1;
+            } else {
+                break;
+            }
+        }
+        let text = &self.input[..len];
+        let kind = match self.read_int_literal(start, text) {
+            Some(kind) => kind,
+            None => self.read_float_literal(start, text),
+        };
+        self.advance_input(len);
+        Token { kind, start, len }
+    }
+
+    // 0x, 0b, 0で始まる8進数と、u, l, llの接尾辞を読む。浮動小数点数ならNoneを返す
+    fn read_int_literal(&self, start: usize, text: &str) -> Option<TokenKind> {
+        let lower = text.to_ascii_lowercase();
+        let (base, prefix) = if lower.starts_with("0x") {
+            (16, 2)
+        } else if lower.starts_with("0b") {
+            (2, 2)
+        } else if text.starts_with('0') {
+            (8, 0)
+        } else {
+            (10, 0)
+        };
+        let digits_len = text[prefix..]
+            .find(|c: char| {
+                if base == 16 {
+                    !c.is_ascii_hexdigit()
+                } else {
+                    !c.is_ascii_digit()
+                }
+            })
+            .unwrap_or(text.len() - prefix);
+        let digits = &text[prefix..prefix + digits_len];
+        let suffix = &text[prefix + digits_len..];
+
+        // 1.5, 1e3, 0x1p3 などは浮動小数点数
+        let lower_suffix = suffix.to_ascii_lowercase();
+        if lower_suffix.starts_with('.')
+            || (base == 16 && lower_suffix.starts_with('p'))
+            || (base != 16 && lower_suffix.starts_with('e'))
+        {
+            return None;
+        }
+
+        if digits.is_empty() {
+            self.error_at(start, text.len(), "invalid numeric literal");
+        }
+        if let Some(c) = digits.chars().find(|c| c.to_digit(base).is_none()) {
+            let name = if base == 8 { "octal" } else { "binary" };
+            self.error_at(
+                start,
+                text.len(),
+                &format!("invalid digit '{}' in {} constant", c, name),
+            );
+        }
+
+        // uはllの前後どちらにも付けられる
+        let (is_unsigned, rest) = if let Some(rest) = suffix.strip_prefix(['u', 'U']) {
+            (true, rest)
+        } else if let Some(rest) = suffix.strip_suffix(['u', 'U']) {
+            (true, rest)
+        } else {
+            (false, suffix)
+        };
+        let l = match rest {
+            "" => 0,
+            "l" | "L" => 1,
+            "ll" | "LL" => 2,
+            _ => self.error_at(
+                start,
+                text.len(),
+                &format!("invalid suffix '{}' on integer constant", suffix),
+            ),
+        };
+
+        let Ok(val) = u64::from_str_radix(digits, base) else {
+            self.error_at(start, text.len(), "integer literal is too large");
+        };
+
+        // C11 6.4.4.1 の表の通り、値が収まる最初の型にする。
+        // 10進数は接尾辞にuがなければunsignedにならないが、long longに収まらない場合はgccに合わせてunsigned long
+        let ty = if is_unsigned && l == 0 && val <= u32::MAX as u64 {
+            new_unsigned(new_int_ty())
+        } else if is_unsigned {
+            new_unsigned(new_long_ty())
+        } else if l == 0 && val <= i32::MAX as u64 {
+            new_int_ty()
+        } else if l == 0 && base != 10 && val <= u32::MAX as u64 {
+            new_unsigned(new_int_ty())
+        } else if val <= i64::MAX as u64 {
+            new_long_ty()
+        } else {
+            new_unsigned(new_long_ty())
+        };
+        Some(TokenKind::Num {
+            val: val as isize,
+            ty,
+        })
+    }
+
+    // 浮動小数点数は読むだけで、まだコード生成はできない
+    fn read_float_literal(&self, start: usize, text: &str) -> TokenKind {
+        let body = text.strip_suffix(['f', 'F', 'l', 'L']).unwrap_or(text);
+        let lower = body.to_ascii_lowercase();
+        let val = match lower.strip_prefix("0x") {
+            Some(hex) => parse_hex_float(hex),
+            None => lower.parse().ok(),
+        };
+        match val {
+            Some(val) => TokenKind::FNum { val },
+            None => self.error_at(start, text.len(), "invalid floating constant"),
+        }
+    }
+}
+
+// 文字列中の1文字。\xや8進数のエスケープは値そのまま、それ以外はUnicodeの文字として符号化する
+enum CharUnit {
+    Char(char),
+    Raw(u32),
+}
+
+// 要素の大きさが1ならUTF-8、2ならUTF-16、4ならUTF-32として、リトルエンディアンで追加する
+fn push_char(buf: &mut Vec<u8>, c: CharUnit, width: usize) {
+    match (c, width) {
+        (CharUnit::Char(c), 1) => {
+            let mut utf8 = [0; 4];
+            buf.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        (CharUnit::Char(c), 2) => {
+            let mut utf16 = [0; 2];
+            for unit in c.encode_utf16(&mut utf16) {
+                buf.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+        (CharUnit::Char(c), _) => buf.extend_from_slice(&(c as u32).to_le_bytes()),
+        (CharUnit::Raw(val), _) => buf.extend_from_slice(&val.to_le_bytes()[..width]),
+    }
+}
+
+// "の前のL, u, U, u8の接頭辞の長さ。文字列リテラルでなければNone
+fn string_prefix_len(input: &str) -> Option<usize> {
+    ["u8", "L", "u", "U", ""]
+        .iter()
+        .find(|prefix| {
+            input
+                .strip_prefix(**prefix)
+                .is_some_and(|rest| rest.starts_with('"'))
+        })
+        .map(|prefix| prefix.len())
+}
+
+pub fn string_elem_type(ty: &Type) -> Type {
+    match &ty.kind {
+        TypeKind::Array { ptr_to, .. } => (**ptr_to).clone(),
+        _ => unreachable!(),
     }
 }
+
+// 0x1.8p3 のような16進数の浮動小数点数。指数部のpは省略できない
+fn parse_hex_float(hex: &str) -> Option<f64> {
+    let (mantissa, exp) = hex.split_once('p')?;
+    let exp: i32 = exp.parse().ok()?;
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let mut val = 0.0;
+    for c in int_part.chars() {
+        val = val * 16.0 + c.to_digit(16)? as f64;
+    }
+    let mut scale = 1.0 / 16.0;
+    for c in frac_part.chars() {
+        val += c.to_digit(16)? as f64 * scale;
+        scale /= 16.0;
+    }
+    Some(val * 2f64.powi(exp))
+}
+
 impl Ctx<'_> {
     pub fn advance_input(&mut self, n: usize) {
         self.input = &self.input[n..];
@@ -19,6 +207,59 @@ impl Ctx<'_> {
     pub fn current_input_position(&self) -> usize {
         self.input_copy.len() - self.input.len()
     }
+
+    // 行の先頭(前に空白しかない)か
+    fn at_line_start(&self) -> bool {
+        let pos = self.current_input_position();
+        self.input_copy[..pos]
+            .chars()
+            .rev()
+            .find(|c| *c != ' ' && *c != '\t')
+            .is_none_or(|c| c == '\n')
+    }
+
+    // # 12 "file.c" 2 や #line 12 "file.c" の行を読み飛ばし、次の行がfile.cの12行目であることを覚える
+    fn read_line_marker(&mut self) {
+        let start = self.current_input_position();
+        let end = self.input.find('\n').map_or(self.input.len(), |i| i + 1);
+        let line = &self.input[..end];
+        self.advance_input(end);
+        let rest = line[1..].trim_start();
+        let rest = rest.strip_prefix("line").unwrap_or(rest).trim_start();
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let Ok(num) = rest[..digits].parse::<usize>() else {
+            self.error_at(start, 1, "invalid preprocessing directive");
+        };
+        // ファイル名を省略したら、同じファイルのまま
+        let file = match rest[digits..].trim_start().strip_prefix('"') {
+            Some(quoted) => unescape_file_name(quoted),
+            None => self
+                .line_markers
+                .last()
+                .map_or(self.cur_file.clone(), |m| m.file.clone()),
+        };
+        self.line_markers.push(LineMarker {
+            pos: self.current_input_position(),
+            line: num,
+            file,
+        });
+    }
+}
+
+// 印のファイル名は"と\\がエスケープされている。閉じる"までを取り出す
+fn unescape_file_name(quoted: &str) -> String {
+    let mut name = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => name.extend(chars.next()),
+            c => name.push(c),
+        }
+    }
+    name
 }
 
 impl Ctx<'_> {
@@ -27,14 +268,15 @@ impl Ctx<'_> {
         while !self.input.is_empty() {
             let c = self.input.chars().next().unwrap();
 
-            if c == ' ' {
+            // 空白、タブ、改行のスキップ
+            if c.is_ascii_whitespace() || c == '\x0b' {
                 self.advance_input(1);
                 continue;
             }
 
-            // 改行文字のスキップ
-            if c == '\n' {
-                self.advance_input(1);
+            // プリプロセッサが出力する行番号の印。診断メッセージの位置を元のファイルの行に戻すのに使う
+            if c == '#' && self.at_line_start() {
+                self.read_line_marker();
                 continue;
             }
 
@@ -49,21 +291,29 @@ impl Ctx<'_> {
 
             // ブロックコメントのスキップ
             if self.input.starts_with("/*") {
-                self.advance_input(2);
-                while !self.input.starts_with("*/") {
-                    self.advance_input(1);
-                }
-                self.advance_input(2);
+                let Some(end) = self.input[2..].find("*/") else {
+                    self.error_at(self.current_input_position(), 2, "unterminated comment");
+                };
+                self.advance_input(end + 4);
                 continue;
             }
 
-            if c.is_ascii_digit() {
-                let num = self.parse_and_skip_number();
+            if c.is_ascii_digit()
+                || (c == '.' && self.input[1..].starts_with(|c: char| c.is_ascii_digit()))
+            {
+                let tok = self.read_number();
+                tokens.push(tok);
+                continue;
+            }
+            if self.input.starts_with("<<=") || self.input.starts_with(">>=") {
                 tokens.push(Token {
-                    kind: TokenKind::Num { val: num },
-                    start: self.current_input_position() - num.to_string().len(),
-                    len: num.to_string().len(),
+                    kind: TokenKind::Punct {
+                        str: self.input[0..3].to_string(),
+                    },
+                    start: self.current_input_position(),
+                    len: 3,
                 });
+                self.advance_input(3);
                 continue;
             }
             if self.input.starts_with("==")
@@ -83,6 +333,8 @@ impl Ctx<'_> {
                 || self.input.starts_with("->")
                 || self.input.starts_with("++")
                 || self.input.starts_with("--")
+                || self.input.starts_with("<<")
+                || self.input.starts_with(">>")
             {
                 tokens.push(Token {
                     kind: TokenKind::Punct {
@@ -114,6 +366,10 @@ impl Ctx<'_> {
                 || c == '^'
                 || c == '|'
                 || c == '.'
+                || c == '?'
+                || c == ':'
+                || c == '!'
+                || c == '~'
             {
                 tokens.push(Token {
                     kind: TokenKind::Punct { str: c.to_string() },
@@ -124,26 +380,59 @@ impl Ctx<'_> {
                 continue;
             }
 
-            if c == '"' {
-                let mut str = String::new();
+            // 文字列リテラル。中身はエスケープシーケンスを解釈して、要素の型で符号化したバイト列にする
+            // start, lenには接頭辞と""を含む元のソース上の範囲を入れる
+            if let Some(prefix_len) = string_prefix_len(self.input) {
+                let start = self.current_input_position();
+                let elem = match &self.input[..prefix_len] {
+                    "L" => new_int_ty(),               // wchar_t
+                    "u" => new_unsigned(new_short()),  // char16_t
+                    "U" => new_unsigned(new_int_ty()), // char32_t
+                    _ => new_char_ty(),                // "", u8
+                };
+                self.advance_input(prefix_len);
+                let str = self.read_string_body(start, elem.size);
+                let len = (str.len() / elem.size) as isize;
+                tokens.push(Token {
+                    kind: TokenKind::Str {
+                        str,
+                        ty: new_array_ty(elem, len),
+                    },
+                    start,
+                    len: self.current_input_position() - start,
+                });
+                continue;
+            }
+
+            // 文字定数。'a'はchar、'ab'のような複数文字はgccと同じくバイトを並べたintの値になる
+            if c == '\'' {
+                let start = self.current_input_position();
                 self.advance_input(1);
-                // while self.input.chars().next().unwrap() != '"' {
-                while !self.input.starts_with('\"') {
-                    // 取り急ぎの実装。後でなんとかしないと。string.cのテストに対応したいがために実装したもの。
-                    if self.input.starts_with("\\\"") {
-                        str.push_str("\\\"");
-                        self.advance_input(2); // \"が入るとstrのlenがずれて、tok.startもずれるかも
-                        continue;
+                let mut bytes = Vec::new();
+                while !self.input.starts_with('\'') {
+                    if self.input.is_empty() || self.input.starts_with('\n') {
+                        self.error_at(start, 1, "unclosed char literal");
                     }
-                    str.push(self.input.chars().next().unwrap());
-                    self.advance_input(1);
+                    let c = self.read_char(1);
+                    push_char(&mut bytes, c, 1);
                 }
-                str.push('\0');
                 self.advance_input(1);
+                let len = self.current_input_position() - start;
+                let val = match bytes.len() {
+                    0 => self.error_at(start, len, "empty character constant"),
+                    1 => bytes[0] as i8 as isize,
+                    _ => bytes
+                        .iter()
+                        .fold(0i32, |val, b| val.wrapping_shl(8) | *b as i32)
+                        as isize,
+                };
                 tokens.push(Token {
-                    kind: TokenKind::Str { str: str.clone() },
-                    start: self.current_input_position() - str.len(),
-                    len: str.len(),
+                    kind: TokenKind::Num {
+                        val,
+                        ty: new_int_ty(),
+                    },
+                    start,
+                    len,
                 });
                 continue;
             }
@@ -160,15 +449,228 @@ impl Ctx<'_> {
                 });
                 continue;
             }
-            self.error_input_at(format!("invalid input: {}", &self.input[0..1]).as_str());
+            // 知らない文字は報告だけして読み飛ばし、残りのエラーも探す
+            self.report(Diagnostic::error(
+                Some(Span {
+                    start: self.current_input_position(),
+                    len: c.len_utf8(),
+                }),
+                &format!("invalid input: {}", c),
+            ));
+            self.advance_input(c.len_utf8());
         }
+        let mut tokens = self.join_adjacent_strings(tokens);
+        tokens.push(Token {
+            kind: TokenKind::Eof,
+            start: self.input_copy.len(),
+            len: 0,
+        });
         tokens
     }
 
+    // "から"までを読んで、要素の大きさwidthで符号化したバイト列にする。末尾の\0を含む
+    fn read_string_body(&mut self, start: usize, width: usize) -> Vec<u8> {
+        self.advance_input(1);
+        let mut str = Vec::new();
+        while !self.input.starts_with('"') {
+            if self.input.is_empty() || self.input.starts_with('\n') {
+                self.error_at(start, 1, "unclosed string literal");
+            }
+            let c = self.read_char(width);
+            push_char(&mut str, c, width);
+        }
+        self.advance_input(1);
+        push_char(&mut str, CharUnit::Raw(0), width);
+        str
+    }
+
+    // 読み終わった文字列リテラルを、別の要素の大きさで読み直す
+    fn reread_string(&mut self, tok: &Token, width: usize) -> Vec<u8> {
+        let saved = self.input;
+        let quote = tok.start + self.input_copy[tok.start..].find('"').unwrap();
+        self.input = &self.input_copy[quote..];
+        let str = self.read_string_body(tok.start, width);
+        self.input = saved;
+        str
+    }
+
+    // "abc" "def" のように隣り合う文字列リテラルを1つにつなげる。
+    // 片方だけがワイド文字列なら、もう片方もその要素の型で読み直す
+    fn join_adjacent_strings(&mut self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut joined: Vec<Token> = Vec::new();
+        for tok in tokens {
+            let Some(prev) = joined.last() else {
+                joined.push(tok);
+                continue;
+            };
+            let (TokenKind::Str { str: str1, ty: ty1 }, TokenKind::Str { str: str2, ty: ty2 }) =
+                (&prev.kind, &tok.kind)
+            else {
+                joined.push(tok);
+                continue;
+            };
+            let elem1 = string_elem_type(ty1);
+            let elem2 = string_elem_type(ty2);
+            if elem1.size > 1
+                && elem2.size > 1
+                && (elem1.size != elem2.size || elem1.is_unsigned != elem2.is_unsigned)
+            {
+                self.error_at(
+                    prev.start,
+                    tok.start + tok.len - prev.start,
+                    "unsupported non-standard concatenation of string literals",
+                );
+            }
+            let elem = if elem1.size > 1 {
+                elem1.clone()
+            } else {
+                elem2.clone()
+            };
+            let mut str1 = if elem1.size != elem.size {
+                self.reread_string(prev, elem.size)
+            } else {
+                str1.clone()
+            };
+            let str2 = if elem2.size != elem.size {
+                self.reread_string(&tok, elem.size)
+            } else {
+                str2.clone()
+            };
+            str1.truncate(str1.len() - elem.size);
+            str1.extend(str2);
+            let len = (str1.len() / elem.size) as isize;
+            let prev = joined.last_mut().unwrap();
+            prev.len = tok.start + tok.len - prev.start;
+            prev.kind = TokenKind::Str {
+                str: str1,
+                ty: new_array_ty(elem, len),
+            };
+        }
+        joined
+    }
+
+    // 1文字読む。エスケープシーケンスはここで解釈する
+    // widthは文字列の要素の大きさで、\xや8進数のエスケープの範囲チェックに使う
+    fn read_char(&mut self, width: usize) -> CharUnit {
+        let c = self.input.chars().next().unwrap();
+        if c != '\\' {
+            self.advance_input(c.len_utf8());
+            return CharUnit::Char(c);
+        }
+
+        let start = self.current_input_position();
+        self.advance_input(1);
+        let Some(c) = self.input.chars().next() else {
+            self.error_at(start, 1, "unterminated escape sequence");
+        };
+        match c {
+            // \0, \101 のような8進数。最大3桁
+            '0'..='7' => {
+                let digits: String = self
+                    .input
+                    .chars()
+                    .take(3)
+                    .take_while(|c| ('0'..='7').contains(c))
+                    .collect();
+                self.advance_input(digits.len());
+                let val = u32::from_str_radix(&digits, 8).unwrap();
+                if width == 1 && val > 0xff {
+                    self.error_at(
+                        start,
+                        digits.len() + 1,
+                        "octal escape sequence out of range",
+                    );
+                }
+                CharUnit::Raw(val)
+            }
+            // \x41 のような16進数。桁数の上限はない
+            'x' => {
+                self.advance_input(1);
+                let digits: String = self
+                    .input
+                    .chars()
+                    .take_while(|c| c.is_ascii_hexdigit())
+                    .collect();
+                self.advance_input(digits.len());
+                if digits.is_empty() {
+                    self.error_at(start, 2, "\\x used with no following hex digits");
+                }
+                let digits = digits.trim_start_matches('0');
+                if digits.len() > width * 2 {
+                    self.error_at(
+                        start,
+                        self.current_input_position() - start,
+                        "hex escape sequence out of range",
+                    );
+                }
+                CharUnit::Raw(u32::from_str_radix(digits, 16).unwrap_or(0))
+            }
+            // \u00e9, \U0001F600 のようなユニバーサル文字名
+            'u' | 'U' => {
+                let n = if c == 'u' { 4 } else { 8 };
+                self.advance_input(1);
+                let digits: String = self
+                    .input
+                    .chars()
+                    .take(n)
+                    .take_while(|c| c.is_ascii_hexdigit())
+                    .collect();
+                self.advance_input(digits.len());
+                let len = self.current_input_position() - start;
+                if digits.len() != n {
+                    self.error_at(start, len, "incomplete universal character name");
+                }
+                let Some(c) = u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                else {
+                    self.error_at(start, len, "invalid universal character");
+                };
+                CharUnit::Char(c)
+            }
+            _ => {
+                self.advance_input(c.len_utf8());
+                let b = match c {
+                    'a' => 7,
+                    'b' => 8,
+                    't' => 9,
+                    'n' => 10,
+                    'v' => 11,
+                    'f' => 12,
+                    'r' => 13,
+                    'e' => 27, // GNU拡張
+                    // \\, \', \", \? と、未知のエスケープはその文字自身
+                    _ => return CharUnit::Char(c),
+                };
+                CharUnit::Raw(b)
+            }
+        }
+    }
+
     pub fn convert_keywords(&mut self) {
         let keywords = vec![
-            "return", "if", "else", "for", "while", "int", "sizeof", "char", "struct", "union",
-            "long", "short", "typedef", "enum",
+            "return",
+            "if",
+            "else",
+            "for",
+            "while",
+            "int",
+            "sizeof",
+            "char",
+            "struct",
+            "union",
+            "long",
+            "short",
+            "typedef",
+            "enum",
+            "static",
+            "extern",
+            "_Alignof",
+            "alignof",
+            "_Alignas",
+            "__attribute__",
+            "signed",
+            "unsigned",
         ];
         for token in &mut self.tokens {
             if let TokenKind::Ident { name } = &token.kind {
@@ -199,11 +701,12 @@ pub fn equal(tok: &Token, s: &str) -> bool {
 
 // for token
 impl Ctx<'_> {
-    pub fn get_and_skip_number(&mut self) -> isize {
-        match self.tokens[0].kind {
-            TokenKind::Num { val } => {
+    pub fn get_and_skip_number(&mut self) -> (isize, Type) {
+        match &self.tokens[0].kind {
+            TokenKind::Num { val, ty } => {
+                let num = (*val, ty.clone());
                 self.consumed_tokens.push(self.tokens.remove(0));
-                val
+                num
             }
             _ => self.error_tok(&self.tokens[0], "expected a number"),
         }
@@ -220,6 +723,14 @@ impl Ctx<'_> {
         }
     }
 
+    // 処理済みのトークンがn個になるまで、トークンを未処理に戻す
+    pub fn rewind(&mut self, n: usize) {
+        while self.consumed_tokens.len() > n {
+            let tok = self.consumed_tokens.pop().unwrap();
+            self.tokens.insert(0, tok);
+        }
+    }
+
     // 複数個飛ばす場合、最後のtokenのみが返る
     pub fn advance(&mut self, n: usize) -> Token {
         assert!(n >= 1);
@@ -250,6 +761,10 @@ impl Ctx<'_> {
         self.error_tok(self.get_tok(0), format!("expected '{}'", op).as_str())
     }
 
+    pub fn at_eof(&self) -> bool {
+        matches!(self.tokens[0].kind, TokenKind::Eof)
+    }
+
     pub fn hequal(&mut self, s: &str) -> bool {
         if let TokenKind::Punct { str } = &self.tokens[0].kind {
             return str == s;
@@ -268,47 +783,4 @@ impl Ctx<'_> {
             false
         }
     }
-
-    #[allow(dead_code)]
-    pub fn show_tokens(&self) {
-        eprintln!("{:#?}", self.tokens);
-    }
-
-    pub fn error_tok(&self, tok: &Token, msg: &str) -> ! {
-        let mut idx = 0;
-        let mut line_idx = 1;
-        let mut line_string_before = String::new();
-        let mut line_string = String::new();
-        for line in self.input_copy.lines() {
-            if idx + line.len() >= tok.start {
-                line_string = line.to_string();
-                break;
-            }
-            idx += line.len() + 1;
-            line_idx += 1;
-            line_string_before = line.to_string();
-        }
-
-        eprintln!("{}:{}: error", self.cur_file, line_idx);
-        eprintln!();
-        eprintln!("|");
-        eprintln!("|{}", line_string_before);
-        eprintln!("|{}", line_string);
-        eprintln!(
-            "|{}{} {}",
-            " ".repeat(tok.start - idx),
-            "^".repeat(tok.len),
-            msg
-        ); // 後々該当箇所のinput_copyを色付けして表す
-        eprintln!("|");
-        eprintln!();
-        panic!();
-    }
-
-    pub fn error_input_at(&self, msg: &str) {
-        eprintln!("{}", self.input_copy);
-        eprintln!("{}^", " ".repeat(self.current_input_position()));
-        eprintln!("jff_error: {}", msg);
-        panic!();
-    }
 }
@@ -1,6 +1,6 @@
-use core::panic;
 use std::{cell::RefCell, rc::Rc};
 
+use crate::sema::operator;
 use crate::types::*;
 
 pub fn new_ptr_to(ty: Type) -> Type {
@@ -10,6 +10,7 @@ pub fn new_ptr_to(ty: Type) -> Type {
         },
         size: 8,
         align: 8,
+        is_unsigned: false,
     }
 }
 
@@ -18,6 +19,7 @@ pub fn new_short() -> Type {
         kind: TypeKind::Short,
         size: 2,
         align: 2,
+        is_unsigned: false,
     }
 }
 
@@ -26,6 +28,7 @@ pub fn new_int_ty() -> Type {
         kind: TypeKind::Int,
         size: 4,
         align: 4,
+        is_unsigned: false,
     }
 }
 
@@ -34,6 +37,7 @@ pub fn new_long_ty() -> Type {
         kind: TypeKind::Long,
         size: 8,
         align: 8,
+        is_unsigned: false,
     }
 }
 
@@ -42,17 +46,26 @@ pub fn new_char_ty() -> Type {
         kind: TypeKind::Char,
         size: 1,
         align: 1,
+        is_unsigned: false,
     }
 }
 
-pub fn new_array_ty(ty: Type, len: usize) -> Type {
+pub fn new_unsigned(ty: Type) -> Type {
+    Type {
+        is_unsigned: true,
+        ..ty
+    }
+}
+
+pub fn new_array_ty(ty: Type, len: isize) -> Type {
     Type {
         kind: TypeKind::Array {
             ptr_to: Box::new(ty.clone()),
             len,
         },
-        size: ty.size * len,
+        size: if len < 0 { 0 } else { ty.size * len as usize },
         align: ty.align,
+        is_unsigned: false,
     }
 }
 
@@ -60,10 +73,70 @@ pub fn get_common_type(ty1: Type, ty2: Type) -> Type {
     if let TypeKind::Ptr { ptr_to } = ty1.kind {
         return new_ptr_to(*ptr_to); // こうなのか？なぜかは知らん
     }
-    if ty1.size == 8 || ty2.size == 8 {
-        return new_long_ty();
+    // char, shortはintに格上げされる。short + shortもintになるらしい
+    let ty1 = if ty1.size < 4 { new_int_ty() } else { ty1 };
+    let ty2 = if ty2.size < 4 { new_int_ty() } else { ty2 };
+    // 大きい方の型に合わせる。同じ大きさなら、どちらかがunsignedならunsignedになる
+    // longはunsigned intの値をすべて表せるので、long + unsigned int はlong
+    if ty1.size != ty2.size {
+        return if ty1.size > ty2.size { ty1 } else { ty2 };
+    }
+    let ty = if ty1.size == 8 {
+        new_long_ty()
+    } else {
+        new_int_ty()
+    };
+    if ty1.is_unsigned || ty2.is_unsigned {
+        new_unsigned(ty)
+    } else {
+        ty
+    }
+}
+
+// 値を型の大きさで切り詰めて、isizeに拡張し直す
+pub fn truncate(val: isize, ty: &Type) -> isize {
+    match (ty.size, ty.is_unsigned) {
+        (1, true) => val as u8 as isize,
+        (1, false) => val as i8 as isize,
+        (2, true) => val as u16 as isize,
+        (2, false) => val as i16 as isize,
+        (4, true) => val as u32 as isize,
+        (4, false) => val as i32 as isize,
+        _ => val,
+    }
+}
+
+// エラーや警告に出す型の名前。gccと同じ書き方にする
+pub fn type_name(ty: &Type) -> String {
+    let unsigned = if ty.is_unsigned { "unsigned " } else { "" };
+    match &ty.kind {
+        TypeKind::Char => format!("{}char", unsigned),
+        TypeKind::Short => format!("short {}int", unsigned),
+        TypeKind::Int => format!("{}int", unsigned),
+        TypeKind::Long => format!("long {}int", unsigned),
+        TypeKind::Enum { tag, .. } => tagged_name("enum", tag),
+        TypeKind::Struct { tag, .. } => tagged_name("struct", tag),
+        TypeKind::Union { tag, .. } => tagged_name("union", tag),
+        TypeKind::Ptr { ptr_to } => {
+            let base = type_name(ptr_to);
+            if base.ends_with('*') {
+                format!("{}*", base)
+            } else {
+                format!("{} *", base)
+            }
+        }
+        TypeKind::Array { ptr_to, len } if *len < 0 => format!("{}[]", type_name(ptr_to)),
+        TypeKind::Array { ptr_to, len } => format!("{}[{}]", type_name(ptr_to), len),
+    }
+}
+
+// gccと同じく、無名なら struct <anonymous> と表示する
+fn tagged_name(keyword: &str, tag: &str) -> String {
+    if tag.is_empty() {
+        format!("{} <anonymous>", keyword)
+    } else {
+        format!("{} {}", keyword, tag)
     }
-    new_int_ty() // short + shortもintになるらしい
 }
 
 pub fn is_integer_node(node: &Node) -> bool {
@@ -88,9 +161,11 @@ pub fn is_pointer_node(node: &Node) -> bool {
     }
 }
 
-#[allow(dead_code)]
 pub fn is_integer(ty: &Type) -> bool {
-    matches!(ty.kind, TypeKind::Int | TypeKind::Short | TypeKind::Long)
+    matches!(
+        ty.kind,
+        TypeKind::Int | TypeKind::Short | TypeKind::Char | TypeKind::Long | TypeKind::Enum { .. }
+    )
 }
 
 #[allow(dead_code)]
@@ -98,16 +173,6 @@ pub fn is_pointer(ty: &Type) -> bool {
     matches!(ty.kind, TypeKind::Ptr { .. })
 }
 
-pub fn get_pointer_or_array_size(node: &Node) -> usize {
-    match &node.ty {
-        Some(ty) => match &ty.kind {
-            TypeKind::Ptr { ptr_to } | TypeKind::Array { ptr_to, .. } => ptr_to.size,
-            _ => panic!("not a pointer or array"),
-        },
-        None => panic!("no type information"),
-    }
-}
-
 pub fn copy_type(node: &Node) -> Type {
     node.clone().ty.unwrap()
 }
@@ -122,30 +187,55 @@ impl Ctx<'_> {
             return;
         }
 
+        let op = operator(&node.kind);
         match &mut node.kind {
-            NodeKind::Add { lhs, rhs }
-            | NodeKind::Sub { lhs, rhs }
-            | NodeKind::Mul { lhs, rhs }
+            // ポインタの足し算、引き算はnew_add, new_subで型を決めている
+            NodeKind::Add { lhs, rhs } | NodeKind::Sub { lhs, rhs } => {
+                self.add_type(lhs);
+                self.add_type(rhs);
+                let ty = get_common_type(copy_type(lhs), copy_type(rhs));
+                node.ty = Some(ty);
+            }
+            NodeKind::Mul { lhs, rhs }
             | NodeKind::Div { lhs, rhs }
-            | NodeKind::Mod { lhs, rhs } => {
+            | NodeKind::Mod { lhs, rhs }
+            | NodeKind::BitAnd { lhs, rhs }
+            | NodeKind::BitXor { lhs, rhs }
+            | NodeKind::BitOr { lhs, rhs } => {
                 self.add_type(lhs);
                 self.add_type(rhs);
+                self.check_integer_operands(op, lhs, rhs, node.span);
                 let ty = get_common_type(copy_type(lhs), copy_type(rhs));
                 node.ty = Some(ty);
             }
             NodeKind::NdAssign { lhs, rhs } => {
                 self.add_type(lhs);
                 self.add_type(rhs);
-                node.ty = lhs.ty.clone();
+                self.check_assign(lhs, rhs, node.span);
+                // 右辺は左辺の型に変換してから代入する。代入式の値も変換後の値になる
+                let ty = copy_type(lhs);
+                if is_integer(&ty) && rhs.ty.is_some() {
+                    **rhs = self.new_cast((**rhs).clone(), ty.clone());
+                }
+                node.ty = Some(ty);
             }
             NodeKind::Eq { lhs, rhs }
             | NodeKind::Ne { lhs, rhs }
             | NodeKind::Lt { lhs, rhs }
             | NodeKind::Le { lhs, rhs }
             | NodeKind::Gt { lhs, rhs }
-            | NodeKind::Ge { lhs, rhs }
-            | NodeKind::And { lhs, rhs }
-            | NodeKind::Or { lhs, rhs } => {
+            | NodeKind::Ge { lhs, rhs } => {
+                self.add_type(lhs);
+                self.add_type(rhs);
+                self.check_scalar_operands(op, lhs, rhs, node.span);
+                self.usual_arith_conv(lhs, rhs);
+                node.ty = Some(new_int_ty());
+            }
+            NodeKind::And { lhs, rhs } | NodeKind::Or { lhs, rhs } => {
+                self.add_type(lhs);
+                self.add_type(rhs);
+                self.check_condition(lhs);
+                self.check_condition(rhs);
                 self.usual_arith_conv(lhs, rhs);
                 node.ty = Some(new_int_ty());
             }
@@ -161,44 +251,115 @@ impl Ctx<'_> {
             }
             NodeKind::Addr { lhs } => {
                 self.add_type(lhs);
+                self.check_addr(lhs);
                 if let Some(ty) = &lhs.ty {
                     node.ty = Some(new_ptr_to(ty.clone()));
                 }
             }
-            NodeKind::Deref { lhs, tok } => {
+            NodeKind::Deref { lhs } => {
                 self.add_type(lhs);
                 if let Some(ty) = &lhs.ty {
                     match &ty.kind {
                         TypeKind::Ptr { ptr_to } | TypeKind::Array { ptr_to, .. } => {
-                            node.ty = Some((**ptr_to).clone());
+                            node.ty = Some(self.complete_type((**ptr_to).clone()));
                         }
-                        _ => self.error_tok(tok, "not a pointer or array"),
+                        _ => self.error_node(lhs, "not a pointer or array"),
                     }
                 } else {
-                    panic!("no type information");
+                    self.error_node(lhs, "operand has no type");
                 }
             }
-            NodeKind::Neg { lhs } => {
+            // 単項演算とシフトの結果は、左辺を整数拡張した型になる
+            NodeKind::Neg { lhs } | NodeKind::BitNot { lhs } => {
                 self.add_type(lhs);
-                node.ty = lhs.ty.clone();
+                self.check_unary_operand(op, lhs, node.span);
+                node.ty = Some(get_common_type(copy_type(lhs), new_int_ty()));
+            }
+            NodeKind::Not { lhs } => {
+                self.add_type(lhs);
+                self.check_unary_operand(op, lhs, node.span);
+                node.ty = Some(new_int_ty());
+            }
+            NodeKind::Shl { lhs, rhs } | NodeKind::Shr { lhs, rhs } => {
+                self.add_type(lhs);
+                self.add_type(rhs);
+                self.check_integer_operands(op, lhs, rhs, node.span);
+                node.ty = Some(get_common_type(copy_type(lhs), new_int_ty()));
+            }
+            NodeKind::Cond { cond, then, els } => {
+                self.add_type(cond);
+                self.check_condition(cond);
+                self.add_type(then);
+                self.add_type(els);
+                if is_integer_node(then) && is_integer_node(els) {
+                    node.ty = Some(get_common_type(copy_type(then), copy_type(els)));
+                } else {
+                    node.ty = then.ty.clone();
+                }
             }
             NodeKind::Return { lhs } | NodeKind::ExprStmt { lhs } => {
                 self.add_type(lhs);
                 node.ty = lhs.ty.clone();
             }
             NodeKind::GNUStmtExpr { body } => {
-                let last_node = body.last().unwrap();
+                let Some(last_node) = body.last() else {
+                    self.error_span(
+                        node.span,
+                        "statement expression returning void is not supported",
+                    );
+                };
                 node.ty = last_node.ty.clone();
             }
             NodeKind::Member { member, .. } => {
                 node.ty = Some(member.ty.clone()); // よくわからん
             }
-            _ => {} // Block, If, For, While, Funccall
+            NodeKind::If { cond, .. }
+            | NodeKind::While { cond, .. }
+            | NodeKind::For {
+                cond: Some(cond), ..
+            } => {
+                self.check_condition(cond);
+            }
+            _ => {} // Block, Funccall
+        }
+    }
+
+    // 不完全型のstruct, unionがあとで定義されていれば、定義された型を返す
+    pub fn complete_type(&self, ty: Type) -> Type {
+        match &ty.kind {
+            TypeKind::Struct { members, id, .. } | TypeKind::Union { members, id, .. }
+                if members.is_empty() =>
+            {
+                self.struct_types[*id].clone().unwrap_or(ty)
+            }
+            _ => ty,
+        }
+    }
+
+    pub fn is_incomplete_struct(&self, ty: &Type) -> bool {
+        match &ty.kind {
+            TypeKind::Struct { id, .. } | TypeKind::Union { id, .. } => {
+                self.struct_types[*id].is_none()
+            }
+            _ => false,
+        }
+    }
+
+    // ポインタ演算で使う、指す先の型の大きさ
+    pub fn pointee_size(&self, node: &Node) -> usize {
+        match &node.ty {
+            Some(ty) => match &ty.kind {
+                TypeKind::Ptr { ptr_to } | TypeKind::Array { ptr_to, .. } => {
+                    self.complete_type((**ptr_to).clone()).size
+                }
+                _ => self.error_node(node, "not a pointer or array"),
+            },
+            None => self.error_node(node, "operand has no type"),
         }
     }
 
     pub fn usual_arith_conv(&mut self, lhs: &mut Node, rhs: &mut Node) {
-        let ty = get_common_type(copy_type(lhs), copy_type(lhs));
+        let ty = get_common_type(copy_type(lhs), copy_type(rhs));
         *lhs = self.new_cast(lhs.clone(), ty.clone());
         *rhs = self.new_cast(rhs.clone(), ty);
     }
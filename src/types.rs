@@ -1,4 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 #[derive(Debug)]
 pub struct Ctx<'a> {
@@ -7,9 +11,89 @@ pub struct Ctx<'a> {
     pub tokens: Vec<Token>,
     pub consumed_tokens: Vec<Token>,
     pub gvars: Vec<Rc<RefCell<Var>>>, // find_varのために型をrefcellにしてみる。不適切の恐れあり
-    pub cur_func: String,
+    pub cur_func: String,             // 関数の外(ファイルスコープ)では空文字
     pub cur_file: String,
+    pub original_input: Option<&'a str>, // -eで渡したプリプロセス前のソース。ファイルから読めない行の表示に使う
+    pub line_markers: Vec<LineMarker>,   // プリプロセッサが出力した行番号の印。位置の順に並ぶ
     pub functions: HashMap<String, Function>,
+    pub global_scope: Scope, // ファイルスコープのtag, typedef, enum。グローバル変数はgvarsで管理する
+    pub struct_types: Vec<Option<Type>>, // struct, unionの定義。まだメンバーが定義されていなければNone
+    pub diags: RefCell<Vec<Diagnostic>>, // 報告したエラーなど。&selfのメソッドからも積めるようにRefCellにする
+    pub opts: CompileOptions,
+}
+
+// ドライバからコンパイラ本体に渡すオプション
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    pub color: bool,                  // 診断メッセージに色を付ける
+    pub warnings: HashSet<Warning>,   // 有効な警告
+    pub werror: bool,                 // -Werror。すべての警告をエラーにする
+    pub werror_for: HashSet<Warning>, // -Werror=name
+    pub diagnostics_format: DiagnosticsFormat,
+    pub dump: Option<Dump>, // コードを生成する代わりに、トークン列や構文木を出力する
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dump {
+    Tokens,  // --dump-tokens
+    Ast,     // --dump-ast
+    AstJson, // --dump-ast=json
+    Ir,      // --dump-ir
+}
+
+// 診断メッセージの出力形式
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DiagnosticsFormat {
+    #[default]
+    Text, // gcc, clangと同じ人が読む形式
+    Json, // 1行に1つのJSON。CIなどのツールで読む
+}
+
+// -W<name>で個別に切り替えられる警告。名前はgccに合わせる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Warning {
+    UnusedVariable,
+    UnusedParameter,
+    ImplicitFunctionDeclaration,
+    Overflow,   // 定数が変換後の型に収まらない
+    Conversion, // 代入で値が変わるかもしれない暗黙の変換
+    ReturnType,
+    Shadow,
+}
+
+//
+// diagnostic
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+// プリプロセッサが出力する # 12 "file.c" の印。posから始まる行が、fileのline行目にあたる
+// 最初の印のファイルはコンパイルしている入力そのもの(-eなら<stdin>)なので、表示するときはcur_fileにする
+#[derive(Debug, Clone)]
+pub struct LineMarker {
+    pub pos: usize,
+    pub line: usize,
+    pub file: String,
+}
+
+// ソース上の範囲。startはinput_copyの中のバイト位置。Ctxは1つのファイルを扱うので、ファイルはCtx.cur_file
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Option<Span>, // ファイル全体や、場所を特定できないものはNone
+    pub message: String,
+    pub notes: Vec<Diagnostic>,   // 補足。severityはNote
+    pub warning: Option<Warning>, // 警告(と-Werrorでエラーにしたもの)の種類
 }
 
 #[derive(Debug)]
@@ -24,9 +108,19 @@ pub struct Function {
     pub scope_idx: isize,
     pub exited_scope: Vec<Scope>,
     pub is_def: bool,
+    pub is_static: bool, // staticな関数はファイル外から見えない
+    pub span: Span,      // 関数名の位置
 }
 
-#[derive(Debug)]
+// 宣言の記憶域クラス
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VarAttr {
+    pub is_static: bool,
+    pub is_extern: bool,
+    pub align: usize, // _Alignasで指定されたアラインメント。指定がなければ0
+}
+
+#[derive(Debug, Default)]
 pub struct Scope {
     pub variables: Vec<Rc<RefCell<Var>>>,
     pub tags: Vec<StructTag>,
@@ -57,11 +151,30 @@ pub struct Enum {
 //
 #[derive(Debug, Clone)]
 pub enum TokenKind {
-    Punct { str: String },
-    Num { val: isize },
-    Ident { name: String },
-    Keyword { name: String },
-    Str { str: String },
+    Punct {
+        str: String,
+    },
+    Num {
+        val: isize,
+        ty: Type,
+    }, // tyは接尾辞と値の大きさから決まるリテラルの型
+    FNum {
+        #[allow(dead_code)]
+        val: f64,
+    },
+    Ident {
+        name: String,
+    },
+    Keyword {
+        name: String,
+    },
+    // エスケープシーケンスを解釈し、要素の型で符号化したバイト列。末尾の\0を含む
+    // tyは要素の型の配列。"abc"ならchar[4]、L"abc"ならint[4]
+    Str {
+        str: Vec<u8>,
+        ty: Type,
+    },
+    Eof, // 入力の終わり。tokens[0]が必ずあるように、最後に1つだけ置く
 }
 
 #[derive(Debug, Clone)]
@@ -77,19 +190,47 @@ pub struct Token {
 #[derive(Debug, Clone)]
 pub struct Var {
     pub name: String,
-    pub offset: usize,
+    pub asm_name: String, // アセンブリ上のシンボル名。関数内のstatic変数は他と被らない名前にする
     pub ty: Type,
+    pub align: usize, // 型のアラインメントか、_Alignasなどで指定されたもののうち大きい方
     #[allow(dead_code)]
     pub is_param: bool, // 8個を超える引数を扱う際、スタックを利用して引数を渡すことになると思うので、その実装の際に必要になる想定
     pub is_local: bool,
+    pub is_static: bool,     // staticなグローバル変数はファイル外から見えない
+    pub is_definition: bool, // externの場合はfalseで、領域を確保しない
+    pub is_tentative: bool, // int x; のような初期化子のない定義。ほかのファイルの定義とまとめられる
     pub init_gval: Option<InitGval>,
+    pub span: Span,    // 宣言した名前の位置
+    pub is_used: bool, // 一度でも参照されたか。-Wunused-variableで使う
 }
 
 #[derive(Debug, Clone)]
 pub enum InitGval {
-    Str(String),
-    #[allow(dead_code)]
-    Num(isize), // 初期化の際に必要だけど./test.shとかで少し邪魔だからdead_codeにしている
+    Str(Vec<u8>),
+    // 初期化子を評価したバイト列。ポインタの初期値がほかのグローバル変数のアドレスの場合はrelocsに入る
+    Data {
+        bytes: Vec<u8>,
+        relocs: Vec<Relocation>,
+    },
+}
+
+// int *p = &x + 1; なら、offset: 0, label: "x", addend: 4
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    pub offset: usize,
+    pub label: String,
+    pub addend: isize,
+}
+
+// 変数の初期化子。配列、構造体の場合はchildrenに要素ごとの初期化子が入る
+// int x[2][3] = {{1, 2, 3}, {4, 5, 6}}; なら、childrenが2つで、それぞれのchildrenが3つ
+#[derive(Debug, Clone)]
+pub struct Initializer {
+    pub ty: Type,
+    pub expr: Option<Node>, // スカラー、もしくは式で初期化された構造体の場合
+    pub children: Vec<Initializer>,
+    pub is_flexible: bool, // int x[] = {...} のように、初期化子の要素数で長さが決まる場合
+    pub member_idx: usize, // unionの場合、どのメンバを初期化するか
 }
 
 //
@@ -191,7 +332,6 @@ pub enum NodeKind {
     },
     Deref {
         lhs: Box<Node>,
-        tok: Token,
     },
     FuncCall {
         name: String,
@@ -212,6 +352,25 @@ pub enum NodeKind {
         lhs: Box<Node>,
         rhs: Box<Node>,
     },
+    BitNot {
+        lhs: Box<Node>,
+    },
+    Not {
+        lhs: Box<Node>,
+    },
+    Shl {
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+    Shr {
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+    Cond {
+        cond: Box<Node>,
+        then: Box<Node>,
+        els: Box<Node>,
+    },
     Member {
         lhs: Box<Node>,
         member: Member,
@@ -219,6 +378,9 @@ pub enum NodeKind {
     Cast {
         lhs: Box<Node>,
     },
+    MemZero {
+        var: Rc<RefCell<Var>>,
+    },
 }
 
 // for struct and union
@@ -227,12 +389,19 @@ pub struct Member {
     pub name: String,
     pub ty: Type,
     pub offset: usize, // 構造体からの相対オフセット
+    // ビットフィールドの場合、offsetはtyの大きさの記憶単位の位置で、その中のbit_offsetビット目からbit_widthビットを使う
+    pub is_bitfield: bool,
+    pub bit_offset: usize,
+    pub bit_width: usize,
+    // _Alignasやaligned属性で指定されたアラインメント。指定がなければ0で、struct_layoutで実際の値を入れる
+    pub align: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct Node {
     pub kind: NodeKind,
     pub ty: Option<Type>,
+    pub span: Span, // 式や文全体のソース上の範囲。エラーや警告で指す
 }
 
 //
@@ -246,20 +415,26 @@ pub enum TypeKind {
     Ptr {
         ptr_to: Box<Type>,
     },
-    #[allow(dead_code)]
     Array {
         ptr_to: Box<Type>,
-        len: usize, // <- これをまだ未使用だからdead_codeにしている, lenがある方が自然だおと持っている
+        len: isize, // int x[] のように長さが未定の場合は-1
     },
     Char,
+    // idはCtx.struct_typesの添字。不完全型へのポインタは、あとで定義された型をidで引き直す
+    // tagはエラーメッセージなどで型を表示するためのもので、無名なら空
     Struct {
         members: Vec<Member>,
+        id: usize,
+        tag: String,
     },
     Union {
         members: Vec<Member>,
+        id: usize,
+        tag: String,
     },
     Enum {
         members: Vec<EnumMember>,
+        tag: String,
     },
 }
 
@@ -276,4 +451,5 @@ pub struct Type {
     pub kind: TypeKind,
     pub size: usize,
     pub align: usize,
+    pub is_unsigned: bool, // 整数型のみ。unsigned int なら TypeKind::Int で true
 }
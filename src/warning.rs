@@ -0,0 +1,244 @@
+// 警告。-W<name>で個別に切り替えられ、-Werrorでエラーにできる
+use std::collections::HashSet;
+
+use crate::type_utils::{is_integer, truncate, type_name};
+use crate::types::*;
+
+impl Warning {
+    const ALL: [Warning; 7] = [
+        Warning::UnusedVariable,
+        Warning::UnusedParameter,
+        Warning::ImplicitFunctionDeclaration,
+        Warning::Overflow,
+        Warning::Conversion,
+        Warning::ReturnType,
+        Warning::Shadow,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::UnusedParameter => "unused-parameter",
+            Warning::ImplicitFunctionDeclaration => "implicit-function-declaration",
+            Warning::Overflow => "overflow",
+            Warning::Conversion => "conversion",
+            Warning::ReturnType => "return-type",
+            Warning::Shadow => "shadow",
+        }
+    }
+
+    // -W<name>の名前から。-Wallのようにまとめて切り替えるものも含む
+    pub fn from_name(name: &str) -> Vec<Warning> {
+        match name {
+            "all" => vec![
+                Warning::UnusedVariable,
+                Warning::ImplicitFunctionDeclaration,
+                Warning::Overflow,
+                Warning::ReturnType,
+            ],
+            "extra" => vec![Warning::UnusedParameter],
+            "unused" => vec![Warning::UnusedVariable, Warning::UnusedParameter],
+            _ => Warning::ALL
+                .into_iter()
+                .filter(|warning| warning.name() == name)
+                .collect(),
+        }
+    }
+
+    // オプションを指定しなくても有効なもの
+    pub fn defaults() -> HashSet<Warning> {
+        HashSet::from([
+            Warning::ImplicitFunctionDeclaration,
+            Warning::Overflow,
+            Warning::ReturnType,
+        ])
+    }
+}
+
+impl Ctx<'_> {
+    // 有効な警告なら報告する。-Werrorの対象ならエラーとして報告する
+    pub fn warn(&self, warning: Warning, mut diag: Diagnostic) {
+        if !self.opts.warnings.contains(&warning) {
+            return;
+        }
+        diag.warning = Some(warning);
+        if self.opts.werror || self.opts.werror_for.contains(&warning) {
+            diag.severity = Severity::Error;
+        }
+        self.report(diag);
+    }
+
+    // 関数の定義を読み終わったところで、一度も参照されなかった変数と引数を報告する
+    pub fn check_unused(&self, func: &Function) {
+        for scope in &func.exited_scope {
+            for var in &scope.variables {
+                let var = var.borrow();
+                // extern宣言で入れたグローバル変数は、ほかで使われているかもしれない
+                if var.is_used || (!var.is_local && var.asm_name == var.name) {
+                    continue;
+                }
+                if var.is_param {
+                    let msg = format!("unused parameter '{}'", var.name);
+                    self.warn(
+                        Warning::UnusedParameter,
+                        Diagnostic::warning(var.span, &msg),
+                    );
+                } else {
+                    let msg = format!("unused variable '{}'", var.name);
+                    self.warn(Warning::UnusedVariable, Diagnostic::warning(var.span, &msg));
+                }
+            }
+        }
+    }
+
+    // 関数の最後までreturnせずに到達しうるなら報告する。mainは0を返すことになっているので除く
+    pub fn check_return(&self, name: &str, body: &Node) {
+        if name == "main" || !may_fall_through(body) {
+            return;
+        }
+        // 関数の最後の}を指す
+        let span = Span {
+            start: body.span.start + body.span.len - 1,
+            len: 1,
+        };
+        self.warn(
+            Warning::ReturnType,
+            Diagnostic::warning(span, "control reaches end of non-void function"),
+        );
+    }
+
+    // 外側のスコープの変数や、グローバル変数と同じ名前の変数を宣言したら報告する
+    // 同じスコープの中で同じ名前なら再定義なので、ここでは扱わない
+    pub fn check_shadow(&self, name: &str, span: Span) {
+        let mut scopes = self.visible_scopes().into_iter();
+        // 先頭は今のスコープ、最後はファイルスコープ
+        let cur_scope = scopes.next();
+        if cur_scope.is_some_and(|scope| scope.variables.iter().any(|v| v.borrow().name == name)) {
+            return;
+        }
+        let outer = scopes
+            .flat_map(|scope| scope.variables.iter())
+            .find(|var| var.borrow().name == name);
+        let (prev, msg) = match outer {
+            Some(var) => (var.clone(), "a previous local"),
+            None => match self.gvars.iter().find(|var| {
+                let var = var.borrow();
+                var.name == name && var.asm_name == name
+            }) {
+                Some(var) => (var.clone(), "a global declaration"),
+                None => return,
+            },
+        };
+        let msg = format!("declaration of '{}' shadows {}", name, msg);
+        self.warn(
+            Warning::Shadow,
+            Diagnostic::warning(span, &msg)
+                .with_note(prev.borrow().span, "shadowed declaration is here"),
+        );
+    }
+
+    // 整数の代入や初期化で、値が変わってしまう変換を報告する。tyは代入先の型
+    pub fn check_conversion(&self, ty: &Type, rhs: &Node) {
+        let Some(from) = &rhs.ty else {
+            return;
+        };
+        if !is_integer(ty) || !is_integer(from) {
+            return;
+        }
+        let (to_name, from_name) = (type_name(ty), type_name(from));
+        if let Some(val) = const_value(rhs) {
+            let converted = truncate(val, ty);
+            if converted == val {
+                return;
+            }
+            // 符号の有無だけが変わる場合。unsignedに負の数を入れるのと、
+            // 同じ幅のunsignedをsignedとして読み直すのはよくあるので何も言わない
+            let as_other = truncate(
+                val,
+                &Type {
+                    is_unsigned: !ty.is_unsigned,
+                    ..ty.clone()
+                },
+            );
+            if as_other == val && (ty.is_unsigned || (from.is_unsigned && from.size == ty.size)) {
+                return;
+            }
+            let msg = format!(
+                "overflow in conversion from '{}' to '{}' changes value from '{}' to '{}'",
+                from_name, to_name, val, converted
+            );
+            self.warn(Warning::Overflow, Diagnostic::warning(rhs.span, &msg));
+            return;
+        }
+        if unpromoted_size(rhs) > ty.size {
+            let msg = format!(
+                "conversion from '{}' to '{}' may change value",
+                from_name, to_name
+            );
+            self.warn(Warning::Conversion, Diagnostic::warning(rhs.span, &msg));
+        }
+    }
+}
+
+// 文の最後まで実行が進みうるか。break, gotoはないので、returnと無限ループだけを見る
+fn may_fall_through(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::Return { .. } => false,
+        NodeKind::Block { body } => body.iter().all(may_fall_through),
+        NodeKind::If {
+            then,
+            els: Some(els),
+            ..
+        } => may_fall_through(then) || may_fall_through(els),
+        NodeKind::For { cond: None, .. } => false,
+        NodeKind::For {
+            cond: Some(cond), ..
+        }
+        | NodeKind::While { cond, .. } => const_value(cond).is_none_or(|val| val == 0),
+        _ => true,
+    }
+}
+
+// 整数の定数式なら、その値
+fn const_value(node: &Node) -> Option<isize> {
+    let ty = node.ty.as_ref()?;
+    let val = match &node.kind {
+        NodeKind::Num { val } => *val,
+        NodeKind::Cast { lhs } => const_value(lhs)?,
+        NodeKind::Neg { lhs } => const_value(lhs)?.wrapping_neg(),
+        NodeKind::BitNot { lhs } => !const_value(lhs)?,
+        _ => return None,
+    };
+    Some(truncate(val, ty))
+}
+
+// 整数拡張される前の大きさ。char同士の足し算の結果をcharに入れても警告しないように、
+// 演算の結果は元の被演算子の大きい方とみなす。定数は値が収まる大きさ
+fn unpromoted_size(node: &Node) -> usize {
+    let size = node.ty.as_ref().map_or(8, |ty| ty.size);
+    if let Some(val) = const_value(node) {
+        return [1, 2, 4]
+            .into_iter()
+            .find(|size| {
+                let bits = size * 8;
+                val >= -(1 << (bits - 1)) && val < (1 << bits)
+            })
+            .unwrap_or(8);
+    }
+    match &node.kind {
+        NodeKind::Cast { lhs } if lhs.ty.as_ref().is_some_and(is_integer) => {
+            unpromoted_size(lhs).min(size)
+        }
+        NodeKind::Add { lhs, rhs }
+        | NodeKind::Sub { lhs, rhs }
+        | NodeKind::Mul { lhs, rhs }
+        | NodeKind::Div { lhs, rhs }
+        | NodeKind::Mod { lhs, rhs }
+        | NodeKind::BitAnd { lhs, rhs }
+        | NodeKind::BitOr { lhs, rhs }
+        | NodeKind::BitXor { lhs, rhs } => unpromoted_size(lhs).max(unpromoted_size(rhs)),
+        NodeKind::Neg { lhs } | NodeKind::BitNot { lhs } => unpromoted_size(lhs),
+        NodeKind::Cond { then, els, .. } => unpromoted_size(then).max(unpromoted_size(els)),
+        _ => size,
+    }
+}
@@ -2,7 +2,12 @@
 assert() {
 	expected="$1"
 	input="$2"
-	./target/debug/jff "$input" > tmp.s || exit
+	# ファイルがなければ、引数の文字列をソースとしてコンパイルする
+	if [ -f "$input" ]; then
+		./target/debug/jff -S -o tmp.s "$input" || exit
+	else
+		./target/debug/jff -S -o tmp.s -e "$input" || exit
+	fi
 	gcc-14 -o tmp tmp.s
 	./tmp
 	actual="$?"
@@ -0,0 +1,107 @@
+#include "test.h"
+
+int _Alignas(512) g1;
+int _Alignas(512) g2;
+char g3;
+int g4;
+long g5;
+char g6;
+_Alignas(16) char g7;
+char g8 __attribute__((aligned(32)));
+
+struct packed_s {
+  char a;
+  int b;
+  short c;
+} __attribute__((packed));
+
+struct __attribute__((packed)) packed_t {
+  char a;
+  long b;
+};
+
+struct aligned_s {
+  char a;
+} __attribute__((aligned(16)));
+
+struct wire {
+  char tag;
+  int len __attribute__((aligned(2)));
+  char body[3];
+} __attribute__((packed));
+
+int main() {
+  ASSERT(1, _Alignof(char));
+  ASSERT(2, _Alignof(short));
+  ASSERT(4, _Alignof(int));
+  ASSERT(8, _Alignof(long));
+  ASSERT(8, _Alignof(long *));
+  ASSERT(1, _Alignof(char[3]));
+  ASSERT(4, _Alignof(int[3]));
+  ASSERT(1, _Alignof(struct { char a; char b; }[2]));
+  ASSERT(8, _Alignof(struct { char a; long b; }[2]));
+  ASSERT(4, alignof(int));
+
+  ASSERT(1, ({
+           _Alignas(char) char x, y;
+           &y - &x;
+         }));
+  ASSERT(8, ({
+           _Alignas(long) char x, y;
+           &y - &x;
+         }));
+  ASSERT(16, ({
+           _Alignas(16) char x, y;
+           &y - &x;
+         }));
+  ASSERT(8, ({
+           _Alignas(8) int x, y;
+           &y - &x;
+         }) * 4);
+  ASSERT(0, (&g1 - &g2) % 128);
+  ASSERT(16, ({
+           struct {
+             _Alignas(16) char x, y;
+           } a;
+           &a.y - &a.x;
+         }));
+  ASSERT(8, ({
+           struct T {
+             _Alignas(8) char a;
+           };
+           _Alignof(struct T);
+         }));
+  ASSERT(32, ({
+           static _Alignas(32) char s;
+           _Alignof(s);
+         }));
+  ASSERT(16, _Alignof(g7));
+  ASSERT(32, _Alignof(g8));
+
+  ASSERT(7, sizeof(struct packed_s));
+  ASSERT(1, _Alignof(struct packed_s));
+  ASSERT(9, sizeof(struct packed_t));
+  ASSERT(16, sizeof(struct aligned_s));
+  ASSERT(16, _Alignof(struct aligned_s));
+  ASSERT(32, ({
+           struct aligned_s x[2];
+           sizeof(x);
+         }));
+  ASSERT(2, _Alignof(struct wire));
+  ASSERT(10, sizeof(struct wire));
+  ASSERT(5, ({
+           struct packed_s x;
+           x.b = 5;
+           x.b;
+         }));
+  ASSERT(3, ({
+           struct wire w;
+           w.body[2] = 3;
+           w.body[2];
+         }));
+  ASSERT(8, sizeof(union { char a; long b; } __attribute__((packed))));
+  ASSERT(1, _Alignof(union { char a; long b; } __attribute__((packed))));
+
+  printf("OK\n");
+  return 0;
+}
@@ -101,6 +101,57 @@ int main() {
          }));
 
   // ASSERT(0, 1073741824 * 100 / 100);
+
+  ASSERT(1, 1 << 0);
+  ASSERT(8, 1 << 3);
+  ASSERT(10, 5 << 1);
+  ASSERT(2, 5 >> 1);
+  ASSERT(-1, -1 >> 1);
+  ASSERT(-4, -8 >> 1);
+  ASSERT(1, (-8 >> 1) == -4);
+  ASSERT(1, ({
+           int i = 1;
+           i <<= 0;
+           i;
+         }));
+  ASSERT(8, ({
+           int i = 1;
+           i <<= 3;
+           i;
+         }));
+  ASSERT(2, ({
+           int i = 5;
+           i >>= 1;
+           i;
+         }));
+  ASSERT(-1, ({
+           int i = -1;
+           i >>= 1;
+           i;
+         }));
+
+  ASSERT(0, !1);
+  ASSERT(0, !2);
+  ASSERT(1, !0);
+  ASSERT(-1, ~0);
+  ASSERT(0, ~-1);
+  ASSERT(-6, ~5);
+
+  ASSERT(2, 0 ? 1 : 2);
+  ASSERT(1, 1 ? 1 : 2);
+  ASSERT(-1, 0 ? -2 : -1);
+  ASSERT(-2, 1 ? -2 : -1);
+  ASSERT(4, sizeof(0 ? 1 : 2));
+  ASSERT(8, sizeof(0 ? (long)1 : (long)2));
+  ASSERT(3, ({
+           int x = 2;
+           x > 1 ? x + 1 : x - 1;
+         }));
+
+  ASSERT(2, 12 / 3 / 2);
+  ASSERT(1, 7 % 4 % 2);
+  ASSERT(6, 12 / 4 * 2);
+
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
@@ -0,0 +1,145 @@
+#include "test.h"
+
+struct {
+  char a;
+  int b : 5;
+  int c : 10;
+} g45 = {1, 2, 3}, g46 = {};
+
+struct {
+  int a : 3;
+  int : 2;
+  int b : 4;
+} g47 = {1, 5};
+
+// {}を省いた初期化子でも、名前のないビットフィールドは飛ばす
+struct T48 {
+  int a : 3;
+  int : 5;
+  int b : 4;
+} g48[1] = {1, 2};
+struct {
+  struct T48 t;
+  int c;
+} g49 = {.t.a = 1, 2, 3};
+
+int main() {
+  ASSERT(4, sizeof(struct { int x : 1; }));
+  ASSERT(8, sizeof(long));
+  ASSERT(8, sizeof(struct { long x : 1; }));
+  ASSERT(1, sizeof(struct { char x : 3; char y : 5; }));
+  ASSERT(2, sizeof(struct { char x : 3; char y : 6; }));
+  ASSERT(4, sizeof(struct { char a; int b : 5; int c : 10; }));
+  ASSERT(8, sizeof(struct { int a : 20; int b : 20; }));
+  ASSERT(8, sizeof(struct { int a : 3; int : 0; int b : 3; }));
+  ASSERT(1, sizeof(struct { char a : 3; int : 2; }));
+
+  ASSERT(1, g45.a);
+  ASSERT(2, g45.b);
+  ASSERT(3, g45.c);
+  ASSERT(0, g46.a);
+  ASSERT(0, g46.b);
+  ASSERT(0, g46.c);
+  ASSERT(1, g47.a);
+  ASSERT(5, g47.b);
+  ASSERT(1, g48[0].a);
+  ASSERT(2, g48[0].b);
+  ASSERT(1, g49.t.a);
+  ASSERT(2, g49.t.b);
+  ASSERT(3, g49.c);
+  ASSERT(2, ({
+           struct T48 x[1] = {1, 2};
+           x[0].b;
+         }));
+
+  typedef struct {
+    int a : 10;
+    int b : 10;
+    int c : 10;
+  } T3;
+
+  ASSERT(1, ({
+           T3 x = {1, 2, 3};
+           x.a;
+         }));
+  ASSERT(2, ({
+           T3 x = {1, 2, 3};
+           x.b;
+         }));
+  ASSERT(3, ({
+           T3 x = {1, 2, 3};
+           x.c;
+         }));
+  ASSERT(5, ({
+           T3 x = {1, 2, 3};
+           x.b = 5;
+           x.b;
+         }));
+  ASSERT(3, ({
+           T3 x = {1, 2, 3};
+           x.b = 5;
+           x.c;
+         }));
+  ASSERT(-1, ({
+           T3 x;
+           x.a = 1023;
+           x.a;
+         }));
+  ASSERT(-2, ({
+           T3 x;
+           x.b = 1022;
+         }));
+  ASSERT(-3, ({
+           struct {
+             int a : 3;
+           } x;
+           x.a = 5;
+           x.a;
+         }));
+  ASSERT(7, ({
+           struct {
+             char a;
+             int b : 5;
+             int c : 10;
+           } x = {.c = 7};
+           x.c;
+         }));
+  ASSERT(0, ({
+           union {
+             int a : 4;
+             int b;
+           } x;
+           x.b = 0;
+           x.a = 8;
+           x.b - 8;
+         }));
+
+  // unsignedのビットフィールドはゼロ拡張される
+  ASSERT(7, ({
+    struct {
+      unsigned a : 3;
+      int b : 3;
+    } x;
+    x.a = 7;
+    x.b = 7;
+    x.a;
+  }));
+  ASSERT(-1, ({
+    struct {
+      unsigned a : 3;
+      int b : 3;
+    } x;
+    x.a = 7;
+    x.b = 7;
+    x.b;
+  }));
+  ASSERT(3, ({
+    struct {
+      unsigned char a : 2;
+    } x;
+    x.a = 7;
+  }));
+
+  printf("OK\n");
+  return 0;
+}
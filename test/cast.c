@@ -75,6 +75,46 @@ int main() {
     (int)x;
   }));
 
+  // unsignedへのキャストはゼロ拡張になる
+  ASSERT(255, ({
+    char x = -1;
+    (unsigned char)x;
+  }));
+  ASSERT(65535, ({
+    int x = -1;
+    (unsigned short)x;
+  }));
+  ASSERT(1, ({
+    int x = -1;
+    (unsigned int)x == 0xffffffff;
+  }));
+  ASSERT(1, ({
+    unsigned int x = -1;
+    (long)x == 0xffffffff;
+  }));
+  ASSERT(-1, ({
+    unsigned char x = 255;
+    (signed char)x;
+  }));
+  ASSERT(44, (char)300);
+  ASSERT(44, (unsigned char)300);
+  ASSERT(-1, (short)65535);
+
+  // 代入でも左辺の型に変換される
+  ASSERT(44, ({
+    char x;
+    x = 300;
+  }));
+  ASSERT(1, ({
+    int x = -1;
+    long y = x;
+    y < 0;
+  }));
+  ASSERT(1, ({
+    int x = 0 - 1;
+    x < 0;
+  }));
+
   printf("OK\n");
   return 0;
 }
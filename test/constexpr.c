@@ -0,0 +1,132 @@
+#include "test.h"
+
+int g1[2 + 3];
+int g2[sizeof(int) * 2] = {[1 + 1] = 5};
+long g3 = 3 * 4 - 2;
+int g4 = (1 << 4) | 1;
+char g5 = (char)257;
+int g6 = 1 > 2 ? 10 : 20;
+
+int main() {
+  ASSERT(10, ({
+           enum { ten = 1 + 2 + 3 + 4 };
+           ten;
+         }));
+  ASSERT(8, ({
+           enum { a = 1 << 3, b = a + 1, c = b * 2 };
+           a;
+         }));
+  ASSERT(18, ({
+           enum { a = 1 << 3, b = a + 1, c = b * 2 };
+           c;
+         }));
+  ASSERT(3, ({
+           enum { a = 2 };
+           enum { b = a > 1 ? 3 : 4 };
+           b;
+         }));
+  ASSERT(24, ({
+           enum { n = 3 };
+           int x[n * 2];
+           sizeof(x);
+         }));
+  ASSERT(8, ({
+           int x[1 + 1];
+           sizeof(x);
+         }));
+  ASSERT(6, ({
+           char x[8 - 2];
+           sizeof(x);
+         }));
+  ASSERT(6, ({
+           char x[2 * 3];
+           sizeof(x);
+         }));
+  ASSERT(3, ({
+           char x[12 / 4];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[12 % 10];
+           sizeof(x);
+         }));
+  ASSERT(4, ({
+           char x[1 << 2];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[4 >> 1];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[(1 == 1) + 1];
+           sizeof(x);
+         }));
+  ASSERT(1, ({
+           char x[(1 != 1) + 1];
+           sizeof(x);
+         }));
+  ASSERT(1, ({
+           char x[(1 < 1) + 1];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[(1 <= 1) + 1];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[1 ? 2 : 3];
+           sizeof(x);
+         }));
+  ASSERT(3, ({
+           char x[0 ? 2 : 3];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[!0 + 1];
+           sizeof(x);
+         }));
+  ASSERT(1, ({
+           char x[!1 + 1];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[~-3];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[(5 || 6) + 1];
+           sizeof(x);
+         }));
+  ASSERT(1, ({
+           char x[(0 || 0) + 1];
+           sizeof(x);
+         }));
+  ASSERT(2, ({
+           char x[(1 && 1) + 1];
+           sizeof(x);
+         }));
+  ASSERT(1, ({
+           char x[(1 && 0) + 1];
+           sizeof(x);
+         }));
+  ASSERT(3, ({
+           char x[(int)3];
+           sizeof(x);
+         }));
+  ASSERT(16, ({
+           char x[sizeof(int) * 4];
+           sizeof(x);
+         }));
+
+  ASSERT(20, sizeof(g1));
+  ASSERT(5, g2[2]);
+  ASSERT(32, sizeof(g2));
+  ASSERT(10, g3);
+  ASSERT(17, g4);
+  ASSERT(1, g5);
+  ASSERT(20, g6);
+
+  printf("OK\n");
+  return 0;
+}
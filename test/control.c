@@ -35,6 +35,23 @@ int main() {
              x = 3;
            x;
          }));
+  ASSERT(2, ({
+           int x;
+           if (5)
+             x = 2;
+           else
+             x = 3;
+           x;
+         }));
+  ASSERT(3, ({
+           int i = 3;
+           int j = 0;
+           while (i) {
+             i = i - 1;
+             j = j + 1;
+           }
+           j;
+         }));
   ASSERT(55, ({
            int i = 0;
            int j = 0;
@@ -88,6 +105,12 @@ int main() {
   ASSERT(0, 0 && 1);
   ASSERT(0, (2 - 2) && 5);
   ASSERT(1, 1 && 5);
+  // 左辺で決まれば右辺は評価しない
+  ASSERT(0, ({ int *p = 0; p && *p; }));
+  ASSERT(1, ({ int *p = 0; !p || *p; }));
+  ASSERT(3, ({ int x = 3; 0 && (x = 5); x; }));
+  ASSERT(3, ({ int x = 3; 1 || (x = 5); x; }));
+  ASSERT(5, ({ int x = 3; 1 && (x = 5); x; }));
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
@@ -1,4 +1,9 @@
 #include "test.h"
+enum color { red, green = 5, blue };
+enum { ga = 10, gb };
+
+enum color next_color(enum color c) { return c + 1; }
+
 int main() {
   ASSERT(0, ({
            enum { zero, one, two };
@@ -45,6 +50,18 @@ int main() {
            enum t y;
            sizeof(y);
          }));
+  ASSERT(0, red);
+  ASSERT(6, blue);
+  ASSERT(11, gb);
+  ASSERT(6, next_color(green));
+  ASSERT(4, ({
+           enum color c = blue;
+           sizeof(c);
+         }));
+  ASSERT(1, ({
+           enum { red = 1 };
+           red;
+         }));
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
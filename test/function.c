@@ -17,6 +17,8 @@ int fib(int x) {
     return 1;
   return fib(x - 1) + fib(x - 2);
 }
+// 定義の後の宣言で、定義が消えないこと
+int ret3();
 int main() {
   ASSERT(3, ret3());
   ASSERT(8, add2(3, 5));
@@ -0,0 +1,274 @@
+#include "test.h"
+
+int g3[3] = {1, 2, 3};
+int g4[] = {4, 5, 6, 7};
+char g5[] = "foo";
+char g6[10] = "bar";
+struct {
+  char a;
+  int b;
+  short c;
+} g7 = {1, 2, 3};
+int g8[2][3] = {{1, 2, 3}, {4, 5}};
+int g9[2][2] = {1, 2, 3};
+struct {
+  int x;
+  int y;
+} g10 = {.y = 5};
+int g11[5] = {[3] = 7, 8};
+union {
+  int a;
+  char b[4];
+} g12 = {16909060};
+long g13 = -1;
+int g14 = 3;
+int *g15 = &g14;
+int *g16 = g3 + 1;
+char *g17 = "abc";
+char *g18[] = {"foo", "bar", "baz"};
+struct {
+  int a;
+  char *b;
+  short c;
+  int *d;
+} g19 = {1, "xyz", 2, &g3[2]};
+int *g20 = &g3[1] - 1;
+long g21[2] = {100000000000, -2};
+short g22[] = {-1, 2};
+
+int main() {
+  ASSERT(1, ({
+           int x[3] = {1, 2, 3};
+           x[0];
+         }));
+  ASSERT(2, ({
+           int x[3] = {1, 2, 3};
+           x[1];
+         }));
+  ASSERT(3, ({
+           int x[3] = {1, 2, 3};
+           x[2];
+         }));
+  ASSERT(0, ({
+           int x[3] = {1};
+           x[2];
+         }));
+  ASSERT(2, ({
+           int x[2][3] = {{1, 2, 3}, {4, 5, 6}};
+           x[0][1];
+         }));
+  ASSERT(4, ({
+           int x[2][3] = {{1, 2, 3}, {4, 5, 6}};
+           x[1][0];
+         }));
+  ASSERT(6, ({
+           int x[2][3] = {{1, 2, 3}, {4, 5, 6}};
+           x[1][2];
+         }));
+  ASSERT(0, ({
+           int x[2][3] = {{1, 2}};
+           x[1][2];
+         }));
+  ASSERT(4, ({
+           int x[2][3] = {1, 2, 3, 4, 5, 6};
+           x[1][0];
+         }));
+
+  ASSERT(3, ({
+           int x[] = {1, 2, 3};
+           x[2];
+         }));
+  ASSERT(16, ({
+           int x[] = {1, 2, 3, 4};
+           sizeof(x);
+         }));
+  ASSERT(4, ({
+           char x[] = "foo";
+           sizeof(x);
+         }));
+  ASSERT(111, ({
+           char x[] = "foo";
+           x[2];
+         }));
+  ASSERT(0, ({
+           char x[] = "foo";
+           x[3];
+         }));
+  ASSERT(98, ({
+           char x[10] = "abc";
+           x[1];
+         }));
+  ASSERT(0, ({
+           char x[10] = "abc";
+           x[9];
+         }));
+  ASSERT(97, ({
+           char x[2][4] = {"abc", "def"};
+           x[0][0];
+         }));
+  ASSERT(102, ({
+           char x[2][4] = {"abc", "def"};
+           x[1][2];
+         }));
+
+  ASSERT(1, ({
+           struct {
+             int a;
+             int b;
+             int c;
+           } x = {1, 2, 3};
+           x.a;
+         }));
+  ASSERT(3, ({
+           struct {
+             int a;
+             int b;
+             int c;
+           } x = {1, 2, 3};
+           x.c;
+         }));
+  ASSERT(0, ({
+           struct {
+             int a;
+             int b;
+             int c;
+           } x = {1};
+           x.c;
+         }));
+  ASSERT(2, ({
+           struct {
+             int a;
+             int b;
+           } x[2] = {{1, 2}, {3, 4}};
+           x[0].b;
+         }));
+  ASSERT(3, ({
+           struct {
+             int a;
+             int b;
+           } x[2] = {1, 2, 3, 4};
+           x[1].a;
+         }));
+  ASSERT(2, ({
+           struct {
+             int a;
+             int b;
+           } x = {1, 2};
+           struct {
+             int a;
+             int b;
+           } y = x;
+           y.b;
+         }));
+  ASSERT(4, ({
+           union {
+             int a;
+             char b;
+           } x = {4};
+           x.b;
+         }));
+
+  ASSERT(7, ({
+           struct {
+             int a;
+             int b;
+           } x = {.b = 7};
+           x.b;
+         }));
+  ASSERT(0, ({
+           struct {
+             int a;
+             int b;
+           } x = {.b = 7};
+           x.a;
+         }));
+  ASSERT(3, ({
+           struct {
+             int a;
+             int b;
+             int c;
+           } x = {.b = 2, 3};
+           x.c;
+         }));
+  ASSERT(7, ({
+           int x[5] = {[3] = 7};
+           x[3];
+         }));
+  ASSERT(8, ({
+           int x[5] = {[3] = 7, 8};
+           x[4];
+         }));
+  ASSERT(0, ({
+           int x[5] = {[3] = 7, 8};
+           x[2];
+         }));
+  ASSERT(24, ({
+           int x[] = {[5] = 1};
+           sizeof(x);
+         }));
+  ASSERT(5, ({
+           struct {
+             int a[3];
+             int b;
+           } x = {.a[1] = 5};
+           x.a[1];
+         }));
+  ASSERT(6, ({
+           struct {
+             int a;
+             int b;
+           } x[2] = {[1].b = 6};
+           x[1].b;
+         }));
+  ASSERT(9, ({
+           union {
+             int a;
+             char b;
+           } x = {.b = 9};
+           x.b;
+         }));
+
+  ASSERT(1, g3[0]);
+  ASSERT(3, g3[2]);
+  ASSERT(7, g4[3]);
+  ASSERT(16, sizeof(g4));
+  ASSERT(4, sizeof(g5));
+  ASSERT(111, g5[1]);
+  ASSERT(114, g6[2]);
+  ASSERT(0, g6[9]);
+  ASSERT(1, g7.a);
+  ASSERT(2, g7.b);
+  ASSERT(3, g7.c);
+  ASSERT(3, g8[0][2]);
+  ASSERT(5, g8[1][1]);
+  ASSERT(0, g8[1][2]);
+  ASSERT(3, g9[1][0]);
+  ASSERT(0, g9[1][1]);
+  ASSERT(0, g10.x);
+  ASSERT(5, g10.y);
+  ASSERT(7, g11[3]);
+  ASSERT(8, g11[4]);
+  ASSERT(4, g12.b[0]);
+  ASSERT(1, g12.b[3]);
+  ASSERT(-1, g13);
+  ASSERT(3, g14);
+  ASSERT(3, *g15);
+  ASSERT(2, *g16);
+  ASSERT(98, g17[1]);
+  ASSERT(102, g18[0][0]);
+  ASSERT(97, g18[1][1]);
+  ASSERT(122, g18[2][2]);
+  ASSERT(24, sizeof(g18));
+  ASSERT(1, g19.a);
+  ASSERT(121, g19.b[1]);
+  ASSERT(2, g19.c);
+  ASSERT(3, *g19.d);
+  ASSERT(1, *g20);
+  ASSERT(23, (g21[0] / 65536) / 65536);
+  ASSERT(-2, g21[1]);
+  ASSERT(-1, g22[0]);
+  ASSERT(2, g22[1]);
+
+  printf("OK\n");
+  return 0;
+}
@@ -0,0 +1,95 @@
+#include "test.h"
+
+int main() {
+  // 10進数以外の整数リテラル
+  ASSERT(511, 0777);
+  ASSERT(0, 0);
+  ASSERT(8, 010);
+  ASSERT(255, 0xff);
+  ASSERT(255, 0XFF);
+  ASSERT(3054, 0xbEe);
+  ASSERT(5, 0b101);
+  ASSERT(5, 0B101);
+  ASSERT(1, 0x10000 == 65536);
+
+  // 接尾辞と値の大きさで型が決まる
+  ASSERT(4, sizeof(0));
+  ASSERT(4, sizeof(2147483647));
+  ASSERT(8, sizeof(2147483648));
+  ASSERT(4, sizeof(0x80000000));
+  ASSERT(4, sizeof(0xffffffff));
+  ASSERT(8, sizeof(0x100000000));
+  ASSERT(8, sizeof(1L));
+  ASSERT(8, sizeof(1l));
+  ASSERT(8, sizeof(1LL));
+  ASSERT(8, sizeof(1ll));
+  ASSERT(4, sizeof(1U));
+  ASSERT(4, sizeof(1u));
+  ASSERT(8, sizeof(1UL));
+  ASSERT(8, sizeof(1lu));
+  ASSERT(8, sizeof(1ULL));
+  ASSERT(8, sizeof(1llu));
+  ASSERT(4, sizeof('a'));
+
+  // 0x80000000はunsigned intなので、比較も符号なしになる
+  ASSERT(1, 0x80000000 > 0);
+  ASSERT(0, 2147483648 < 0);
+  ASSERT(1, -1 < 0);
+  ASSERT(0, -1 < 0U);
+  ASSERT(1, -1 > 1U);
+  ASSERT(0, -1L < 0UL);
+  ASSERT(1, 0xffffffff == -1U);
+  ASSERT(-1, 0xffffffffffffffff);
+
+  ASSERT(1, ({
+           unsigned long x = 0xffffffffffffffff;
+           x == -1;
+         }));
+
+  // movに収まらない大きな値
+  ASSERT(6382179, 'abc');
+  ASSERT(1, 0x12345678 == 305419896);
+  ASSERT(1, ({
+           long x = 0x123456789abcdef0;
+           (x >> 32) == 0x12345678;
+         }));
+  ASSERT(1, ({
+           long x = 0x123456789abcdef0;
+           (x & 0xffffffff) == 0x9abcdef0;
+         }));
+  ASSERT(1, ({
+           long x = -0x12345678;
+           x + 0x12345678 == 0;
+         }));
+  ASSERT(1, ({
+           unsigned long x = 0xffff0000ffff1234;
+           x >> 48 == 0xffff;
+         }));
+  ASSERT(1, ({
+           unsigned long x = 0xffff0000ffff1234;
+           (x & 0xffffffff) == 0xffff1234;
+         }));
+  ASSERT(-65536, -65536);
+  ASSERT(-305419896, -305419896);
+  ASSERT(65537, 0x10001);
+
+  // スタックやメンバのオフセットも即値に収まらない
+  ASSERT(3, ({
+           char buf[5000];
+           buf[4999] = 3;
+           buf[0] = 1;
+           buf[4999];
+         }));
+  ASSERT(7, ({
+           struct {
+             char a[10000];
+             int b;
+           } x, y;
+           x.b = 7;
+           y = x;
+           y.b;
+         }));
+
+  printf("OK\n");
+  return 0;
+}
@@ -25,6 +25,20 @@ int main() {
   ASSERT(8, sizeof((long)-10 - 5));
   ASSERT(8, sizeof((long)-10 * 5));
   ASSERT(8, sizeof((long)-10 / 5));
+  ASSERT(1, sizeof(signed char));
+  ASSERT(1, sizeof(unsigned char));
+  ASSERT(2, sizeof(unsigned short));
+  ASSERT(2, sizeof(short int));
+  ASSERT(2, sizeof(signed short int));
+  ASSERT(4, sizeof(unsigned));
+  ASSERT(4, sizeof(signed));
+  ASSERT(4, sizeof(unsigned int));
+  ASSERT(8, sizeof(unsigned long));
+  ASSERT(8, sizeof(long int));
+  ASSERT(8, sizeof(long long));
+  ASSERT(8, sizeof(unsigned long long int));
+  ASSERT(8, sizeof(long unsigned int long));
+
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+#include "test.h"
+
+static int s1 = 3;
+static int s2;
+extern int e1;
+int e1 = 5;
+extern int e2[];
+int e2[3] = {1, 2, 3};
+int g1;
+extern int g1;
+
+static int static_fn() { return 4; }
+static int counter() {
+  static int i;
+  static int j = 10;
+  i = i + 1;
+  j = j + 1;
+  return i * 100 + j;
+}
+int counter2() {
+  static int i = 5;
+  i = i + 1;
+  return i;
+}
+
+int main() {
+  ASSERT(3, s1);
+  ASSERT(0, s2);
+  ASSERT(5, e1);
+  ASSERT(3, e2[2]);
+  ASSERT(12, sizeof(e2));
+  ASSERT(4, static_fn());
+  ASSERT(111, counter());
+  ASSERT(212, counter());
+  ASSERT(313, counter());
+  ASSERT(6, counter2());
+  ASSERT(7, counter2());
+  ASSERT(2, ({
+           static int x = 2;
+           x;
+         }));
+  ASSERT(3, ({
+           static int i = 3;
+           int *p = &i;
+           *p;
+         }));
+  ASSERT(5, ({
+           extern int e1;
+           e1;
+         }));
+  ASSERT(7, ({
+           static int a[3] = {5, 6, 7};
+           a[2];
+         }));
+  ASSERT(8, ({
+           g1 = 8;
+           extern int g1;
+           g1;
+         }));
+  ASSERT(5, ({
+           int e1 = 1;
+           int r;
+           {
+             extern int e1;
+             r = e1;
+           }
+           r;
+         }));
+  ASSERT(1, ({
+           int e1 = 1;
+           {
+             extern int e1;
+           }
+           e1;
+         }));
+
+  printf("OK\n");
+  return 0;
+}
@@ -7,9 +7,132 @@ int main() {
   ASSERT(99, "abc"[2]);
   ASSERT(0, "abc"[3]);
   ASSERT(4, sizeof("abc"));
+
+  ASSERT(7, "\a"[0]);
+  ASSERT(8, "\b"[0]);
+  ASSERT(9, "\t"[0]);
+  ASSERT(10, "\n"[0]);
+  ASSERT(11, "\v"[0]);
+  ASSERT(12, "\f"[0]);
+  ASSERT(13, "\r"[0]);
+  ASSERT(27, "\e"[0]);
+  ASSERT(34, "\""[0]);
+  ASSERT(39, "\'"[0]);
+  ASSERT(92, "\\"[0]);
+  ASSERT(63, "\?"[0]);
+  ASSERT(2, sizeof("\\"));
+
+  ASSERT(106, "\j"[0]);
+  ASSERT(107, "\k"[0]);
+
+  ASSERT(0, "\0"[0]);
+  ASSERT(16, "\20"[0]);
+  ASSERT(65, "\101"[0]);
+  ASSERT(104, "\1500"[0]);
+  ASSERT(48, "\1500"[1]);
+  ASSERT(0, "\x00"[0]);
+  ASSERT(119, "\x77"[0]);
+  ASSERT(-91, "\xA5"[0]);
+  ASSERT(-1, "\x00ff"[0]);
+  ASSERT(4, sizeof("a\0b"));
+  ASSERT(98, "a\0b"[2]);
+  ASSERT(0, "a\0b"[3]);
+
+  ASSERT(-61, "é"[0]);
+  ASSERT(-87, "é"[1]);
+  ASSERT(3, sizeof("é"));
+  ASSERT(5, sizeof("\U0001F600"));
+
+  ASSERT(97, 'a');
+  ASSERT(10, '\n');
+  ASSERT(39, '\'');
+  ASSERT(34, '"');
+  ASSERT(0, '\0');
+  ASSERT(127, '\x7f');
+  ASSERT(-128, '\x80');
+  ASSERT(-1, '\377');
+  ASSERT(4, sizeof('a'));
+  ASSERT(24930, 'ab');
+  ASSERT(25185, 'ba');
+
+  ASSERT(7, ({
+           char x[] = "a\tb\n";
+           sizeof(x) + x[1] - 9 + 2;
+         }));
+  ASSERT(-2, ({
+           char x[] = "\xfe";
+           x[0];
+         }));
+
+  // 隣り合う文字列リテラルはつながる
+  ASSERT(7, sizeof("abc" "def"));
+  ASSERT(100, ("abc" "def")[3]);
+  ASSERT(0, ("abc" "def")[6]);
+  ASSERT(3, sizeof("\x4" "1"));
+  ASSERT(49, ("\x4" "1")[1]);
+  ASSERT(5, ({
+           char x[] = "ab"
+                      "cd";
+           sizeof(x);
+         }));
+  // 同じ文字列リテラルは同じシンボルになる
+  ASSERT(1, ({
+           char *p = "xyz";
+           char *q = "xyz";
+           p == q;
+         }));
+
+  // ワイド文字列、UTF文字列
+  ASSERT(16, sizeof(L"abc"));
+  ASSERT(98, L"abc"[1]);
+  ASSERT(0, L"abc"[3]);
+  ASSERT(8, sizeof(u"abc"));
+  ASSERT(99, u"abc"[2]);
+  ASSERT(16, sizeof(U"abc"));
+  ASSERT(97, U"abc"[0]);
+  ASSERT(4, sizeof(u8"abc"));
+  ASSERT(98, u8"abc"[1]);
+  ASSERT(233, L"é"[0]);
+  ASSERT(8, sizeof(L"é"));
+  ASSERT(233, u"é"[0]);
+  ASSERT(127843, L"🍣"[0]);
+  ASSERT(127843, U"🍣"[0]);
+  ASSERT(6, sizeof(u"🍣"));
+  ASSERT(55356, u"🍣"[0]);
+  ASSERT(57187, u"🍣"[1]);
+  ASSERT(-1, L"\xffffffff"[0]);
+  ASSERT(65535, u"\xffff"[0]);
+  ASSERT(511, U"\777"[0]);
+  ASSERT(3, sizeof(u8"é"));
+
+  // 片方がワイド文字列なら、全体がワイド文字列になる
+  ASSERT(12, sizeof("a" L"b"));
+  ASSERT(97, ("a" L"b")[0]);
+  ASSERT(6, sizeof(u"a" "\x62"));
+  ASSERT(98, (u"a" "\x62")[1]);
+  ASSERT(12, sizeof("é" U"x"));
+  ASSERT(233, ("é" U"x")[0]);
+
+  ASSERT(12, ({
+           int x[] = L"ab";
+           sizeof(x);
+         }));
+  ASSERT(98, ({
+           unsigned short x[] = u"ab";
+           x[1];
+         }));
+  ASSERT(-1, ({
+           int x[] = L"\xffffffff";
+           x[0];
+         }));
+  ASSERT(3, ({
+           struct {
+             int n;
+             unsigned s[3];
+           } x = {1, U"abc"};
+           sizeof(x.s) / sizeof(x.s[0]) - x.s[3 - 3] + 'a' - x.n + 1;
+         }));
+
   printf("OK\n");
   return 0;
 }
-
-// // エスケープシーケンスは未サポート
-// \" をうまく扱え流ようにならないと実行できない
\ No newline at end of file
@@ -1,5 +1,47 @@
 #include "test.h"
 
+struct point {
+  int x;
+  int y;
+};
+union word {
+  int i;
+  char c[4];
+};
+struct point g_origin;
+
+int point_sum(struct point *p) { return p->x + p->y; }
+
+struct list;
+struct list *g_head;
+struct list {
+  int val;
+  struct list *next;
+};
+struct list g_nodes[3] = {{1, &g_nodes[1]}, {2, &g_nodes[2]}, {3, 0}};
+
+int list_sum(struct list *p) {
+  int sum = 0;
+  while (p) {
+    sum = sum + p->val;
+    p = p->next;
+  }
+  return sum;
+}
+
+struct buf {
+  int len;
+  char data[];
+};
+struct buf g_buf = {3, "abc"};
+
+// 後から型が完成するグローバル変数
+struct pair g_pair;
+struct pair {
+  int a;
+  long b;
+};
+
 int main() {
   ASSERT(1, ({
            struct {
@@ -350,6 +392,79 @@ int main() {
   //          } x;
   //          sizeof(x);
   //        }));
+  ASSERT(8, sizeof(struct point));
+  ASSERT(4, sizeof(union word));
+  ASSERT(5, ({
+           struct point p;
+           p.x = 2;
+           p.y = 3;
+           point_sum(&p);
+         }));
+  ASSERT(0, point_sum(&g_origin));
+  ASSERT(12, ({
+           struct point {
+             int x;
+             int y;
+             int z;
+           };
+           sizeof(struct point);
+         }));
+  ASSERT(1, ({
+           union word w;
+           w.i = 257;
+           w.c[1];
+         }));
+  ASSERT(6, list_sum(g_nodes));
+  ASSERT(3, ({
+           g_head = g_nodes;
+           g_head->next->next->val;
+         }));
+  ASSERT(2, ({
+           g_head = g_nodes;
+           (g_head + 1)->val;
+         }));
+  ASSERT(1, ({
+           g_head = g_nodes;
+           (g_head + 2) - (g_head + 1);
+         }));
+  ASSERT(1, ({
+           g_head = g_nodes;
+           struct list l = *g_head;
+           l.val;
+         }));
+  ASSERT(3, ({
+           struct T;
+           struct T *p;
+           struct T {
+             int x;
+           } t;
+           t.x = 3;
+           p = &t;
+           p->x;
+         }));
+  ASSERT(8, ({
+           struct T *p;
+           struct T {
+             int a;
+             int b;
+           };
+           sizeof(*p);
+         }));
+  ASSERT(4, sizeof(struct buf));
+  ASSERT(8, sizeof(struct { int a; long b[]; }));
+  ASSERT(3, g_buf.len);
+  ASSERT(99, g_buf.data[2]);
+  ASSERT(0, g_buf.data[3]);
+  ASSERT(98, ({
+           struct buf *b = &g_buf;
+           b->data[1];
+         }));
+  ASSERT(16, sizeof(g_pair));
+  ASSERT(8, _Alignof(g_pair));
+  ASSERT(7, ({
+           g_pair.b = 7;
+           g_pair.b;
+         }));
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
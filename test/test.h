@@ -1,2 +1,3 @@
 #define ASSERT(x, y) assert(x, y, #y)
-int printf();
\ No newline at end of file
+int printf();
+int assert();
\ No newline at end of file
@@ -1,4 +1,15 @@
 #include "test.h"
+typedef int MyInt, MyInt2[4];
+typedef struct {
+  int a;
+  long b;
+} Pair;
+
+MyInt g1 = 3;
+Pair g2;
+
+MyInt add_pair(Pair *p) { return p->a + p->b; }
+
 int main() {
   ASSERT(1, ({
            typedef int t;
@@ -32,6 +43,20 @@ int main() {
            t x;
            sizeof(x);
          }));
+  ASSERT(3, g1);
+  ASSERT(4, sizeof(MyInt));
+  ASSERT(16, sizeof(MyInt2));
+  ASSERT(16, sizeof(Pair));
+  ASSERT(7, ({
+           g2.a = 3;
+           g2.b = 4;
+           add_pair(&g2);
+         }));
+  ASSERT(1, ({
+           typedef long MyInt;
+           sizeof(MyInt) == 8;
+         }));
+  ASSERT(4, sizeof(MyInt));
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
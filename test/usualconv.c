@@ -42,6 +42,86 @@ int main() {
            y = x;
            y.a;
          }));
+
+  // 同じ大きさならunsignedに揃える
+  ASSERT(0, -1 < (unsigned)1);
+  ASSERT(1, (unsigned)-1 > 1);
+  ASSERT(1, ({
+           unsigned x = 1;
+           int y = -1;
+           x < y;
+         }));
+  // longはunsigned intの値をすべて表せるのでlongになる
+  ASSERT(1, ({
+           unsigned x = 1;
+           long y = -1;
+           y < x;
+         }));
+  ASSERT(1, ({
+           unsigned char x = 255;
+           x + 1 == 256;
+         }));
+  ASSERT(1, ({
+           unsigned short x = 65535;
+           x * 2 == 131070;
+         }));
+  ASSERT(1, ({
+           unsigned x = 0xffffffff;
+           x + 1 == 0;
+         }));
+
+  // 割り算、剰余、右シフトは符号なしで行う
+  ASSERT(1, ({
+           unsigned x = -2;
+           x / 2 == 0x7fffffff;
+         }));
+  ASSERT(1, ({
+           unsigned x = -1;
+           x % 10 == 5;
+         }));
+  ASSERT(1, ({
+           unsigned x = -1;
+           x >> 31;
+         }));
+  ASSERT(-1, ({
+           int x = -1;
+           x >> 31;
+         }));
+  ASSERT(1, ({
+           unsigned long x = -1;
+           x >> 63;
+         }));
+  ASSERT(1, ({
+           unsigned long x = -1;
+           x / 2 == 0x7fffffffffffffff;
+         }));
+  ASSERT(1, ({
+           unsigned char x = 0x80;
+           (x << 1) == 256;
+         }));
+  ASSERT(1, ({
+           char x = -128;
+           -x == 128;
+         }));
+  ASSERT(1, ({
+           unsigned char x = 0;
+           ~x == -1;
+         }));
+
+  // 定数式でも同じ規則で計算する
+  ASSERT(1, ({
+           char a[(unsigned)-1 / 2 == 0x7fffffff];
+           sizeof(a);
+         }));
+  ASSERT(1, ({
+           char a[(unsigned char)511 == 255];
+           sizeof(a);
+         }));
+  ASSERT(1, ({
+           char a[-1 > 1U];
+           sizeof(a);
+         }));
+
   printf("OK\n");
   return 0;
 }
\ No newline at end of file
@@ -1,6 +1,17 @@
 #include "test.h"
 
 int g1, g2[4];
+int g3;
+int g3;
+int g4;
+int g4 = 4;
+int g4;
+extern int g5;
+int g5;
+int g6[];
+int g6[3];
+static int g7;
+static int g7;
 
 int main() {
   ASSERT(3, ({
@@ -128,6 +139,14 @@ int main() {
          }));
 
   ASSERT(4, sizeof(g1));
+  ASSERT(0, g3);
+  ASSERT(4, g4);
+  ASSERT(0, g5);
+  ASSERT(12, sizeof(g6));
+  ASSERT(5, ({
+           g7 = 5;
+           g7;
+         }));
   ASSERT(16, sizeof(g2));
 
   ASSERT(1, ({